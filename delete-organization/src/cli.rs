@@ -0,0 +1,676 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::client::{Engine, TlsBackend, DEFAULT_DEADLOCK_PATTERN, DEFAULT_USER_AGENT};
+use crate::output::{Compression, DeleteStyle, LineEnding, OutputFormat};
+use crate::{SPARQL_ENDPOINT, URI};
+
+// Without a subcommand the arguments of `generate` are accepted, so existing invocations
+// keep working.
+#[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub generate: GenerateArgs,
+
+    /// Use the values of the [profiles.<PROFILE>] table of the profile file on top of its
+    /// top-level values.
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+
+    /// Print the effective value of every option and where it comes from, then exit.
+    #[arg(long, global = true)]
+    pub show_config: bool,
+
+    /// Write the counters of the run (requests, retries, errors, bytes received, statements
+    /// and phase timings) to this file in the Prometheus text format, also when it fails or
+    /// stops early.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub metrics_out: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Write the DELETE statements of a deletion to a file (the default).
+    Generate(GenerateArgs),
+    /// Write the DELETE statements and run them against the endpoint.
+    Execute(ExecuteArgs),
+    /// Count the subjects and triples a deletion would remove, without writing statements.
+    Count(CountArgs),
+    /// Check which subjects of a previous run's report still have triples.
+    Verify(VerifyArgs),
+    /// Estimate how many requests a deletion needs, using COUNT queries.
+    Estimate(EstimateArgs),
+    /// List the types of the resources linked to the seeds, to help writing the config.
+    ListTypes(EstimateArgs),
+    /// Write a starter config with the types linked to a sample of instances of a type.
+    GenerateConfig(GenerateConfigArgs),
+}
+
+#[derive(Args)]
+pub struct GenerateArgs {
+    #[command(flatten)]
+    pub connection: ConnectionArgs,
+
+    #[command(flatten)]
+    pub traversal: TraversalArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Args)]
+pub struct ExecuteArgs {
+    #[command(flatten)]
+    pub generate: GenerateArgs,
+
+    #[command(flatten)]
+    pub execution: ExecutionArgs,
+}
+
+#[derive(Args)]
+pub struct CountArgs {
+    #[command(flatten)]
+    pub connection: ConnectionArgs,
+
+    #[command(flatten)]
+    pub traversal: TraversalArgs,
+
+    /// Also write the counts, with every subject that would be deleted, as JSON report to
+    /// this file.
+    #[arg(long, value_name = "FILE")]
+    pub report: Option<PathBuf>,
+
+    /// Print the subjects that are deleted now but not by the run of this earlier report,
+    /// and the other way around.
+    #[arg(long, value_name = "REPORT")]
+    pub diff: Option<PathBuf>,
+
+    /// Append every subject that would be deleted to this file as soon as it is discovered,
+    /// one JSON object with its uri, type and depth per line. `-` writes to stdout.
+    #[arg(long, value_name = "PATH")]
+    pub subjects_out: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    #[command(flatten)]
+    pub connection: ConnectionArgs,
+
+    #[command(flatten)]
+    pub statement: StatementArgs,
+
+    /// Report of the run to verify, as written by --report.
+    #[arg(long, value_name = "FILE")]
+    pub report: PathBuf,
+}
+
+#[derive(Args)]
+pub struct EstimateArgs {
+    #[command(flatten)]
+    pub connection: ConnectionArgs,
+
+    #[command(flatten)]
+    pub traversal: TraversalArgs,
+}
+
+#[derive(Args)]
+pub struct GenerateConfigArgs {
+    #[command(flatten)]
+    pub connection: ConnectionArgs,
+
+    /// IRI of the type to start from, e.g. the type of the resources to delete.
+    #[arg(long = "type", value_name = "IRI")]
+    pub uri_type: String,
+
+    /// Number of instances per type whose linked types are looked up.
+    #[arg(long, default_value_t = 100)]
+    pub sample_size: usize,
+
+    /// Number of levels of linked types to look up the links of in turn.
+    #[arg(long, default_value_t = 1)]
+    pub depth: usize,
+
+    /// Write the config to this file instead of stdout.
+    #[arg(long, value_name = "PATH")]
+    pub out: Option<PathBuf>,
+}
+
+/// Where the endpoints are and how to talk to them, shared by every subcommand.
+#[derive(Args)]
+pub struct ConnectionArgs {
+    /// SPARQL endpoint used for the config-driven deletion.
+    #[arg(long, default_value = SPARQL_ENDPOINT)]
+    pub endpoint: String,
+
+    /// SPARQL endpoint the lookups following reverse edges are sent to, defaults to
+    /// --endpoint. Needs --allow-split-endpoints if it differs.
+    #[arg(long)]
+    pub reverse_endpoint: Option<String>,
+
+    /// SPARQL endpoint the lookups following forward edges are sent to, defaults to
+    /// --endpoint. Needs --allow-split-endpoints if it differs.
+    #[arg(long)]
+    pub forward_endpoint: Option<String>,
+
+    /// Proceed even though the phases are configured to read from different endpoints.
+    #[arg(long)]
+    pub allow_split_endpoints: bool,
+
+    /// User-Agent sent with every request, so endpoint operators can attribute the load.
+    #[arg(long, default_value = DEFAULT_USER_AGENT)]
+    pub user_agent: String,
+
+    /// Abort instead of buffering a SPARQL response larger than this many bytes.
+    #[arg(long, default_value_t = 1024 * 1024 * 1024)]
+    pub max_response_bytes: usize,
+
+    /// How often a request the endpoint answers with 429 or 503 and a Retry-After header is
+    /// sent again, after waiting as long as the header asks. 0 fails right away.
+    #[arg(long, default_value_t = 3)]
+    pub retry_after_retries: u32,
+
+    /// Issue at most this many SPARQL requests per second, e.g. to stay under the limit of
+    /// a reverse proxy in front of the endpoint. Unlimited by default.
+    #[arg(long, value_name = "REQ_PER_SEC")]
+    pub rate_limit: Option<f64>,
+
+    /// Maximum number of idle connections kept open to the endpoint, unlimited by default.
+    #[arg(long)]
+    pub pool_max_idle_per_host: Option<usize>,
+
+    /// Use HTTP/2 without upgrading from HTTP/1.1 first. Virtuoso only speaks HTTP/1.1,
+    /// so this needs an HTTP/2 capable proxy in front of it.
+    #[arg(long)]
+    pub http2_prior_knowledge: bool,
+
+    /// Interval in seconds of TCP keepalive probes on idle connections, 0 disables them.
+    #[arg(long, default_value_t = 60)]
+    pub tcp_keepalive: u64,
+
+    /// TLS implementation used for HTTPS endpoints.
+    #[arg(long, value_enum, default_value_t = TlsBackend::Native)]
+    pub tls_backend: TlsBackend,
+
+    /// Also trust this root certificate (PEM or DER), e.g. the CA of an internal endpoint.
+    #[arg(long, value_name = "PATH")]
+    pub ca_cert: Option<PathBuf>,
+
+    /// DANGEROUS: accept any TLS certificate, including expired, self-signed and ones for
+    /// another host. Anyone on the network path can then read and alter the traffic, so
+    /// only use this against development endpoints.
+    #[arg(long)]
+    pub insecure: bool,
+
+    /// Send all requests through this proxy, e.g. `http://proxy.example.org:3128`. Defaults
+    /// to the proxies in HTTP_PROXY and HTTPS_PROXY.
+    #[arg(long, value_name = "URL")]
+    pub proxy: Option<String>,
+
+    /// Comma-separated hosts, domains and IP ranges that are reached without the proxy,
+    /// e.g. `localhost,.internal,10.0.0.0/8`. Defaults to NO_PROXY.
+    #[arg(long, value_name = "HOSTS")]
+    pub no_proxy: Option<String>,
+
+    /// SPARQL store behind the endpoint, which decides how --query-timeout and --max-rows
+    /// are passed along. The generic engine passes nothing. Virtuoso also gets a `format`
+    /// field asking for JSON results, since it may answer with HTML otherwise.
+    #[arg(long, value_enum, default_value_t = Engine::Generic)]
+    pub engine: Engine,
+
+    /// Have the store abort every query that runs longer than this many seconds.
+    #[arg(long, value_name = "SECS")]
+    pub query_timeout: Option<u64>,
+
+    /// Have the store return at most this many rows per query. A lookup reaching the limit
+    /// fails the run, since the rest of its results would be missing from the deletion.
+    #[arg(long, value_name = "ROWS")]
+    pub max_rows: Option<usize>,
+
+    /// Form field the query text is sent in, for gateways that do not use the standard one.
+    #[arg(long, value_name = "NAME", default_value = "query")]
+    pub query_field: String,
+
+    /// Form field the text of a SPARQL update is sent in.
+    #[arg(long, value_name = "NAME", default_value = "update")]
+    pub update_field: String,
+
+    /// Do not check that the endpoints answer a trivial query, and the update endpoint a
+    /// no-op update, before starting.
+    #[arg(long)]
+    pub skip_health_check: bool,
+
+    /// Log every SPARQL query and update, with the endpoint it is sent to, right before
+    /// sending it. The same as running with RUST_LOG=sparql=debug.
+    #[arg(long)]
+    pub print_queries: bool,
+}
+
+/// Which triples of a subject a statement deletes, shared by the traversal and `verify`.
+#[derive(Args)]
+pub struct StatementArgs {
+    /// Config describing which forward/reverse types to follow per type.
+    #[arg(long, default_value = "config/config-op.json")]
+    pub config: PathBuf,
+
+    /// Only delete resources created after this ISO 8601 date or date-time, according
+    /// to --provenance-predicate.
+    #[arg(long, requires = "provenance_predicate")]
+    pub since: Option<String>,
+
+    /// Predicate linking a resource to its creation timestamp, used by --since.
+    #[arg(long, requires = "since")]
+    pub provenance_predicate: Option<String>,
+
+    /// Only delete triples with this predicate (repeatable), as <...> IRI or a prefixed name
+    /// from the config. All predicates are deleted by default.
+    #[arg(long = "predicate", value_name = "IRI")]
+    pub predicates: Vec<String>,
+
+    /// Prefix for the names of the ?g, ?s, ?p and ?o variables in generated statements,
+    /// e.g. `__` for ?__g, so they do not collide with a query they are embedded in.
+    #[arg(long, value_name = "PREFIX", default_value = "")]
+    pub var_prefix: String,
+
+    /// Delete from the default graph instead of from whichever named graphs contain the
+    /// triples, for stores that do not use named graphs.
+    #[arg(long)]
+    pub no_named_graphs: bool,
+
+    /// Only delete from this named graph, given as <...> IRI or prefixed name from the
+    /// config. Statements name it once with WITH instead of matching every graph.
+    #[arg(long, conflicts_with = "no_named_graphs")]
+    pub graph: Option<String>,
+
+    /// Instead of deleting the triples of every subject, move them to this IRI, given as
+    /// <...> or prefixed name from the config, e.g. a tombstone. Triples pointing to the
+    /// subjects are left alone.
+    #[arg(long, value_name = "IRI")]
+    pub rename_to: Option<String>,
+
+    /// Parse every generated query and statement before using it.
+    #[arg(long)]
+    pub validate: bool,
+
+    /// Only follow and check asserted triples on endpoints that reason, by putting
+    /// --inference-pragma before every query.
+    #[arg(long)]
+    pub no_inference: bool,
+
+    /// Store-specific pragma that disables reasoning for a query. The default is Virtuoso's.
+    #[arg(
+        long,
+        value_name = "PRAGMA",
+        default_value = r#"DEFINE input:inference "none""#
+    )]
+    pub inference_pragma: String,
+}
+
+/// What to delete and how the config is followed to find everything linked to it.
+#[derive(Args)]
+pub struct TraversalArgs {
+    /// URI of the resource to delete, including the angle brackets.
+    #[arg(long, default_value = URI)]
+    pub uri: String,
+
+    /// rdf:type of the resource to delete, either as <...> IRI or a prefixed name from the
+    /// config. Looked up in the store when omitted.
+    #[arg(long = "type")]
+    pub uri_type: Option<String>,
+
+    /// Only follow resources whose types all appear in the config, as key or edge, so a
+    /// resource that also has a type the config does not know is left alone.
+    #[arg(long)]
+    pub strict_types: bool,
+
+    /// Follow the edges of every type in an order shuffled with this seed instead of the
+    /// config's order. The deleted subjects must not change, only the order of the output.
+    #[arg(long, value_name = "N")]
+    pub traversal_seed: Option<u64>,
+
+    /// Only keep N randomly chosen URIs of every lookup, to try a run end-to-end on a small
+    /// part of the data. The URIs left out are neither deleted nor followed, and the output
+    /// is marked as a sample.
+    #[arg(long, value_name = "N")]
+    pub sample: Option<usize>,
+
+    /// Seed for --sample, the same seed picks the same URIs from the same data.
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "sample")]
+    pub sample_seed: u64,
+
+    /// Print the order in which the config's types are processed and the edges each one
+    /// follows, then exit without sending any queries.
+    #[arg(long)]
+    pub explain: bool,
+
+    /// Before the traversal, ask the endpoint for an instance of every type of the config
+    /// and warn about the types without any, which usually point to a typo.
+    #[arg(long)]
+    pub validate_config_against_endpoint: bool,
+
+    /// Fail instead of warning when the config lists an edge twice, or when
+    /// --validate-config-against-endpoint finds types without instances.
+    #[arg(long)]
+    pub strict_config: bool,
+
+    /// File with a SELECT query whose ?s bindings are deleted instead of --uri. All of them
+    /// are treated as being of --type.
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "uri_type",
+        conflicts_with = "uri"
+    )]
+    pub seed_query: Option<PathBuf>,
+
+    /// Read the URIs to delete from stdin, one per line, instead of --uri. Blank lines and
+    /// lines starting with # are skipped. All of them are treated as being of --type.
+    #[arg(long, requires = "uri_type", conflicts_with_all = ["uri", "seed_query"])]
+    pub seed_from_stdin: bool,
+
+    #[command(flatten)]
+    pub statement: StatementArgs,
+
+    /// Periodically save the traversal state to this JSON file and resume from it
+    /// if it already exists.
+    #[arg(long)]
+    pub state_file: Option<PathBuf>,
+
+    /// Abort once more than this many DELETE statements are generated.
+    #[arg(long, default_value_t = 100_000)]
+    pub max_statements: usize,
+
+    /// Only follow discovered URIs starting with this namespace (repeatable).
+    #[arg(long = "follow-namespace", value_name = "NAMESPACE")]
+    pub follow_namespaces: Vec<String>,
+
+    /// Never follow discovered URIs starting with this namespace (repeatable), takes
+    /// precedence over --follow-namespace.
+    #[arg(long = "skip-namespace", value_name = "NAMESPACE")]
+    pub skip_namespaces: Vec<String>,
+
+    /// Only expand discovered URIs starting with this namespace (repeatable). URIs outside
+    /// of it are still deleted when they are linked directly, but their edges are not
+    /// followed.
+    #[arg(long = "in-namespace", value_name = "NAMESPACE")]
+    pub in_namespaces: Vec<String>,
+
+    /// Once the traversal holds more than this many URIs in its per-type lists, drop the
+    /// lists of the types that are already processed. Cannot be combined with --state-file,
+    /// which needs them to resume.
+    #[arg(long, value_name = "URIS", conflicts_with = "state_file")]
+    pub max_stored_uris: Option<usize>,
+
+    /// Stop the traversal once it has taken this long, e.g. `30m` or `2h 30m`, keeping the
+    /// statements written so far, and exit with status 124.
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub max_runtime: Option<Duration>,
+
+    /// Check every statement with an ASK query first and leave out the ones that would not
+    /// delete anything. Costs one extra request per statement.
+    #[arg(long)]
+    pub prune_empty_blocks: bool,
+
+    /// Only follow the `reverse` edges of the config.
+    #[arg(long, conflicts_with = "only_forward")]
+    pub only_reverse: bool,
+
+    /// Only follow the `forward` edges of the config.
+    #[arg(long)]
+    pub only_forward: bool,
+
+    /// Maximum number of URIs in the VALUES block of a lookup query. Halved automatically
+    /// whenever the endpoint rejects a query as too large. Unlimited by default.
+    #[arg(long)]
+    pub chunk_size: Option<usize>,
+
+    /// Fetch the results of every lookup in pages ordered by IRI, each page starting after
+    /// the last IRI of the previous one, instead of in a single response.
+    #[arg(long)]
+    pub keyset_pagination: bool,
+
+    /// Number of rows per page of --keyset-pagination. Keep it below --max-rows.
+    #[arg(
+        long,
+        value_name = "ROWS",
+        default_value_t = 10_000,
+        requires = "keyset_pagination"
+    )]
+    pub page_size: usize,
+
+    /// When the lookup of an edge fails, report it at the end and carry on with the other
+    /// edges instead of aborting. The run still fails if any edge did.
+    #[arg(long)]
+    pub continue_on_error: bool,
+
+    /// Skip checking that the seed URI has any triples before starting the traversal.
+    #[arg(long)]
+    pub no_precheck: bool,
+
+    /// Resolve relative IRIs in query results against this base IRI. Without it, a relative
+    /// IRI in a result is an error.
+    #[arg(long, value_name = "IRI")]
+    pub base: Option<String>,
+}
+
+/// How the generated statements are written.
+#[derive(Args)]
+pub struct OutputArgs {
+    /// Layout of the generated SPARQL.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
+    pub format: OutputFormat,
+
+    /// Form of the generated DELETE statements.
+    #[arg(long, value_enum, default_value_t = DeleteStyle::Full)]
+    pub delete_style: DeleteStyle,
+
+    /// Write every DELETE statement to its own numbered file (0001.rq, 0002.rq, ...) in
+    /// this directory instead of writing them all to generated_sparql_queries/output.txt.
+    #[arg(long, value_name = "DIR")]
+    pub split_output: Option<PathBuf>,
+
+    /// Write the statements into one file per config type in this directory, named after
+    /// the type (e.g. Bestuurseenheid.rq), instead of writing them all to one file.
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["split_output", "flatten_output"])]
+    pub per_type_output: Option<PathBuf>,
+
+    /// Line ending of the written statements, separators included.
+    #[arg(long, value_enum, default_value_t = LineEnding::Lf)]
+    pub line_ending: LineEnding,
+
+    /// Append to the output file instead of replacing it.
+    #[arg(long, conflicts_with_all = ["split_output", "per_type_output"])]
+    pub append_output: bool,
+
+    /// Write the statements to stdout instead of a file, without a manifest. Progress and
+    /// summary messages go to stderr then.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "split_output",
+            "per_type_output",
+            "append_output",
+            "compress",
+            "subjects_out",
+        ]
+    )]
+    pub stdout: bool,
+
+    /// Compress the output file while writing it, to `output.txt.gz` for gzip.
+    #[arg(long, value_enum, conflicts_with_all = ["split_output", "per_type_output"])]
+    pub compress: Option<Compression>,
+
+    /// Text written after every statement instead of the format's default `;` line.
+    /// Understands \n and \t, e.g. --separator '\n\n' for blank lines only. Statements
+    /// are only valid SPARQL together if the separator contains a `;`.
+    #[arg(long)]
+    pub separator: Option<String>,
+
+    /// Wrap every statement in this text, with the statement in place of its
+    /// `{{statement}}` placeholder, e.g. 'DEFINE sql:log-enable 3\n{{statement}}'.
+    /// Understands the same escapes as --separator.
+    #[arg(long, value_name = "TEMPLATE")]
+    pub output_template: Option<String>,
+
+    /// Precede every statement with a comment saying which edge of the config led to it.
+    #[arg(long)]
+    pub statement_headers: bool,
+
+    /// Delete all discovered subjects together, in one statement per --chunk-size subjects,
+    /// instead of one statement per lookup. Nothing is written until the traversal is done.
+    #[arg(long, conflicts_with = "subjects_out")]
+    pub flatten_output: bool,
+
+    /// Only write the first N statements, as a preview of the output, and stop the traversal
+    /// there. Unlike --max-statements this is not an error, but the output is incomplete and
+    /// cannot be executed.
+    #[arg(long, value_name = "N")]
+    pub head: Option<usize>,
+
+    /// After the statements, also delete the reification nodes (rdf:Statement) of
+    /// statements about any deleted subject, found through rdf:subject, rdf:predicate and
+    /// rdf:object. Costs one extra query per chunk of subjects.
+    #[arg(long, conflicts_with = "rename_to")]
+    pub delete_reifications: bool,
+
+    /// After the statements, drop the named graphs they leave without any triples. Costs
+    /// one extra query per statement.
+    #[arg(long, conflicts_with = "no_named_graphs")]
+    pub drop_empty_graphs: bool,
+
+    /// Write every triple the statements delete to this file as N-Quads, for review. Costs
+    /// one extra query per statement.
+    #[arg(long, value_name = "PATH")]
+    pub triples_out: Option<PathBuf>,
+
+    /// Write a JSON report of the deleted subjects and how often each predicate occurs
+    /// among the deleted triples to this file. Costs one extra query per statement.
+    #[arg(long, value_name = "FILE")]
+    pub report: Option<PathBuf>,
+
+    /// Print the subjects that are deleted now but not by the run of this earlier report,
+    /// and the other way around, e.g. to review the effect of a config change.
+    #[arg(long, value_name = "REPORT")]
+    pub diff: Option<PathBuf>,
+
+    /// Append every deleted subject to this file as soon as it is discovered, one JSON
+    /// object with its uri, type and depth per line. `-` writes to stdout.
+    #[arg(long, value_name = "PATH")]
+    pub subjects_out: Option<PathBuf>,
+}
+
+/// How `execute` runs the statements.
+#[derive(Args)]
+pub struct ExecutionArgs {
+    /// SPARQL endpoint the statements are executed against, defaults to --endpoint.
+    #[arg(long)]
+    pub update_endpoint: Option<String>,
+
+    /// After executing, check every deleted subject with an ASK query and report the ones
+    /// that still have triples.
+    #[arg(long)]
+    pub verify_after: bool,
+
+    /// Execute without asking for confirmation, needed when stdin is not a terminal.
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Number of statements sent per update request.
+    #[arg(long, default_value_t = 1)]
+    pub exec_batch_size: usize,
+
+    /// Regex matched against the response to an update to recognize a transaction
+    /// deadlock, which is retried instead of failing the run.
+    #[arg(long, value_name = "REGEX", default_value = DEFAULT_DEADLOCK_PATTERN)]
+    pub deadlock_pattern: String,
+
+    /// How often an update aborted by a deadlock is retried before giving up.
+    #[arg(long, default_value_t = 3)]
+    pub deadlock_retries: u32,
+
+    /// Wait before the first deadlock retry, doubled for every further one.
+    #[arg(long, value_name = "MILLIS", default_value_t = 500)]
+    pub deadlock_backoff_ms: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Cli {
+        Cli::try_parse_from([&["delete-organization"], args].concat()).unwrap()
+    }
+
+    #[test]
+    fn arguments_are_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn without_a_subcommand_the_generate_arguments_apply() {
+        let cli = parse(&["--uri", "<http://x/org>", "--type", "<http://t/Org>"]);
+
+        assert!(cli.command.is_none());
+        assert_eq!(cli.generate.traversal.uri, "<http://x/org>");
+        assert_eq!(cli.generate.connection.endpoint, SPARQL_ENDPOINT);
+    }
+
+    #[test]
+    fn each_subcommand_takes_its_own_arguments() {
+        let Some(Command::Generate(generate)) =
+            parse(&["generate", "--config", "ops.yaml", "--chunk-size", "50"]).command
+        else {
+            panic!("expected generate");
+        };
+        assert_eq!(
+            generate.traversal.statement.config,
+            PathBuf::from("ops.yaml")
+        );
+        assert_eq!(generate.traversal.chunk_size, Some(50));
+
+        let Some(Command::Execute(execute)) =
+            parse(&["execute", "-y", "--exec-batch-size", "20", "--verify-after"]).command
+        else {
+            panic!("expected execute");
+        };
+        assert!(execute.execution.yes && execute.execution.verify_after);
+        assert_eq!(execute.execution.exec_batch_size, 20);
+
+        let Some(Command::Count(count)) = parse(&["count", "--report", "r.json"]).command else {
+            panic!("expected count");
+        };
+        assert_eq!(count.report, Some(PathBuf::from("r.json")));
+
+        let Some(Command::Verify(verify)) = parse(&["verify", "--report", "r.json"]).command else {
+            panic!("expected verify");
+        };
+        assert_eq!(verify.report, PathBuf::from("r.json"));
+
+        let Some(Command::Estimate(estimate)) =
+            parse(&["estimate", "--endpoint", "http://db/sparql"]).command
+        else {
+            panic!("expected estimate");
+        };
+        assert_eq!(estimate.connection.endpoint, "http://db/sparql");
+    }
+
+    #[test]
+    fn options_of_other_subcommands_are_rejected() {
+        for args in [
+            &["generate", "--yes"][..],
+            &["count", "--exec-batch-size", "2"],
+            &["verify"],
+            &["estimate", "--report", "r.json"],
+            &["--only-reverse", "--only-forward"],
+        ] {
+            let parsed = Cli::try_parse_from([&["delete-organization"], args].concat());
+            assert!(parsed.is_err(), "{:?} must not parse", args);
+        }
+    }
+}
@@ -0,0 +1,938 @@
+use std::collections::HashMap;
+use std::env;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use clap::ValueEnum;
+use log::warn;
+use regex::Regex;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, RETRY_AFTER},
+    redirect::Policy,
+    Certificate, Client, NoProxy, Proxy, RequestBuilder, Response, StatusCode, Url,
+};
+use serde_json::Value;
+
+use crate::error::ScratchpadError;
+use crate::fetch_sparql_results;
+use crate::metrics::RequestStats;
+use crate::planner::Direction;
+
+pub const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Header carrying the per-run ID so endpoint operators can correlate all queries of
+/// a single invocation.
+pub const RUN_ID_HEADER: &str = "x-request-id";
+
+/// Virtuoso's errors for a deadlocked (SR172) or timed out (SR171) transaction, and the
+/// SQLSTATE of a serialization failure.
+pub const DEFAULT_DEADLOCK_PATTERN: &str = r"SR17[12]|40001|[Dd]eadlock";
+
+/// Redirects followed per request before giving up.
+const MAX_REDIRECTS: usize = 5;
+/// Longest Retry-After that is waited for, a longer one fails the request instead.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// HTTP client together with the settings that apply to every SPARQL request of a run.
+pub struct SparqlClient {
+    pub http: Client,
+    /// Error out instead of buffering responses larger than this many bytes.
+    pub max_response_bytes: Option<usize>,
+    /// Spaces out requests when the endpoint only accepts a limited rate.
+    pub rate_limiter: Option<RateLimiter>,
+    /// How often a request answered with 429 or 503 and a Retry-After header is sent again
+    /// after waiting as long as asked.
+    pub retry_after_retries: u32,
+    /// Store-specific limits applied to every query.
+    pub limits: QueryLimits,
+    /// Form fields carrying the query and the update text, `query` and `update` per the
+    /// SPARQL protocol.
+    pub query_field: String,
+    pub update_field: String,
+    pub stats: Arc<RequestStats>,
+    redirect_reported: AtomicBool,
+    /// rdf:types of the URIs looked up so far in this run.
+    types: Mutex<HashMap<String, Vec<String>>>,
+}
+
+impl SparqlClient {
+    pub fn new(http: Client) -> Self {
+        SparqlClient {
+            http,
+            max_response_bytes: None,
+            rate_limiter: None,
+            retry_after_retries: 0,
+            limits: QueryLimits::default(),
+            query_field: "query".to_string(),
+            update_field: "update".to_string(),
+            stats: Arc::default(),
+            redirect_reported: AtomicBool::new(false),
+            types: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The types of `uri` if they were looked up before in this run, counting the cache hit
+    /// or miss in the stats.
+    pub fn cached_types(&self, uri: &str) -> Option<Vec<String>> {
+        let types = self.types.lock().unwrap().get(uri).cloned();
+        self.stats.count_type_lookup(types.is_some());
+
+        types
+    }
+
+    /// Remembers the types of `uri` for later lookups, see [`SparqlClient::cached_types`].
+    pub fn cache_types(&self, uri: &str, types: Vec<String>) {
+        self.types.lock().unwrap().insert(uri.to_string(), types);
+    }
+
+    /// Warns, once per run, when a request to `endpoint` was redirected and answered by
+    /// `final_url`, so the redirect can be avoided by using the final URL directly.
+    pub fn check_redirect(&self, endpoint: &str, final_url: &Url) {
+        if Url::parse(endpoint).is_ok_and(|url| url == *final_url) {
+            return;
+        }
+        if !self.redirect_reported.swap(true, Ordering::Relaxed) {
+            warn!(
+                "{} redirected to {}, pass the latter as endpoint to avoid the extra round trip",
+                endpoint, final_url
+            );
+        }
+    }
+
+    /// A POST of `params` as form to `endpoint`. The form is always encoded as UTF-8, which
+    /// the content type says explicitly, since some stores decode a form without a charset
+    /// as Latin-1 and then miss every IRI with a non-ASCII character.
+    pub fn post_form(&self, endpoint: &str, params: &HashMap<&str, &str>) -> RequestBuilder {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/x-www-form-urlencoded; charset=utf-8"),
+        );
+        // Set after the form, which would replace the content type with its own.
+        self.http.post(endpoint).form(params).headers(headers)
+    }
+
+    /// Sends the request `build` makes, counting it in the stats. While the endpoint answers
+    /// 429 Too Many Requests or 503 Service Unavailable with a Retry-After header, waits as
+    /// long as it asks and sends the request again, up to `retry_after_retries` times.
+    pub async fn send(
+        &self,
+        endpoint: &str,
+        build: impl Fn() -> RequestBuilder,
+    ) -> Result<Response, reqwest::Error> {
+        let mut attempt = 0;
+        loop {
+            self.throttle().await;
+            self.stats.count_request();
+            let response = build()
+                .send()
+                .await
+                .inspect_err(|_| self.stats.count_error())?;
+
+            let status = response.status();
+            let retry_after = match status {
+                StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+                    retry_after(response.headers())
+                }
+                _ => None,
+            };
+            match retry_after {
+                Some(delay) if attempt < self.retry_after_retries && delay <= MAX_RETRY_AFTER => {
+                    attempt += 1;
+                    self.stats.count_retry();
+                    warn!(
+                        "{} answered {}, retrying in {:.1?} as asked by Retry-After ({}/{})",
+                        endpoint, status, delay, attempt, self.retry_after_retries
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                _ => {
+                    if !status.is_success() {
+                        self.stats.count_error();
+                    }
+                    return Ok(response);
+                }
+            }
+        }
+    }
+
+    /// Waits until the rate limit, if any, allows the next request.
+    pub async fn throttle(&self) {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.wait().await;
+        }
+    }
+}
+
+/// Where the planner sends its queries. Anything that answers SPARQL queries with results in
+/// the SPARQL JSON format can stand in for the endpoint, e.g. canned responses.
+pub trait SparqlBackend {
+    fn query(&self, sparql: &str) -> impl Future<Output = Result<Value, ScratchpadError>> + Send;
+
+    /// Sends a lookup that follows edges in `direction`, to the same place as every other
+    /// query unless the backend reads the directions from stores of their own.
+    fn lookup(
+        &self,
+        _direction: Direction,
+        sparql: &str,
+    ) -> impl Future<Output = Result<Value, ScratchpadError>> + Send {
+        self.query(sparql)
+    }
+}
+
+/// A SPARQL endpoint reached over HTTP.
+pub struct HttpBackend {
+    pub client: SparqlClient,
+    pub endpoint: String,
+    /// Endpoint of the reverse lookups, `endpoint` when `None`.
+    pub reverse_endpoint: Option<String>,
+    /// Endpoint of the forward lookups, `endpoint` when `None`.
+    pub forward_endpoint: Option<String>,
+}
+
+impl HttpBackend {
+    pub fn new(client: SparqlClient, endpoint: &str) -> Self {
+        HttpBackend {
+            client,
+            endpoint: endpoint.to_string(),
+            reverse_endpoint: None,
+            forward_endpoint: None,
+        }
+    }
+}
+
+impl SparqlBackend for HttpBackend {
+    fn query(&self, sparql: &str) -> impl Future<Output = Result<Value, ScratchpadError>> + Send {
+        fetch_sparql_results(&self.client, &self.endpoint, sparql)
+    }
+
+    fn lookup(
+        &self,
+        direction: Direction,
+        sparql: &str,
+    ) -> impl Future<Output = Result<Value, ScratchpadError>> + Send {
+        let endpoint = match direction {
+            Direction::Reverse => self.reverse_endpoint.as_ref(),
+            Direction::Forward => self.forward_endpoint.as_ref(),
+        };
+        fetch_sparql_results(&self.client, endpoint.unwrap_or(&self.endpoint), sparql)
+    }
+}
+
+/// Issues requests at most at a fixed rate by handing out evenly spaced time slots.
+pub struct RateLimiter {
+    interval: Duration,
+    next_slot: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: f64) -> Result<Self, ScratchpadError> {
+        if !(requests_per_second.is_finite() && requests_per_second > 0.0) {
+            return Err(ScratchpadError::Config(format!(
+                "--rate-limit must be a positive number of requests per second, got {}",
+                requests_per_second
+            )));
+        }
+
+        Ok(RateLimiter {
+            interval: Duration::from_secs_f64(1.0 / requests_per_second),
+            next_slot: Mutex::new(None),
+        })
+    }
+
+    /// Claims the next free slot and sleeps until it starts.
+    pub async fn wait(&self) {
+        let delay = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let now = Instant::now();
+            let slot = next_slot.map_or(now, |next| next.max(now));
+            *next_slot = Some(slot + self.interval);
+            slot - now
+        };
+
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// The wait a Retry-After header asks for, given either in seconds or as an HTTP date. A
+/// date in the past means no wait at all.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// Retries of updates the store aborted because of a transaction deadlock. Such an update
+/// did not change anything, so it is safe to send again.
+pub struct DeadlockRetry {
+    pattern: Regex,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl DeadlockRetry {
+    pub fn new(pattern: &str, retries: u32, backoff: Duration) -> Result<Self, ScratchpadError> {
+        let pattern = Regex::new(pattern).map_err(|e| {
+            ScratchpadError::Config(format!("Invalid --deadlock-pattern {}: {}", pattern, e))
+        })?;
+
+        Ok(DeadlockRetry {
+            pattern,
+            retries,
+            backoff,
+        })
+    }
+
+    /// Whether a response body reports a deadlock. Some Virtuoso setups send it with a
+    /// success status, so this applies to every response.
+    pub fn is_deadlock(&self, body: &str) -> bool {
+        self.pattern.is_match(body)
+    }
+
+    /// How long to wait before retry `attempt` (starting at 1), or `None` once the retries
+    /// are used up. The wait doubles with every attempt.
+    pub fn delay(&self, attempt: u32) -> Option<Duration> {
+        (attempt <= self.retries).then(|| self.backoff * 2u32.saturating_pow(attempt - 1))
+    }
+}
+
+/// TLS implementation used for HTTPS endpoints.
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum TlsBackend {
+    /// The platform's TLS library (OpenSSL on Linux), which uses the system trust store.
+    #[default]
+    Native,
+    /// rustls with the Mozilla root certificates bundled into the binary.
+    Rustls,
+}
+
+/// SPARQL store behind the endpoint, for the store-specific ways of limiting a query.
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum Engine {
+    /// Plain SPARQL 1.1, no limits can be passed along.
+    #[default]
+    Generic,
+    Virtuoso,
+    Blazegraph,
+    Graphdb,
+}
+
+/// Media type of SPARQL JSON results, asked for in the Accept header.
+pub const SPARQL_RESULTS_JSON: &str = "application/sparql-results+json";
+
+/// Limits the store enforces on every query, in the form the engine understands.
+#[derive(Default)]
+pub struct QueryLimits {
+    /// Put before every query, like the inference pragma.
+    pub pragma: Option<String>,
+    /// Extra form fields sent with every query.
+    pub params: Vec<(&'static str, String)>,
+    /// A result with this many rows may have been cut off by the store.
+    pub max_rows: Option<usize>,
+}
+
+impl Engine {
+    /// The pragmas and parameters that make the store stop a query after `timeout` or
+    /// after returning `max_rows` rows, along with the ones every query of the engine needs.
+    /// Errors for limits the engine has no way to pass.
+    pub fn limits(
+        self,
+        timeout: Option<Duration>,
+        max_rows: Option<usize>,
+    ) -> Result<QueryLimits, ScratchpadError> {
+        let unsupported = |option: &str| {
+            ScratchpadError::Config(format!(
+                "{} is not supported with --engine {}",
+                option,
+                self.to_possible_value().unwrap().get_name()
+            ))
+        };
+        let mut limits = QueryLimits {
+            max_rows,
+            ..QueryLimits::default()
+        };
+
+        match self {
+            Engine::Virtuoso => {
+                // Some Virtuoso setups ignore the Accept header and answer with HTML.
+                limits
+                    .params
+                    .push(("format", SPARQL_RESULTS_JSON.to_string()));
+                limits.pragma = max_rows.map(|rows| format!("DEFINE sql:max-rows {}", rows));
+                if let Some(timeout) = timeout {
+                    limits
+                        .params
+                        .push(("timeout", timeout.as_millis().to_string()));
+                }
+            }
+            Engine::Blazegraph | Engine::Graphdb if max_rows.is_some() => {
+                return Err(unsupported("--max-rows"))
+            }
+            Engine::Blazegraph => {
+                if let Some(timeout) = timeout {
+                    limits
+                        .params
+                        .push(("maxQueryTimeMillis", timeout.as_millis().to_string()));
+                }
+            }
+            Engine::Graphdb => {
+                if let Some(timeout) = timeout {
+                    limits
+                        .params
+                        .push(("timeout", timeout.as_secs().to_string()));
+                }
+            }
+            Engine::Generic if max_rows.is_some() => return Err(unsupported("--max-rows")),
+            Engine::Generic if timeout.is_some() => return Err(unsupported("--query-timeout")),
+            Engine::Generic => {}
+        }
+
+        Ok(limits)
+    }
+}
+
+/// Connection settings of the HTTP client, see the README for what works against Virtuoso.
+pub struct ClientOptions {
+    /// Idle connections kept open per host, `None` keeps reqwest's default (unlimited).
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Talk HTTP/2 without negotiating it first. Only works if the endpoint speaks HTTP/2
+    /// over cleartext or over TLS with ALPN.
+    pub http2_prior_knowledge: bool,
+    pub tcp_keepalive: Option<Duration>,
+    pub tls_backend: TlsBackend,
+    /// Extra root certificate (PEM or DER) to trust, e.g. an internal CA.
+    pub ca_cert: Option<PathBuf>,
+    /// Accept any certificate. Only meant for development setups.
+    pub insecure: bool,
+    /// Proxy for all requests, instead of the one from `HTTP_PROXY`/`HTTPS_PROXY`.
+    pub proxy: Option<String>,
+    /// Comma-separated hosts that bypass the proxy, instead of the ones from `NO_PROXY`.
+    pub no_proxy: Option<String>,
+}
+
+/// Generates an ID that is unique enough to tell runs apart in the endpoint's logs.
+pub fn generate_run_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+
+    format!("{:x}-{:x}", nanos, std::process::id())
+}
+
+/// Builds the HTTP client shared by all queries of a run.
+pub fn build_client(
+    user_agent: &str,
+    run_id: &str,
+    options: &ClientOptions,
+) -> Result<Client, Box<dyn std::error::Error>> {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        HeaderName::from_static(RUN_ID_HEADER),
+        HeaderValue::from_str(run_id)?,
+    );
+
+    let mut builder = Client::builder()
+        .user_agent(user_agent)
+        .default_headers(headers)
+        .redirect(redirect_policy())
+        .tcp_keepalive(options.tcp_keepalive);
+    if let Some(max_idle) = options.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max_idle);
+    }
+    if options.http2_prior_knowledge {
+        builder = builder.http2_prior_knowledge();
+    }
+    builder = match options.tls_backend {
+        TlsBackend::Native => builder.use_native_tls(),
+        TlsBackend::Rustls => builder.use_rustls_tls(),
+    };
+    if let Some(path) = &options.ca_cert {
+        builder = builder.add_root_certificate(load_certificate(path)?);
+    }
+    if options.insecure {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    for proxy in proxies(options)? {
+        builder = builder.proxy(proxy);
+    }
+    let client = builder.build()?;
+
+    Ok(client)
+}
+
+/// The proxies given on the command line. Without --proxy, the proxies from `HTTP_PROXY`
+/// and `HTTPS_PROXY` are used, which reqwest picks up by itself unless --no-proxy has to be
+/// applied to them. Unlike reqwest, a malformed proxy URL is an error instead of being
+/// ignored, so requests do not silently bypass the proxy.
+fn proxies(options: &ClientOptions) -> Result<Vec<Proxy>, ScratchpadError> {
+    let no_proxy = match &options.no_proxy {
+        Some(hosts) => NoProxy::from_string(hosts),
+        None => NoProxy::from_env(),
+    };
+    let invalid = |source: &str, url: &str, e: reqwest::Error| {
+        ScratchpadError::Config(format!("invalid proxy URL '{}' in {}: {}", url, source, e))
+    };
+
+    if let Some(url) = &options.proxy {
+        let proxy = Proxy::all(url.as_str()).map_err(|e| invalid("--proxy", url, e))?;
+        return Ok(vec![proxy.no_proxy(no_proxy)]);
+    }
+
+    // reqwest reads the environment itself, it only has to be done here to validate the
+    // URLs and to apply --no-proxy.
+    let mut proxies = vec![];
+    for name in ["HTTP_PROXY", "HTTPS_PROXY"] {
+        let Some(url) = proxy_env(name) else {
+            continue;
+        };
+        let proxy = if name == "HTTP_PROXY" {
+            Proxy::http(url.as_str())
+        } else {
+            Proxy::https(url.as_str())
+        };
+        let proxy = proxy.map_err(|e| invalid(name, &url, e))?;
+        if options.no_proxy.is_some() {
+            proxies.push(proxy.no_proxy(no_proxy.clone()));
+        }
+    }
+
+    Ok(proxies)
+}
+
+/// A proxy variable in upper or lower case, the way curl and reqwest look them up.
+fn proxy_env(name: &str) -> Option<String> {
+    [name.to_string(), name.to_lowercase()]
+        .iter()
+        .find_map(|name| env::var(name).ok().filter(|url| !url.is_empty()))
+}
+
+/// Follows 307 and 308, which repeat the POST with its body and headers. 301, 302 and 303
+/// make clients turn the POST into a GET without body, which would silently lose the query,
+/// so they are an error instead.
+fn redirect_policy() -> Policy {
+    Policy::custom(|attempt| {
+        if attempt.previous().len() > MAX_REDIRECTS {
+            return attempt.error(format!("more than {} redirects", MAX_REDIRECTS));
+        }
+        match attempt.status() {
+            StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => attempt.follow(),
+            status => {
+                let message = format!(
+                    "the endpoint answered with {} to {}, following it would turn the POST \
+                     into a GET and lose the query. Use the redirect target as endpoint instead",
+                    status,
+                    attempt.url()
+                );
+                attempt.error(message)
+            }
+        }
+    })
+}
+
+fn load_certificate(path: &PathBuf) -> Result<Certificate, ScratchpadError> {
+    let invalid = |reason: String| {
+        ScratchpadError::Config(format!(
+            "Cannot use {} as CA certificate: {}",
+            path.display(),
+            reason
+        ))
+    };
+
+    let bytes = std::fs::read(path).map_err(|e| invalid(e.to_string()))?;
+    // Certificate::from_pem accepts anything without a PEM header, so check for it first.
+    if bytes.starts_with(b"-----BEGIN") {
+        Certificate::from_pem(&bytes)
+    } else {
+        Certificate::from_der(&bytes)
+    }
+    .map_err(|e| invalid(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{json_response, response, TestServer};
+
+    fn options() -> ClientOptions {
+        ClientOptions {
+            pool_max_idle_per_host: None,
+            http2_prior_knowledge: false,
+            tcp_keepalive: None,
+            tls_backend: TlsBackend::Rustls,
+            ca_cert: None,
+            insecure: false,
+            proxy: None,
+            no_proxy: Some("*".to_string()),
+        }
+    }
+
+    #[test]
+    fn deadlock_backoff_doubles_until_the_retries_run_out() {
+        let deadlock = DeadlockRetry::new("deadlock", 3, Duration::from_millis(250)).unwrap();
+
+        let delays = (1..=4)
+            .map(|attempt| deadlock.delay(attempt))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            delays,
+            [
+                Some(Duration::from_millis(250)),
+                Some(Duration::from_millis(500)),
+                Some(Duration::from_secs(1)),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn deadlock_pattern_is_matched_anywhere_in_the_body() {
+        let deadlock = DeadlockRetry::new(DEFAULT_DEADLOCK_PATTERN, 1, Duration::ZERO).unwrap();
+
+        assert!(
+            deadlock.is_deadlock("<pre>Virtuoso 40001 Error SR172: Transaction deadlocked</pre>")
+        );
+        assert!(deadlock.is_deadlock("ERROR: could not serialize access (SQLSTATE 40001)"));
+        assert!(!deadlock.is_deadlock("Virtuoso 42000 Error SR186: No permission"));
+        assert!(matches!(
+            DeadlockRetry::new("SR17[", 1, Duration::ZERO),
+            Err(ScratchpadError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn retry_after_is_read_in_seconds_or_as_a_date() {
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+            headers
+        };
+        let in_a_minute = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+
+        assert_eq!(retry_after(&headers("2")), Some(Duration::from_secs(2)));
+        let until_date = retry_after(&headers(&in_a_minute)).unwrap();
+        assert!(until_date > Duration::from_secs(58) && until_date <= Duration::from_secs(60));
+        assert_eq!(
+            retry_after(&headers("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after(&headers("soon")), None);
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+    }
+
+    #[tokio::test]
+    async fn too_many_requests_is_retried_after_the_wait_it_asks_for() {
+        let busy = || response("429 Too Many Requests", &[("Retry-After", "2")], b"");
+        let empty = r#"{"head":{"vars":[]},"results":{"bindings":[]}}"#;
+        let server = TestServer::start(vec![busy(), json_response(empty), busy()]);
+        let mut client = SparqlClient::new(reqwest::Client::new());
+        client.retry_after_retries = 1;
+
+        let started = std::time::Instant::now();
+        let patient = fetch_sparql_results(&client, &server.endpoint, "ASK {}").await;
+        let waited = started.elapsed();
+        client.retry_after_retries = 0;
+        let impatient = fetch_sparql_results(&client, &server.endpoint, "ASK {}").await;
+
+        assert!(patient.is_ok());
+        assert!(waited >= Duration::from_secs(2));
+        assert!(impatient.is_err());
+        assert_eq!(client.stats.retries(), 1);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn each_engine_passes_the_limits_its_own_way() {
+        let timeout = Some(Duration::from_secs(90));
+
+        let virtuoso = Engine::Virtuoso.limits(timeout, Some(10_000)).unwrap();
+        assert_eq!(
+            virtuoso.pragma.as_deref(),
+            Some("DEFINE sql:max-rows 10000")
+        );
+        assert_eq!(
+            virtuoso.params,
+            [
+                ("format", SPARQL_RESULTS_JSON.to_string()),
+                ("timeout", "90000".to_string())
+            ]
+        );
+        assert_eq!(virtuoso.max_rows, Some(10_000));
+
+        let blazegraph = Engine::Blazegraph.limits(timeout, None).unwrap();
+        assert_eq!(blazegraph.pragma, None);
+        assert_eq!(
+            blazegraph.params,
+            [("maxQueryTimeMillis", "90000".to_string())]
+        );
+
+        let graphdb = Engine::Graphdb.limits(timeout, None).unwrap();
+        assert_eq!(graphdb.params, [("timeout", "90".to_string())]);
+
+        let generic = Engine::Generic.limits(None, None).unwrap();
+        assert!(generic.pragma.is_none() && generic.params.is_empty());
+    }
+
+    #[test]
+    fn limits_an_engine_cannot_pass_are_refused() {
+        let refused = |engine: Engine, timeout: Option<u64>, max_rows| match engine
+            .limits(timeout.map(Duration::from_secs), max_rows)
+        {
+            Err(ScratchpadError::Config(msg)) => msg,
+            _ => panic!("the limit must be refused"),
+        };
+
+        assert_eq!(
+            refused(Engine::Generic, Some(5), None),
+            "--query-timeout is not supported with --engine generic"
+        );
+        assert_eq!(
+            refused(Engine::Graphdb, None, Some(100)),
+            "--max-rows is not supported with --engine graphdb"
+        );
+        assert_eq!(
+            refused(Engine::Blazegraph, Some(5), Some(100)),
+            "--max-rows is not supported with --engine blazegraph"
+        );
+    }
+
+    #[tokio::test]
+    async fn requests_identify_the_tool_and_the_run() {
+        let empty = r#"{"head":{"vars":[]},"results":{"bindings":[]}}"#;
+        let server = TestServer::start(vec![json_response(empty), json_response(empty)]);
+        let run_id = generate_run_id();
+        let default =
+            SparqlClient::new(build_client(DEFAULT_USER_AGENT, &run_id, &options()).unwrap());
+        let custom = SparqlClient::new(build_client("ops-cleanup/2", &run_id, &options()).unwrap());
+
+        fetch_sparql_results(&default, &server.endpoint, "ASK {}")
+            .await
+            .unwrap();
+        fetch_sparql_results(&custom, &server.endpoint, "ASK {}")
+            .await
+            .unwrap();
+
+        let requests = server
+            .requests()
+            .into_iter()
+            .map(|request| request.to_lowercase())
+            .collect::<Vec<_>>();
+        assert!(DEFAULT_USER_AGENT.starts_with("delete-organization/"));
+        assert!(requests[0].contains(&format!("\r\nuser-agent: {}\r\n", DEFAULT_USER_AGENT)));
+        assert!(requests[1].contains("\r\nuser-agent: ops-cleanup/2\r\n"));
+        for request in &requests {
+            assert!(request.contains(&format!("\r\nx-request-id: {}\r\n", run_id)));
+        }
+    }
+
+    #[tokio::test]
+    async fn pool_tuning_keeps_http1_while_prior_knowledge_starts_with_http2() {
+        let empty = r#"{"head":{"vars":[]},"results":{"bindings":[]}}"#;
+        let server = TestServer::start(vec![json_response(empty), json_response(empty)]);
+        let tuned = ClientOptions {
+            pool_max_idle_per_host: Some(0),
+            tcp_keepalive: Some(Duration::from_secs(30)),
+            ..options()
+        };
+        let http2 = ClientOptions {
+            http2_prior_knowledge: true,
+            ..options()
+        };
+        let tuned = SparqlClient::new(build_client(DEFAULT_USER_AGENT, "run", &tuned).unwrap());
+        let http2 = SparqlClient::new(build_client(DEFAULT_USER_AGENT, "run", &http2).unwrap());
+
+        let over_http1 = fetch_sparql_results(&tuned, &server.endpoint, "ASK {}").await;
+        // The server only speaks HTTP/1.1, so the HTTP/2 client cannot make sense of it.
+        let over_http2 = fetch_sparql_results(&http2, &server.endpoint, "ASK {}").await;
+
+        assert!(over_http1.is_ok());
+        assert!(over_http2.is_err());
+        let requests = server.requests();
+        assert!(requests[0].starts_with("POST /sparql HTTP/1.1\r\n"));
+        assert!(requests[1].starts_with("PRI * HTTP/2.0\r\n"));
+    }
+
+    #[tokio::test]
+    async fn requests_go_through_the_proxy_unless_the_host_bypasses_it() {
+        let empty = r#"{"head":{"vars":[]},"results":{"bindings":[]}}"#;
+        let server = TestServer::start(vec![json_response(empty), json_response(empty)]);
+        // The test server plays the proxy as well as the endpoint behind it.
+        let endpoint = server.endpoint.replace("127.0.0.1", "localhost");
+        let proxied = |no_proxy: &str| ClientOptions {
+            proxy: Some(server.endpoint.trim_end_matches("/sparql").to_string()),
+            no_proxy: Some(no_proxy.to_string()),
+            ..options()
+        };
+        let through = build_client(DEFAULT_USER_AGENT, "run", &proxied("intranet.example"));
+        let bypassing = build_client(DEFAULT_USER_AGENT, "run", &proxied("localhost"));
+
+        fetch_sparql_results(&SparqlClient::new(through.unwrap()), &endpoint, "ASK {}")
+            .await
+            .unwrap();
+        fetch_sparql_results(&SparqlClient::new(bypassing.unwrap()), &endpoint, "ASK {}")
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert!(requests[0].starts_with(&format!("POST {} HTTP/1.1\r\n", endpoint)));
+        assert!(requests[1].starts_with("POST /sparql HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn malformed_proxy_urls_are_an_error() {
+        let options = ClientOptions {
+            proxy: Some("http://proxy.example:port".to_string()),
+            ..options()
+        };
+
+        let Err(ScratchpadError::Config(msg)) = proxies(&options) else {
+            panic!("a malformed proxy must not be ignored");
+        };
+        assert!(msg.starts_with("invalid proxy URL 'http://proxy.example:port' in --proxy: "));
+    }
+
+    #[test]
+    fn unusable_ca_certificates_are_reported_with_their_path() {
+        let dir = std::env::temp_dir().join(format!("ca-cert-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let garbled = dir.join("garbled.pem");
+        std::fs::write(
+            &garbled,
+            "-----BEGIN CERTIFICATE-----\n!!!\n-----END CERTIFICATE-----\n",
+        )
+        .unwrap();
+        let with_ca = |path: PathBuf| ClientOptions {
+            ca_cert: Some(path),
+            ..options()
+        };
+
+        for path in [dir.join("missing.pem"), garbled] {
+            let error = build_client(DEFAULT_USER_AGENT, "run", &with_ca(path.clone()))
+                .unwrap_err()
+                .to_string();
+            assert!(error.contains(&format!("Cannot use {} as CA certificate", path.display())));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn both_tls_backends_build_with_or_without_verification() {
+        for tls_backend in [TlsBackend::Rustls, TlsBackend::Native] {
+            for insecure in [false, true] {
+                let options = ClientOptions {
+                    tls_backend,
+                    insecure,
+                    ..options()
+                };
+                assert!(build_client(DEFAULT_USER_AGENT, "run", &options).is_ok());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limit_spaces_out_requests() {
+        let empty = || json_response(r#"{"head":{"vars":[]},"results":{"bindings":[]}}"#);
+        let server = TestServer::start(vec![empty(), empty(), empty()]);
+        let mut client = SparqlClient::new(Client::new());
+        client.rate_limiter = Some(RateLimiter::new(2.0).unwrap());
+
+        let started = Instant::now();
+        for _ in 0..3 {
+            fetch_sparql_results(&client, &server.endpoint, "ASK {}")
+                .await
+                .unwrap();
+        }
+
+        // The first request goes out at once, the others half a second apart.
+        assert!(started.elapsed() >= Duration::from_millis(1000));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn rate_limit_must_be_positive() {
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                RateLimiter::new(rate),
+                Err(ScratchpadError::Config(_))
+            ));
+        }
+        assert!(RateLimiter::new(0.5).is_ok());
+    }
+
+    #[tokio::test]
+    async fn permanent_redirects_keep_the_query_and_are_reported() {
+        let canonical = TestServer::start(vec![json_response(
+            r#"{"head":{"vars":[]},"results":{"bindings":[]}}"#,
+        )]);
+        let balancer = TestServer::start(vec![
+            response(
+                "308 Permanent Redirect",
+                &[("Location", &canonical.endpoint)],
+                b"",
+            ),
+            response("302 Found", &[("Location", &canonical.endpoint)], b""),
+        ]);
+        let client =
+            SparqlClient::new(build_client(DEFAULT_USER_AGENT, "run", &options()).unwrap());
+
+        let followed = fetch_sparql_results(&client, &balancer.endpoint, "ASK {}").await;
+        let refused = fetch_sparql_results(&client, &balancer.endpoint, "ASK {}").await;
+
+        assert!(followed.is_ok());
+        assert!(client.redirect_reported.load(Ordering::Relaxed));
+        let redirected = canonical.requests().remove(0).to_lowercase();
+        assert!(redirected.starts_with("post /sparql "));
+        assert!(redirected.contains(&format!("\r\naccept: {}\r\n", SPARQL_RESULTS_JSON)));
+        assert!(redirected.ends_with("query=ask+%7b%7d"));
+        let Err(error) = refused else {
+            panic!("a 302 must not turn the query into a GET");
+        };
+        assert!(error.to_string().contains("turn the POST into a GET"));
+        assert_eq!(balancer.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn lookups_go_to_the_endpoint_of_their_direction() {
+        let empty = || json_response(r#"{"head":{"vars":[]},"results":{"bindings":[]}}"#);
+        let main = TestServer::start(vec![empty(), empty()]);
+        let reverse = TestServer::start(vec![empty()]);
+        let mut backend = HttpBackend::new(SparqlClient::new(Client::new()), &main.endpoint);
+        backend.reverse_endpoint = Some(reverse.endpoint.clone());
+
+        backend.query("SELECT * { ?s ?p ?o }").await.unwrap();
+        backend
+            .lookup(Direction::Reverse, "SELECT ?s { ?s ?p <http://x/reverse> }")
+            .await
+            .unwrap();
+        backend
+            .lookup(Direction::Forward, "SELECT ?o { <http://x/forward> ?p ?o }")
+            .await
+            .unwrap();
+
+        let main = main.requests().join("\n");
+        let reverse = reverse.requests().join("\n");
+        assert!(reverse.contains("reverse"));
+        assert!(!main.contains("reverse"));
+        assert!(main.contains("forward"));
+    }
+
+    #[test]
+    fn run_ids_differ_between_runs() {
+        let first = generate_run_id();
+        std::thread::sleep(Duration::from_millis(1));
+
+        assert_ne!(first, generate_run_id());
+        assert!(first.ends_with(&format!("-{:x}", std::process::id())));
+    }
+}
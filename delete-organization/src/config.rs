@@ -0,0 +1,357 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use indexmap::IndexMap;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::ScratchpadError;
+use crate::validate::validate_path;
+
+/// A <...> IRI or a prefixed name like `prov:wasDerivedFrom` in a property path.
+static PREFIXED_NAME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<[^>]*>|[A-Za-z_][\w.-]*:[\w-]*|:[\w-]+").unwrap());
+
+/// An edge of the config that is likely a mistake, see [`JsonConfig::edge_warnings`].
+pub enum EdgeWarning {
+    /// The same edge listed twice in one array, so it is followed twice for nothing.
+    Duplicate {
+        key: String,
+        field: &'static str,
+        first: usize,
+        second: usize,
+        type_iri: String,
+    },
+    /// The same edge listed under both `reverse` and `forward`.
+    BothDirections {
+        key: String,
+        reverse: usize,
+        forward: usize,
+        type_iri: String,
+    },
+    /// A type listing itself, whose instances are then expanded until no new ones are found.
+    SelfReference {
+        key: String,
+        field: &'static str,
+        index: usize,
+    },
+}
+
+impl fmt::Display for EdgeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EdgeWarning::Duplicate {
+                key,
+                field,
+                first,
+                second,
+                type_iri,
+            } => write!(
+                f,
+                "{key}: {field}[{first}] and {field}[{second}] both follow {type_iri}"
+            ),
+            EdgeWarning::BothDirections {
+                key,
+                reverse,
+                forward,
+                type_iri,
+            } => write!(
+                f,
+                "{key}: reverse[{reverse}] and forward[{forward}] both follow {type_iri}"
+            ),
+            EdgeWarning::SelfReference { key, field, index } => write!(
+                f,
+                "{key}: {field}[{index}] follows the type itself, its instances are expanded \
+                 until no new ones are found"
+            ),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct JsonConfig {
+    /// Maps prefixes to namespaces so types can be written as e.g. `besluit:Bestuurseenheid`.
+    #[serde(default)]
+    pub prefixes: IndexMap<String, String>,
+    #[serde(flatten)]
+    pub data: IndexMap<String, serde_json::Value>,
+}
+
+impl JsonConfig {
+    /// Reads a config from JSON, or from YAML or TOML when the file has a `.yaml`/`.yml` or
+    /// `.toml` extension. All of them describe the same structure.
+    pub fn load(path: &Path) -> Result<Self, ScratchpadError> {
+        let contents = fs::read_to_string(path)?;
+        let invalid = |e: &dyn std::fmt::Display| {
+            ScratchpadError::Config(format!("Cannot parse {}: {}", path.display(), e))
+        };
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => serde_yaml::from_str(&contents).map_err(|e| invalid(&e)),
+            Some("toml") => toml::from_str(&contents).map_err(|e| invalid(&e)),
+            _ => Ok(serde_json::from_str(&contents)?),
+        }
+    }
+
+    /// Expands a prefixed name into a full `<...>` IRI. Terms that already are full IRIs
+    /// are returned unchanged.
+    pub fn expand_iri(&self, term: &str) -> Result<String, ScratchpadError> {
+        if term.starts_with('<') {
+            return Ok(term.to_string());
+        }
+
+        let Some((prefix, local)) = term.split_once(':') else {
+            return Err(ScratchpadError::Config(format!(
+                "`{}` is neither a <...> IRI nor a prefixed name",
+                term
+            )));
+        };
+
+        match self.prefixes.get(prefix) {
+            Some(namespace) => Ok(format!("<{}{}>", namespace, local)),
+            None => Err(ScratchpadError::Config(format!(
+                "Unknown prefix `{}` in `{}`, declare it under \"prefixes\" or write the IRI as <...>",
+                prefix, term
+            ))),
+        }
+    }
+
+    /// Expands every prefixed name in a property path, leaving the operators, the `a`
+    /// keyword and <...> IRIs as they are.
+    pub fn expand_path(&self, path: &str) -> Result<String, ScratchpadError> {
+        let mut expanded = String::new();
+        let mut last = 0;
+        for name in PREFIXED_NAME.find_iter(path) {
+            expanded.push_str(&path[last..name.start()]);
+            match name.as_str() {
+                iri if iri.starts_with('<') => expanded.push_str(iri),
+                term => expanded.push_str(&self.expand_iri(term)?),
+            }
+            last = name.end();
+        }
+        expanded.push_str(&path[last..]);
+
+        Ok(expanded)
+    }
+
+    /// Rewrites every type in the config, both the keys and the forward/reverse entries
+    /// (including the `type` and `service` of object entries), to full IRIs so the query
+    /// builders never see a prefixed name. Prefixed names in the `path` of object entries
+    /// are expanded too.
+    pub fn expand_prefixes(self) -> Result<Self, ScratchpadError> {
+        let mut data = IndexMap::new();
+
+        for (key, value) in &self.data {
+            let mut value = value.clone();
+            for field in ["reverse", "forward"] {
+                if let Some(array) = value.get_mut(field).and_then(|v| v.as_array_mut()) {
+                    for item in array.iter_mut() {
+                        match item {
+                            Value::String(term) => *term = self.expand_iri(term)?,
+                            Value::Object(edge) => {
+                                for field in ["type", "service"] {
+                                    if let Some(Value::String(term)) = edge.get_mut(field) {
+                                        *term = self.expand_iri(term)?;
+                                    }
+                                }
+                                if let Some(Value::String(path)) = edge.get_mut("path") {
+                                    *path = self.expand_path(path)?;
+                                    validate_path(path)?;
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+
+            data.insert(self.expand_iri(key)?, value);
+        }
+
+        Ok(JsonConfig {
+            prefixes: self.prefixes,
+            data,
+        })
+    }
+
+    /// Lists the edges that are listed twice, under one or both directions, and the types
+    /// listing themselves, in config order. Types are compared as written, so call this on
+    /// an expanded config. A type linked from several parents is not reported, since the
+    /// traversal only visits its instances once.
+    pub fn edge_warnings(&self) -> Vec<EdgeWarning> {
+        let mut warnings = vec![];
+        for (key, value) in &self.data {
+            // An edge is the same if its type, service and path are.
+            let edges = |field: &str| {
+                value
+                    .get(field)
+                    .and_then(|v| v.as_array())
+                    .map(|array| {
+                        array
+                            .iter()
+                            .map(|item| match item {
+                                Value::Object(edge) => {
+                                    ["type", "service", "path"].map(|field| edge.get(field))
+                                }
+                                item => [Some(item), None, None],
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default()
+            };
+            let reverse = edges("reverse");
+            let forward = edges("forward");
+
+            for (field, edges) in [("reverse", &reverse), ("forward", &forward)] {
+                for (index, edge) in edges.iter().enumerate() {
+                    let type_iri = edge[0].and_then(|v| v.as_str()).unwrap_or_default();
+                    if type_iri == key {
+                        warnings.push(EdgeWarning::SelfReference {
+                            key: key.clone(),
+                            field,
+                            index,
+                        });
+                    }
+                    if let Some(first) = edges[..index].iter().position(|other| other == edge) {
+                        warnings.push(EdgeWarning::Duplicate {
+                            key: key.clone(),
+                            field,
+                            first,
+                            second: index,
+                            type_iri: type_iri.to_string(),
+                        });
+                    }
+                }
+            }
+            for (index, edge) in forward.iter().enumerate() {
+                if let Some(reverse_index) = reverse.iter().position(|other| other == edge) {
+                    warnings.push(EdgeWarning::BothDirections {
+                        key: key.clone(),
+                        reverse: reverse_index,
+                        forward: index,
+                        type_iri: edge[0]
+                            .and_then(|v| v.as_str())
+                            .unwrap_or_default()
+                            .to_string(),
+                    });
+                }
+            }
+        }
+
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> JsonConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn prefixed_types_expand_to_the_full_iris() {
+        let config = parse(
+            r#"{
+                "prefixes": { "besluit": "http://data.vlaanderen.be/ns/besluit#" },
+                "besluit:Bestuurseenheid": {
+                    "reverse": ["besluit:Bestuursorgaan"],
+                    "forward": [{ "type": "<http://x/Site>", "path": "besluit:heeft/besluit:in*" }]
+                }
+            }"#,
+        )
+        .expand_prefixes()
+        .unwrap();
+
+        let (key, value) = config.data.first().unwrap();
+        assert_eq!(
+            key,
+            "<http://data.vlaanderen.be/ns/besluit#Bestuurseenheid>"
+        );
+        assert_eq!(
+            value["reverse"][0],
+            "<http://data.vlaanderen.be/ns/besluit#Bestuursorgaan>"
+        );
+        assert_eq!(
+            value["forward"][0]["path"],
+            concat!(
+                "<http://data.vlaanderen.be/ns/besluit#heeft>/",
+                "<http://data.vlaanderen.be/ns/besluit#in>*"
+            )
+        );
+    }
+
+    #[test]
+    fn unknown_prefixes_and_bare_words_are_rejected() {
+        let config = parse(r#"{ "prefixes": { "ex": "http://example.org/" } }"#);
+
+        assert_eq!(config.expand_iri("ex:a").unwrap(), "<http://example.org/a>");
+        assert_eq!(config.expand_iri("<urn:a>").unwrap(), "<urn:a>");
+        assert!(config.expand_iri("foaf:name").is_err());
+        assert!(config.expand_iri("Organization").is_err());
+    }
+
+    #[test]
+    fn unparsable_yaml_and_toml_name_the_file() {
+        let dir = std::env::temp_dir().join(format!("config-errors-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        for (name, contents) in [("bad.yml", "a: [unclosed"), ("bad.toml", "a = ")] {
+            let path = dir.join(name);
+            fs::write(&path, contents).unwrap();
+
+            let Err(ScratchpadError::Config(msg)) = JsonConfig::load(&path) else {
+                panic!("{} must not load", name);
+            };
+            assert!(msg.starts_with(&format!("Cannot parse {}: ", path.display())));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn repeated_and_self_referencing_edges_are_reported_with_their_place() {
+        let config = parse(
+            r#"{
+                "<http://t/Municipality>": {
+                    "reverse": ["<http://t/Mandate>", "<http://t/Council>"],
+                    "forward": [
+                        "<http://t/Council>",
+                        { "type": "<http://t/Address>", "path": "<http://p/at>" },
+                        { "type": "<http://t/Address>", "path": "<http://p/at>" },
+                        { "type": "<http://t/Address>", "path": "<http://p/billedAt>" }
+                    ]
+                },
+                "<http://t/Council>": { "reverse": ["<http://t/Council>"] },
+                "<http://t/Mandate>": { "forward": ["<http://t/Council>"] }
+            }"#,
+        );
+
+        let warnings = config
+            .edge_warnings()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            warnings,
+            [
+                concat!(
+                    "<http://t/Municipality>: forward[1] and forward[2] ",
+                    "both follow <http://t/Address>"
+                ),
+                concat!(
+                    "<http://t/Municipality>: reverse[1] and forward[0] ",
+                    "both follow <http://t/Council>"
+                ),
+                concat!(
+                    "<http://t/Council>: reverse[0] follows the type itself, ",
+                    "its instances are expanded until no new ones are found"
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ScratchpadError {
+    Http(reqwest::Error),
+    Json(serde_json::Error),
+    Io(std::io::Error),
+    /// A generated query or a response from the endpoint is not what we expect.
+    Sparql(String),
+    /// The run is configured in a way that cannot work or is unsafe.
+    Config(String),
+    /// A safety limit of the run was exceeded.
+    Limit(String),
+    /// A saved traversal state cannot be used for this run.
+    State(String),
+    /// The endpoint rejected a query for being too large, see `DeletionPlanner` for the retry.
+    QueryTooLarge(String),
+    /// The store aborted an update because of a transaction deadlock, which can be retried.
+    Deadlock(String),
+}
+
+impl fmt::Display for ScratchpadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScratchpadError::Http(e) => write!(f, "HTTP error: {}", e),
+            ScratchpadError::Json(e) => write!(f, "JSON error: {}", e),
+            ScratchpadError::Io(e) => write!(f, "I/O error: {}", e),
+            ScratchpadError::Sparql(msg) => write!(f, "SPARQL error: {}", msg),
+            ScratchpadError::Config(msg) => write!(f, "Config error: {}", msg),
+            ScratchpadError::Limit(msg) => write!(f, "Limit exceeded: {}", msg),
+            ScratchpadError::State(msg) => write!(f, "State error: {}", msg),
+            ScratchpadError::QueryTooLarge(msg) => write!(f, "Query too large: {}", msg),
+            ScratchpadError::Deadlock(msg) => write!(f, "Transaction deadlock: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ScratchpadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ScratchpadError::Http(e) => Some(e),
+            ScratchpadError::Json(e) => Some(e),
+            ScratchpadError::Io(e) => Some(e),
+            ScratchpadError::Sparql(_)
+            | ScratchpadError::Config(_)
+            | ScratchpadError::Limit(_)
+            | ScratchpadError::State(_)
+            | ScratchpadError::QueryTooLarge(_)
+            | ScratchpadError::Deadlock(_) => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ScratchpadError {
+    fn from(e: reqwest::Error) -> Self {
+        ScratchpadError::Http(e)
+    }
+}
+
+impl From<serde_json::Error> for ScratchpadError {
+    fn from(e: serde_json::Error) -> Self {
+        ScratchpadError::Json(e)
+    }
+}
+
+impl From<std::io::Error> for ScratchpadError {
+    fn from(e: std::io::Error) -> Self {
+        ScratchpadError::Io(e)
+    }
+}
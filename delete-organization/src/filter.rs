@@ -0,0 +1,178 @@
+use crate::error::ScratchpadError;
+
+const XSD_DATE_TIME: &str = "<http://www.w3.org/2001/XMLSchema#dateTime>";
+
+/// Restricts a deletion to resources whose provenance timestamp lies after a cutoff.
+pub struct TemporalFilter {
+    /// Predicate linking a resource to its creation timestamp, as <...> IRI.
+    pub predicate: String,
+    /// Cutoff as xsd:dateTime lexical form.
+    pub since: String,
+}
+
+impl TemporalFilter {
+    pub fn new(predicate: &str, since: &str) -> Result<Self, ScratchpadError> {
+        if !(predicate.starts_with('<') && predicate.ends_with('>')) {
+            return Err(ScratchpadError::Config(format!(
+                "Provenance predicate {} must be a full <...> IRI",
+                predicate
+            )));
+        }
+
+        Ok(TemporalFilter {
+            predicate: predicate.to_string(),
+            since: normalize_timestamp(since)?,
+        })
+    }
+
+    /// Triple pattern and FILTER that only let `var` through if it was created after the
+    /// cutoff. Every line is prefixed with `indent` so it lines up with the query around it.
+    pub fn clause(&self, var: &str, indent: &str) -> String {
+        format!(
+            "\n\n{indent}{var} {} ?created_at .\n{indent}FILTER(?created_at > \"{}\"^^{})",
+            self.predicate,
+            self.since,
+            XSD_DATE_TIME,
+            indent = indent,
+            var = var,
+        )
+    }
+}
+
+/// Accepts `YYYY-MM-DD` or an ISO 8601 date-time and returns an xsd:dateTime, since
+/// comparing a dateTime against a plain date is not defined in SPARQL.
+fn normalize_timestamp(since: &str) -> Result<String, ScratchpadError> {
+    let invalid = || {
+        ScratchpadError::Config(format!(
+            "--since {} is not an ISO 8601 date or date-time",
+            since
+        ))
+    };
+
+    let (date, time) = match since.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (since, None),
+    };
+
+    let date_parts = date.split('-').collect::<Vec<_>>();
+    let valid_date = matches!(
+        date_parts.as_slice(),
+        [y, m, d] if y.len() == 4 && m.len() == 2 && d.len() == 2
+            && [y, m, d].iter().all(|p| p.bytes().all(|b| b.is_ascii_digit()))
+    );
+    if !valid_date {
+        return Err(invalid());
+    }
+
+    match time {
+        None => Ok(format!("{}T00:00:00Z", date)),
+        Some(time) => {
+            let valid_time = time.len() >= 8
+                && time
+                    .bytes()
+                    .all(|b| b.is_ascii_digit() || matches!(b, b':' | b'.' | b'+' | b'-' | b'Z'));
+            if !valid_time {
+                return Err(invalid());
+            }
+
+            Ok(since.to_string())
+        }
+    }
+}
+
+/// Decides which discovered URIs the planner follows. URIs that are not followed are
+/// neither deleted nor expanded further.
+pub trait UriFilter: Send + Sync {
+    /// `uri` and `type_iri` are passed without the surrounding `<>`.
+    fn should_follow(&self, uri: &str, type_iri: &str) -> bool;
+}
+
+/// Follows every discovered URI.
+pub struct FollowAll;
+
+impl UriFilter for FollowAll {
+    fn should_follow(&self, _uri: &str, _type_iri: &str) -> bool {
+        true
+    }
+}
+
+/// Follows URIs by namespace prefix. With a non-empty allowlist only URIs in one of its
+/// namespaces are followed; the denylist always wins.
+#[derive(Default)]
+pub struct NamespaceFilter {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl UriFilter for NamespaceFilter {
+    fn should_follow(&self, uri: &str, _type_iri: &str) -> bool {
+        let allowed = self.allow.is_empty() || self.allow.iter().any(|ns| uri.starts_with(ns));
+        allowed && !self.deny.iter().any(|ns| uri.starts_with(ns))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_become_midnight_date_times() {
+        let filter =
+            TemporalFilter::new("<http://purl.org/dc/terms/created>", "2024-03-01").unwrap();
+
+        assert_eq!(filter.since, "2024-03-01T00:00:00Z");
+        assert_eq!(
+            filter.clause("?s", "  "),
+            format!(
+                "\n\n  ?s <http://purl.org/dc/terms/created> ?created_at .\n  \
+                 FILTER(?created_at > \"2024-03-01T00:00:00Z\"^^{})",
+                XSD_DATE_TIME
+            )
+        );
+    }
+
+    #[test]
+    fn date_times_are_kept_and_malformed_cutoffs_rejected() {
+        let created = "<http://purl.org/dc/terms/created>";
+
+        assert_eq!(
+            TemporalFilter::new(created, "2024-03-01T12:30:00+02:00")
+                .unwrap()
+                .since,
+            "2024-03-01T12:30:00+02:00"
+        );
+        assert!(TemporalFilter::new(created, "01/03/2024").is_err());
+        assert!(TemporalFilter::new(created, "2024-3-1").is_err());
+        assert!(TemporalFilter::new(created, "2024-03-01T12h").is_err());
+        assert!(TemporalFilter::new("dct:created", "2024-03-01").is_err());
+    }
+
+    #[test]
+    fn allowlist_only_follows_its_namespaces() {
+        let filter = NamespaceFilter {
+            allow: vec!["http://data.example/".to_string()],
+            ..NamespaceFilter::default()
+        };
+
+        assert!(filter.should_follow("http://data.example/org/1", "http://t/Org"));
+        assert!(!filter.should_follow("http://other.example/org/1", "http://t/Org"));
+        assert!(FollowAll.should_follow("http://other.example/org/1", "http://t/Org"));
+    }
+
+    #[test]
+    fn denylist_wins_over_the_allowlist() {
+        let filter = NamespaceFilter {
+            allow: vec!["http://data.example/".to_string()],
+            deny: vec!["http://data.example/shared/".to_string()],
+        };
+        let deny_only = NamespaceFilter {
+            deny: vec!["http://data.example/shared/".to_string()],
+            ..NamespaceFilter::default()
+        };
+
+        assert!(!filter.should_follow("http://data.example/shared/code", "http://t/Code"));
+        assert!(filter.should_follow("http://data.example/org/1", "http://t/Org"));
+        assert!(!deny_only.should_follow("http://data.example/shared/code", "http://t/Code"));
+        assert!(deny_only.should_follow("http://anything.example/x", "http://t/Code"));
+    }
+}
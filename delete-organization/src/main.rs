@@ -1,521 +1,3530 @@
-use std::collections::HashSet;
-use std::fs::{File, OpenOptions};
-use std::io::BufReader;
-use std::{collections::HashMap, io::Write};
-
-use indexmap::IndexMap;
-use reqwest::{
-    header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE},
-    Client,
-};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, IsTerminal, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use env_logger::Env;
+use futures::{pin_mut, StreamExt};
+use log::{debug, warn, LevelFilter};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, ACCEPT_ENCODING, CONTENT_TYPE};
+use reqwest::StatusCode;
 
-use serde::Deserialize;
 use serde_json::Value;
 
-#[derive(Deserialize)]
-struct jsonConfig {
-    #[serde(flatten)]
-    data: IndexMap<String, serde_json::Value>,
+use cli::{
+    Command, ConnectionArgs, ExecutionArgs, GenerateArgs, OutputArgs, StatementArgs, TraversalArgs,
+};
+use client::{
+    build_client, generate_run_id, ClientOptions, DeadlockRetry, HttpBackend, RateLimiter,
+    SparqlClient, SPARQL_RESULTS_JSON,
+};
+use config::{EdgeWarning, JsonConfig};
+use error::ScratchpadError;
+use filter::{FollowAll, NamespaceFilter, TemporalFilter, UriFilter};
+use manifest::Manifest;
+use metrics::RunMetrics;
+use output::{
+    prepare_output_dir, unescape_separator, HttpSink, MemorySink, OutputSink, OutputTarget,
+    PerTypeOutput, StdoutSink, SubjectsOut, Tee, STATEMENT_PLACEHOLDER,
+};
+use planner::{
+    explain, DeletionPlanner, Direction, PlannedStatement, PlannerOptions, RenameTo, SubjectMapping,
+};
+use report::{Report, SubjectDiff};
+use scaffold::scaffold_config;
+use sparql_results::{parse_ask, parse_solution, Binding, Solutions};
+use uri::resolve_iri;
+
+/// `println!` for progress and summaries, which goes to stderr instead once stdout carries
+/// data, like the subjects of `--subjects-out -`.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::STDOUT_IS_DATA.load(std::sync::atomic::Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
 }
 
+mod cli;
+mod client;
+mod config;
+mod error;
+mod filter;
+mod manifest;
+mod metrics;
+mod output;
+mod planner;
+mod profile;
+mod report;
+mod scaffold;
+mod sparql_results;
+mod state;
+#[cfg(test)]
+mod test_server;
+mod uri;
+mod validate;
+
+/// Set when stdout is used for data, see [`status!`].
+static STDOUT_IS_DATA: AtomicBool = AtomicBool::new(false);
+
+/// Log target of the queries sent to the endpoints, see --print-queries.
+const QUERY_LOG_TARGET: &str = "sparql";
+
+pub const SPARQL_ENDPOINT: &str = "http://localhost:8870/sparql";
+
+pub const URI: &str =
+    "<http://data.lblod.info/id/bestuurseenheden/9af828073bb4c53989fe0693526a31aec47d85a4bc6ac9d485ca6878eb3b3f1c>";
+
 async fn fetch_sparql_results(
-    client: &Client,
+    client: &SparqlClient,
     endpoint: &str,
     query: &str,
-) -> Result<Value, Box<dyn std::error::Error>> {
+) -> Result<Value, ScratchpadError> {
+    let query = match &client.limits.pragma {
+        Some(pragma) => format!("{}\n{}", pragma, query),
+        None => query.to_string(),
+    };
     let mut params = HashMap::new();
-    params.insert("query", query);
+    params.insert(client.query_field.as_str(), query.as_str());
+    for (name, value) in &client.limits.params {
+        params.insert(name, value);
+    }
+    debug!(target: QUERY_LOG_TARGET, "Query to {}:\n{}", endpoint, query);
 
     let mut headers = HeaderMap::new();
-    headers.insert(
-        ACCEPT,
-        HeaderValue::from_static("application/sparql-results+json"),
-    );
-    headers.insert(
-        CONTENT_TYPE,
-        HeaderValue::from_static("application/x-www-form-urlencoded"),
-    );
-
-    let response = client
-        .post(endpoint)
-        .headers(headers)
-        .form(&params)
-        .send()
+    headers.insert(ACCEPT, HeaderValue::from_static(SPARQL_RESULTS_JSON));
+    // Large result sets compress well; reqwest transparently decompresses the body
+    // since the gzip and deflate features are enabled.
+    headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip, deflate"));
+
+    let started = Instant::now();
+    let mut response = client
+        .send(endpoint, || {
+            client.post_form(endpoint, &params).headers(headers.clone())
+        })
         .await?;
+    client.check_redirect(endpoint, response.url());
 
     let result: Value;
 
     if response.status().is_success() {
-        let body = response.text().await?;
-        result = serde_json::from_str(&body)?;
+        // Virtuoso answers a query that ran into its timeout with whatever it found so far.
+        if response
+            .headers()
+            .get("x-sql-state")
+            .is_some_and(|state| state == "S1TAT")
+        {
+            return Err(ScratchpadError::Limit(format!(
+                "{} returned partial results after reaching --query-timeout",
+                endpoint
+            )));
+        }
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("none")
+            .to_string();
+        let body = match client.max_response_bytes {
+            Some(max) => {
+                let too_large = || {
+                    ScratchpadError::Limit(format!(
+                        "Response from {} exceeds the maximum of {} bytes, \
+                         raise --max-response-bytes if this is expected",
+                        endpoint, max
+                    ))
+                };
+
+                // Fail early if the server announces the size, otherwise stop reading as soon
+                // as the limit is crossed instead of buffering the whole body first.
+                if response
+                    .content_length()
+                    .is_some_and(|len| len > max as u64)
+                {
+                    return Err(too_large());
+                }
+
+                let mut body = Vec::new();
+                while let Some(chunk) = response.chunk().await? {
+                    body.extend_from_slice(&chunk);
+                    if body.len() > max {
+                        return Err(too_large());
+                    }
+                }
+                body
+            }
+            None => response.bytes().await?.to_vec(),
+        };
+        client.stats.add_response_bytes(body.len());
+        // Parse straight from the bytes to avoid holding a second, decoded copy of the body.
+        // A proxy in front of the endpoint may answer with an HTML page and a 200 status.
+        result = serde_json::from_slice(&body).map_err(|err| {
+            ScratchpadError::Sparql(format!(
+                "{} did not return JSON (content-type {}, {}): {}",
+                endpoint,
+                content_type,
+                err,
+                body_snippet(&body)
+            ))
+        })?;
+        if let Some(max) = client.limits.max_rows {
+            let rows = parse_solution(&result).map_or(0, |solutions| solutions.rows().len());
+            if rows >= max {
+                return Err(ScratchpadError::Limit(format!(
+                    "A query to {} returned {} rows, which may have been cut off by \
+                     --max-rows, raise it or lower --chunk-size",
+                    endpoint, rows
+                )));
+            }
+        }
     } else {
-        println!("Error: {:?}", response);
-        println!("Status code: {:?}", response.status());
-        result = serde_json::Value::Null;
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        client.stats.add_response_bytes(body.len());
+        client.stats.add_request_time(started.elapsed());
+        // The caller retries these with a smaller query.
+        if is_query_too_large(status, &body) {
+            return Err(ScratchpadError::QueryTooLarge(format!(
+                "{} rejected the query with {}",
+                endpoint, status
+            )));
+        }
+        return Err(ScratchpadError::Sparql(format!(
+            "{} answered the query with {}: {}",
+            endpoint,
+            status,
+            body_snippet(body.as_bytes())
+        )));
     }
 
+    client.stats.add_request_time(started.elapsed());
+
     Ok(result)
 }
 
-fn parse_json_uris<'a>(
-    value: &'a serde_json::Value,
-    target: &'a str,
-) -> Vec<&'a serde_json::Value> {
-    let mut v: Vec<&serde_json::Value> = vec![];
-
-    // Loop over the results and print them line by line
-    if let Some(value) = value.get("results") {
-        if let Some(bindings) = value.get("bindings") {
-            if let Some(array) = bindings.as_array() {
-                for binding in array {
-                    // println!("{}", binding);
-                    if binding[target]["type"] == "uri" {
-                        v.push(binding);
-                    }
+/// The start of a response body, on a single line, for error messages.
+fn body_snippet(body: &[u8]) -> String {
+    const MAX_CHARS: usize = 200;
+    let text = String::from_utf8_lossy(body);
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(MAX_CHARS) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    }
+}
+
+/// Runs a SPARQL UPDATE, failing if the endpoint does not accept it.
+async fn execute_update(
+    client: &SparqlClient,
+    endpoint: &str,
+    update: &str,
+    deadlock: &DeadlockRetry,
+) -> Result<(), ScratchpadError> {
+    let mut params = HashMap::new();
+    params.insert(client.update_field.as_str(), update);
+    debug!(target: QUERY_LOG_TARGET, "Update to {}:\n{}", endpoint, update);
+
+    let started = Instant::now();
+    let response = client
+        .send(endpoint, || client.post_form(endpoint, &params))
+        .await?;
+    client.check_redirect(endpoint, response.url());
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    client.stats.add_request_time(started.elapsed());
+    client.stats.add_response_bytes(body.len());
+
+    if deadlock.is_deadlock(&body) {
+        return Err(ScratchpadError::Deadlock(format!(
+            "{} aborted the update with {}: {}",
+            endpoint,
+            status,
+            body.trim()
+        )));
+    }
+    if !status.is_success() {
+        return Err(ScratchpadError::Sparql(format!(
+            "{} rejected the update with {}: {}",
+            endpoint,
+            status,
+            body.trim()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Executes `update`, sending it again with backoff as long as the store reports a
+/// deadlock and retries are left.
+async fn execute_update_retrying(
+    client: &SparqlClient,
+    endpoint: &str,
+    update: &str,
+    deadlock: &DeadlockRetry,
+) -> Result<(), ScratchpadError> {
+    let mut attempt = 0;
+    loop {
+        match execute_update(client, endpoint, update, deadlock).await {
+            Err(ScratchpadError::Deadlock(msg)) => {
+                attempt += 1;
+                let Some(delay) = deadlock.delay(attempt) else {
+                    return Err(ScratchpadError::Deadlock(format!(
+                        "{} (gave up after {} retries)",
+                        msg,
+                        attempt - 1
+                    )));
+                };
+                warn!(
+                    "Update deadlocked, retrying in {}ms ({}): {}",
+                    delay.as_millis(),
+                    attempt,
+                    msg
+                );
+                client.stats.count_retry();
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Executes the statements queued in `sink` in requests of `batch_size` statements each.
+/// When a batch fails, its statements are retried one by one to find the one that fails.
+async fn execute_statements(
+    client: &SparqlClient,
+    sink: &HttpSink,
+    batch_size: usize,
+    deadlock: &DeadlockRetry,
+) -> Result<(), ScratchpadError> {
+    let endpoint = sink.endpoint();
+    let statements = sink.statements();
+    let batches = statements.chunks(batch_size).count();
+    let failed = |index: usize, statement: &PlannedStatement, e: ScratchpadError| {
+        let what = match statement.subjects.as_slice() {
+            [] => statement.update.clone(),
+            subjects => format!("deleting {}", subjects.join(", ")),
+        };
+        ScratchpadError::Sparql(format!("Statement {} ({}) failed: {}", index + 1, what, e))
+    };
+
+    for (i, batch) in statements.chunks(batch_size).enumerate() {
+        let update = batch
+            .iter()
+            .map(|statement| statement.update.as_str())
+            .collect::<Vec<_>>()
+            .join(" ;\n");
+
+        match execute_update_retrying(client, endpoint, &update, deadlock).await {
+            Ok(()) => status!(
+                "Batch {}/{}: executed {} statement(s)",
+                i + 1,
+                batches,
+                batch.len()
+            ),
+            Err(e) if batch.len() > 1 => {
+                warn!(
+                    "Batch {}/{} failed ({}), executing its statements one by one",
+                    i + 1,
+                    batches,
+                    e
+                );
+                for (j, statement) in batch.iter().enumerate() {
+                    execute_update_retrying(client, endpoint, &statement.update, deadlock)
+                        .await
+                        .map_err(|e| failed(i * batch_size + j, statement, e))?;
                 }
+                status!(
+                    "Batch {}/{}: executed {} statement(s) one by one",
+                    i + 1,
+                    batches,
+                    batch.len()
+                );
             }
+            Err(e) => return Err(failed(i * batch_size, &batch[0], e)),
         }
     }
 
-    v
+    Ok(())
 }
 
-fn build_delete_snippet(results: &Vec<&serde_json::Value>, target: &str) -> String {
-    let mut s = String::new();
-    s.push_str(
-        r#"DELETE {
-  GRAPH ?g {
-    ?s ?p ?o .
-  }
+/// Asks the user to confirm executing the statements by typing `yes` or the endpoint's
+/// host. Without a terminal to ask on, only `--yes` allows executing.
+fn confirm_execution(
+    statements: usize,
+    endpoint: &str,
+    yes: bool,
+) -> Result<bool, ScratchpadError> {
+    let stdin = std::io::stdin();
+
+    ask_confirmation(statements, endpoint, yes, stdin.is_terminal(), stdin.lock())
 }
-WHERE {
-  VALUES ?s {
-"#,
+
+/// [`confirm_execution`] reading the answer from `input`, which is only asked if it is
+/// `interactive`.
+fn ask_confirmation(
+    statements: usize,
+    endpoint: &str,
+    yes: bool,
+    interactive: bool,
+    mut input: impl BufRead,
+) -> Result<bool, ScratchpadError> {
+    if yes {
+        return Ok(true);
+    }
+    if !interactive {
+        return Err(ScratchpadError::Config(
+            "Refusing to execute without confirmation, stdin is not a terminal. Pass --yes \
+             to execute non-interactively"
+                .to_string(),
+        ));
+    }
+
+    let host = reqwest::Url::parse(endpoint)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.to_string()));
+    print!(
+        "About to execute {} DELETE statement(s) against {}. Type `yes`{} to continue: ",
+        statements,
+        endpoint,
+        host.as_ref()
+            .map(|host| format!(" or `{}`", host))
+            .unwrap_or_default()
     );
+    std::io::stdout().flush()?;
 
-    let mut values = String::new();
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    let answer = answer.trim();
+    if answer == "yes" || host.is_some_and(|host| answer == host) {
+        return Ok(true);
+    }
 
-    // Construct the VALUES snippet.
-    for val in results {
-        // println!("{}", val);
-        values.push_str(&format!(
-            "    <{}>\n",
-            &val[target]["value"].as_str().unwrap()
-        ));
+    status!("Not executing, the generated statements are still in the output");
+    Ok(false)
+}
+
+/// Sends a trivial query to `endpoint`, or a no-op update if it is an `update` endpoint,
+/// so a typo or an unreachable server is reported before any real work is done.
+async fn check_endpoint_health(
+    client: &SparqlClient,
+    endpoint: &str,
+    update: bool,
+) -> Result<(), ScratchpadError> {
+    let mut params = HashMap::new();
+    match update {
+        true => params.insert(client.update_field.as_str(), "INSERT DATA {}"),
+        false => params.insert(client.query_field.as_str(), "ASK {}"),
+    };
+
+    let response = client
+        .send(endpoint, || {
+            client
+                .post_form(endpoint, &params)
+                .header(ACCEPT, SPARQL_RESULTS_JSON)
+        })
+        .await
+        .map_err(|e| ScratchpadError::Config(format!("Cannot reach {}: {}", endpoint, e)))?;
+    client.check_redirect(endpoint, response.url());
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.bytes().await.unwrap_or_default();
+        return Err(ScratchpadError::Config(format!(
+            "{} answered the health check with {}: {}",
+            endpoint,
+            status,
+            body_snippet(&body)
+        )));
     }
 
-    s.push_str(&values);
-    s.push_str("  }\n");
-    s.push_str(
-        r#"
-  GRAPH ?g {
-    ?s ?p ?o .
-  }
+    Ok(())
+}
+
+/// Endpoints signal an oversized query either with a dedicated status code or, like
+/// Virtuoso, with a generic error whose message says so. Other errors, like a timeout
+/// page, are not retried with a smaller query.
+fn is_query_too_large(status: StatusCode, body: &str) -> bool {
+    matches!(
+        status,
+        StatusCode::PAYLOAD_TOO_LARGE | StatusCode::URI_TOO_LONG
+    ) || body.contains("SQ200")
+        || body.to_lowercase().contains("query too large")
+}
+
+/// Returns the bindings whose `target` variable is bound to a URI. Anything that is not a
+/// SELECT result set, like an ASK result or an error object, is an error, so a failed query
+/// cannot be mistaken for one without results.
+fn parse_json_uris<'a>(
+    value: &'a serde_json::Value,
+    target: &'a str,
+) -> Result<Vec<Binding<'a>>, ScratchpadError> {
+    match parse_solution(value)? {
+        Solutions::Select(rows) => Ok(rows
+            .into_iter()
+            .filter(|binding| binding.uri(target).is_some())
+            .collect()),
+        other => Err(ScratchpadError::Sparql(format!(
+            "Expected SELECT results binding ?{}, got {}",
+            target,
+            other.kind()
+        ))),
+    }
+}
+
+/// Names of the graph, subject, predicate and object variables of generated statements,
+/// including the leading `?`.
+#[derive(Clone)]
+pub struct QueryVars {
+    /// `None` when statements target the default graph or a single `graph`.
+    pub g: Option<String>,
+    /// The one named graph statements delete from, with `WITH <graph>` instead of
+    /// `GRAPH ?g` blocks.
+    pub graph: Option<String>,
+    pub s: String,
+    pub p: String,
+    pub o: String,
 }
-"#,
-    );
 
-    s
+impl QueryVars {
+    /// `?g`, `?s`, `?p` and `?o` with `prefix` in front of the name, e.g. `?__g` for `__`.
+    pub fn prefixed(prefix: &str) -> Result<Self, ScratchpadError> {
+        if !prefix.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(ScratchpadError::Config(format!(
+                "Variable prefix {} may only contain letters, digits and underscores",
+                prefix
+            )));
+        }
+        let var = |name: &str| format!("?{}{}", prefix, name);
+
+        Ok(QueryVars {
+            g: Some(var("g")),
+            graph: None,
+            s: var("s"),
+            p: var("p"),
+            o: var("o"),
+        })
+    }
+
+    /// The variable name of `?p` without the `?`, as it appears in query results.
+    pub fn p_name(&self) -> &str {
+        &self.p[1..]
+    }
+}
+
+impl Default for QueryVars {
+    fn default() -> Self {
+        QueryVars::prefixed("").unwrap()
+    }
+}
+
+/// The `subject ?p ?o` pattern of the deleted triples, inside `GRAPH ?g` or `GRAPH <graph>`
+/// unless `vars` has neither. Indented to sit directly inside a top-level block.
+fn quad_pattern(subject: &str, vars: &QueryVars) -> String {
+    match vars.graph.as_ref().or(vars.g.as_ref()) {
+        Some(g) => format!(
+            r#"  GRAPH {} {{
+    {} {} {} .
+  }}"#,
+            g, subject, vars.p, vars.o
+        ),
+        None => format!("  {} {} {} .", subject, vars.p, vars.o),
+    }
+}
+
+/// The start of a DELETE statement with the patterns of [`update_pattern`], scoped to the
+/// single graph of `vars` if it has one.
+fn delete_clause(vars: &QueryVars) -> String {
+    match &vars.graph {
+        Some(graph) => format!("WITH {}\nDELETE", graph),
+        None => "DELETE".to_string(),
+    }
+}
+
+/// [`quad_pattern`] for the DELETE and WHERE of an update, where `WITH` already names the
+/// single graph if there is one.
+fn update_pattern(subject: &str, vars: &QueryVars) -> String {
+    match &vars.graph {
+        Some(_) => format!("  {} {} {} .", subject, vars.p, vars.o),
+        None => quad_pattern(subject, vars),
+    }
 }
 
-fn build_parametrized_delete_query(uri: &str) -> String {
+fn build_parametrized_delete_query(
+    uri: &str,
+    predicates: &[String],
+    since: Option<&TemporalFilter>,
+    vars: &QueryVars,
+) -> String {
     let query = format!(
-        r#"DELETE {{
-  GRAPH ?g {{
-    ?s ?p ?o .
-  }}
+        r#"{delete} {{
+{pattern}
 }}
 WHERE {{
-  VALUES ?s {{
+  VALUES {s} {{
 {}
-  }}
+  }}{}
 
-  GRAPH ?g {{
-    ?s ?p ?o .
-  }}
+{pattern}{}
 }}"#,
-        uri
+        uri,
+        predicate_values(predicates, vars),
+        since.map(|f| f.clause(&vars.s, "  ")).unwrap_or_default(),
+        delete = delete_clause(vars),
+        pattern = update_pattern(&vars.s, vars),
+        s = vars.s,
     );
 
     query
 }
 
-fn create_simple_forward_parametrized_delete_query(uri: &str) -> String {
-    let query = format!(
-        r#"DELETE {{
-  GRAPH ?g {{
-    ?s ?p ?o .
-  }}
+/// Like [`build_parametrized_delete_query`], but re-inserts the deleted triples with the
+/// replacement of their subject, given as `(subject, replacement)` pairs.
+fn build_rename_query(
+    pairs: &[(String, String)],
+    predicates: &[String],
+    since: Option<&TemporalFilter>,
+    vars: &QueryVars,
+) -> String {
+    let renamed = format!("{}_renamed", vars.s);
+    let values = pairs
+        .iter()
+        .map(|(subject, replacement)| format!("    ({} {})", subject, replacement))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        r#"{delete} {{
+{pattern}
+}}
+INSERT {{
+{renamed_pattern}
 }}
 WHERE {{
-  BIND({} AS ?s)
+  VALUES ({s} {renamed}) {{
+{}
+  }}{}
 
-  GRAPH ?g {{
-    ?s ?p ?o .
-  }}
+{pattern}{}
 }}"#,
-        uri
+        values,
+        predicate_values(predicates, vars),
+        since.map(|f| f.clause(&vars.s, "  ")).unwrap_or_default(),
+        delete = delete_clause(vars),
+        pattern = update_pattern(&vars.s, vars),
+        renamed_pattern = update_pattern(&renamed, vars),
+        s = vars.s,
+    )
+}
+
+/// Restricts the predicate variable to the given predicates, nothing if every predicate
+/// should be deleted.
+fn predicate_values(predicates: &[String], vars: &QueryVars) -> String {
+    if predicates.is_empty() {
+        return String::new();
+    }
+
+    format!("\n  VALUES {} {{ {} }}", vars.p, predicates.join(" "))
+}
+
+/// Counts the triples [`build_parametrized_delete_query`] with the same arguments would
+/// delete, per value of `group_by`, e.g. per predicate with `vars.p`.
+fn build_triple_count_query(
+    uri: &str,
+    predicates: &[String],
+    since: Option<&TemporalFilter>,
+    vars: &QueryVars,
+    group_by: &str,
+) -> String {
+    let query = format!(
+        r#"SELECT {group_by} (COUNT(*) AS ?count) WHERE {{
+  VALUES {s} {{
+{}
+  }}{}
+
+{pattern}{}
+}}
+GROUP BY {group_by}"#,
+        uri,
+        predicate_values(predicates, vars),
+        since.map(|f| f.clause(&vars.s, "  ")).unwrap_or_default(),
+        pattern = quad_pattern(&vars.s, vars),
+        s = vars.s,
     );
 
     query
 }
 
-fn create_forward_parametrized_select_query_with_type(uri: &str, uri_type: &str) -> String {
+/// Selects the triples [`build_parametrized_delete_query`] with the same arguments would
+/// delete, together with their graph unless `vars` has no graph variable.
+fn build_deleted_triples_query(
+    uri: &str,
+    predicates: &[String],
+    since: Option<&TemporalFilter>,
+    vars: &QueryVars,
+) -> String {
     let query = format!(
-        r#"
-    SELECT DISTINCT ?o WHERE {{
-      VALUES ?values {{
-        {}
-      }}
+        r#"SELECT {s} {p} {o}{g} WHERE {{
+  VALUES {s} {{
+{}
+  }}{}
 
-      ?values ?p ?o .
-      ?o a {} .
-    }}
-  "#,
-        uri, uri_type
+{pattern}{}
+}}"#,
+        uri,
+        predicate_values(predicates, vars),
+        since.map(|f| f.clause(&vars.s, "  ")).unwrap_or_default(),
+        pattern = quad_pattern(&vars.s, vars),
+        s = vars.s,
+        p = vars.p,
+        o = vars.o,
+        g = vars
+            .g
+            .as_ref()
+            .map(|g| format!(" {}", g))
+            .unwrap_or_default(),
     );
 
     query
 }
 
-fn create_backward_parametrized_select_query_with_type(uri: &str, uri_type: &str) -> String {
+/// Counts all triples of each of `graphs`, given as VALUES block.
+fn build_graph_size_query(graphs: &str) -> String {
     let query = format!(
-        r#"
-    SELECT DISTINCT ?s WHERE {{
-      VALUES ?values {{
-        {}
-      }}
+        r#"SELECT ?g (COUNT(*) AS ?count) WHERE {{
+  VALUES ?g {{
+{}
+  }}
 
-      ?s a {} ;
-        ?p ?values .
-    }}
-  "#,
-        uri, uri_type
+  GRAPH ?g {{
+    ?s ?p ?o .
+  }}
+}}
+GROUP BY ?g"#,
+        graphs
     );
 
     query
 }
 
-fn create_forward_parametrized_query(uri: &str) -> String {
+/// Counts the distinct values of `?x` in `pattern`.
+fn create_count_query(pattern: &str) -> String {
     let query = format!(
-        r#"
-      SELECT DISTINCT ?o WHERE {{
-        VALUES ?values {{
-          {}
-        }}
-
-        ?values ?p ?o .
-      }}
-    "#,
-        uri
+        r#"SELECT (COUNT(DISTINCT ?x) AS ?count) WHERE {{
+{}
+}}"#,
+        pattern
     );
 
     query
 }
 
-fn create_reverse_parametrized_query(uri: &str) -> String {
+/// Asks whether [`build_parametrized_delete_query`] with the same arguments would delete
+/// anything at all.
+fn build_ask_query(
+    uri: &str,
+    predicates: &[String],
+    since: Option<&TemporalFilter>,
+    vars: &QueryVars,
+) -> String {
     let query = format!(
-        r#"
-        SELECT DISTINCT ?s WHERE {{
-          VALUES ?values {{
-            {}
-          }}
-
-          ?s ?p ?values .
-        }}
-    "#,
-        uri
+        r#"ASK {{
+  VALUES {s} {{
+{}
+  }}{}
+
+{pattern}{}
+}}"#,
+        uri,
+        predicate_values(predicates, vars),
+        since.map(|f| f.clause(&vars.s, "  ")).unwrap_or_default(),
+        pattern = quad_pattern(&vars.s, vars),
+        s = vars.s,
     );
 
     query
 }
 
-async fn build_reverse_path(uri: &str) -> Result<String, Box<dyn std::error::Error>> {
-    const SPARQL_ENDPOINT: &str = "http://localhost:8870/sparql";
-    let client = Client::new();
+/// Shorthand equivalent of [`build_parametrized_delete_query`] without extra WHERE clauses:
+/// one `DELETE WHERE` operation per URI, separated by `;`.
+fn build_delete_where_query(uris: &[String], vars: &QueryVars) -> String {
+    uris.iter()
+        .map(|uri| {
+            format!(
+                r#"DELETE WHERE {{
+{}
+}}"#,
+                quad_pattern(uri, vars)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ;\n")
+}
+
+/// The reification properties linking an `rdf:Statement` to its subject, predicate and
+/// object.
+const REIFICATION_PROPERTIES: &str = "<http://www.w3.org/1999/02/22-rdf-syntax-ns#subject> \
+     <http://www.w3.org/1999/02/22-rdf-syntax-ns#predicate> \
+     <http://www.w3.org/1999/02/22-rdf-syntax-ns#object>";
+
+/// Matches the reification nodes `?x` of statements about any of `uris`, given as VALUES
+/// block, in whatever position.
+fn reification_pattern(uris: &str) -> String {
+    format!(
+        r#"  VALUES ?values {{
+{}
+  }}
+  VALUES ?role {{ {} }}
 
-    let mut s = String::new();
+  ?x ?role ?values ."#,
+        uris, REIFICATION_PROPERTIES
+    )
+}
 
-    // Start with the initial URI and fetch all reverse subjects until nothing can be found.
-    let get_initial_reverse_triples = create_reverse_parametrized_query(uri);
+/// Deletes every triple of the reification nodes of statements about any of `uris`, given
+/// as VALUES block. The link to the reified statement is expected in the same graph as the
+/// node's other triples.
+fn build_reification_delete_query(uris: &str, vars: &QueryVars) -> String {
+    let target = format!("{}_reified", vars.s);
+    let role = format!("{}_role", vars.p);
+    let triples = format!(
+        "{s} {role} {target} .\n    {s} {p} {o} .",
+        s = vars.s,
+        p = vars.p,
+        o = vars.o
+    );
+    // With a single graph, `WITH` already names it.
+    let pattern = match (&vars.graph, &vars.g) {
+        (None, Some(g)) => format!("  GRAPH {} {{\n    {}\n  }}", g, triples),
+        _ => format!("  {}", triples.replace("\n    ", "\n  ")),
+    };
+
+    format!(
+        r#"{delete} {{
+{deleted}
+}}
+WHERE {{
+  VALUES {target} {{
+{uris}
+  }}
+  VALUES {role} {{ {properties} }}
 
-    let mut r = fetch_sparql_results(
-        &client,
-        SPARQL_ENDPOINT,
-        get_initial_reverse_triples.as_str(),
+{pattern}
+}}"#,
+        delete = delete_clause(vars),
+        deleted = update_pattern(&vars.s, vars),
+        properties = REIFICATION_PROPERTIES,
     )
-    .await?;
+}
 
-    let mut results = parse_json_uris(&r, "s");
+fn build_drop_graph_query(graph: &str) -> String {
+    format!("DROP SILENT GRAPH {}", graph)
+}
 
-    while !results.is_empty() {
-        s.push_str(build_delete_snippet(&results, "s").as_str());
-        s.push_str("\n;\n\n");
+/// Deletes the triples pointing to `uri`, which the statements deleting the subjects leave
+/// alone. Reflexive triples of `uri` are left to the statement deleting `uri` itself, so
+/// they are deleted exactly once.
+fn create_simple_reverse_parametrized_delete_query(uri: &str, vars: &QueryVars) -> String {
+    format!(
+        r#"{delete} {{
+{pattern}
+}}
+WHERE {{
+  BIND({uri} AS {o})
 
-        // Construct URIs separated by new-lines.
-        // These URIs will be used to create a parametrized query that fetches
-        // reverse triples of these URIs.
-        let uri_value_list = results
-            .iter()
-            .filter_map(|v| v["s"]["value"].as_str().map(|s| format!("<{}>", s)))
-            // .map(|v| format!("<{}>", v["s"]["value"].as_str()))
-            .collect::<Vec<_>>()
-            .join("\n");
-        let get_reverse_triples = create_reverse_parametrized_query(uri_value_list.as_str());
-        r = fetch_sparql_results(&client, SPARQL_ENDPOINT, get_reverse_triples.as_str()).await?;
-        results = parse_json_uris(&r, "s");
-    }
+{pattern}
+  FILTER({s} != {o})
+}}"#,
+        delete = delete_clause(vars),
+        pattern = update_pattern(&vars.s, vars),
+        s = vars.s,
+        o = vars.o,
+    )
+}
 
-    Ok(s)
+/// A generated SELECT together with the variable its results are read from, so the two
+/// cannot drift apart.
+pub struct SparqlQuery {
+    pub text: String,
+    pub result_var: &'static str,
 }
 
-async fn build_forward_path(uri: &str) -> Result<String, Box<dyn std::error::Error>> {
-    const SPARQL_ENDPOINT: &str = "http://localhost:8890/sparql";
-    let client = Client::new();
+/// SELECT of the distinct URIs linked to `values` in `direction`: the `?s` pointing to them
+/// for reverse, the `?o` they point to for forward. Optionally restricted to URIs of
+/// `type_filter` created after `since`, and without any type outside `allowed_types`,
+/// linked through the property `path` instead of any predicate, and evaluated on `service`.
+fn build_select(
+    direction: Direction,
+    values: &str,
+    type_filter: Option<&str>,
+    allowed_types: Option<&[String]>,
+    service: Option<&str>,
+    path: Option<&str>,
+    since: Option<&TemporalFilter>,
+) -> SparqlQuery {
+    let result_var = direction.selected_var();
+    let target = format!("?{}", result_var);
+    let p = path.unwrap_or("?p");
+    let triples = match (direction, type_filter) {
+        (Direction::Reverse, Some(uri_type)) => {
+            format!("?s a {} ;\n        {} ?values .", uri_type, p)
+        }
+        (Direction::Reverse, None) => format!("?s {} ?values .", p),
+        (Direction::Forward, Some(uri_type)) => {
+            format!("?values {} ?o .\n      ?o a {} .", p, uri_type)
+        }
+        (Direction::Forward, None) => format!("?values {} ?o .", p),
+    };
+    let pattern = format!(
+        r#"      VALUES ?values {{
+        {}
+      }}
+
+      {}{}{}"#,
+        values,
+        triples,
+        allowed_types
+            .map(|types| only_types_clause(&target, types))
+            .unwrap_or_default(),
+        since
+            .map(|f| f.clause(&target, "      "))
+            .unwrap_or_default()
+    );
 
-    let mut s = String::new();
+    let text = format!(
+        "\n    SELECT DISTINCT {} WHERE {{\n{}\n    }}\n  ",
+        target,
+        wrap_in_service(&pattern, service)
+    );
 
-    // Start with the initial URI and fetch all reverse subjects until nothing can be found.
-    let get_initial_forward_triples = create_forward_parametrized_query(uri);
+    SparqlQuery { text, result_var }
+}
 
-    let mut r = fetch_sparql_results(
-        &client,
-        SPARQL_ENDPOINT,
-        get_initial_forward_triples.as_str(),
+/// Turns a query from [`build_select`] into the page of its results that follows the IRI
+/// `after`, or into the first page. Ordering by IRI lets the store seek to the start of the
+/// page instead of skipping over all previous results, as it would for an OFFSET.
+fn keyset_page(query: &SparqlQuery, after: Option<&str>, page_size: usize) -> String {
+    let var = query.result_var;
+    let filter = match after {
+        Some(last) => format!("isIRI(?{var}) && STR(?{var}) > \"{last}\""),
+        None => format!("isIRI(?{var})"),
+    };
+    let select = query
+        .text
+        .trim_end()
+        .strip_suffix('}')
+        .expect("a SELECT ends with its WHERE block")
+        .trim_end();
+    format!(
+        r#"{select}
+      FILTER({filter})
+    }}
+    ORDER BY STR(?{var})
+    LIMIT {page_size}
+  "#
     )
-    .await?;
+}
 
-    let mut results = parse_json_uris(&r, "s");
+/// Counts the distinct URIs linked to `values` in `direction` per rdf:type, as ?t and
+/// ?count.
+fn build_linked_types_query(direction: Direction, values: &str) -> SparqlQuery {
+    let target = format!("?{}", direction.selected_var());
+    let triple = match direction {
+        Direction::Reverse => "?s ?p ?values .",
+        Direction::Forward => "?values ?p ?o .",
+    };
+    let text = format!(
+        r#"SELECT ?t (COUNT(DISTINCT {target}) AS ?count) WHERE {{
+  VALUES ?values {{
+    {values}
+  }}
 
-    while !results.is_empty() {
-        s.push_str(build_delete_snippet(&results, "s").as_str());
-        s.push_str("\n;\n\n");
+  {triple}
+  {target} a ?t .
+}}
+GROUP BY ?t"#
+    );
 
-        // Construct URIs separated by new-lines.
-        // These URIs will be used to create a parametrized query that fetches
-        // reverse triples of these URIs.
-        let uri_value_list = results
-            .iter()
-            .filter_map(|v| v["s"]["value"].as_str().map(|s| format!("<{}>", s)))
-            // .map(|v| format!("<{}>", v["s"]["value"].as_str()))
-            .collect::<Vec<_>>()
-            .join("\n");
-        let get_forward_triples = create_forward_parametrized_query(uri_value_list.as_str());
-        r = fetch_sparql_results(&client, SPARQL_ENDPOINT, get_forward_triples.as_str()).await?;
-        results = parse_json_uris(&r, "s");
+    SparqlQuery {
+        text,
+        result_var: "t",
     }
+}
+
+/// Filters out the resources bound to `var` that have a type other than `types`.
+fn only_types_clause(var: &str, types: &[String]) -> String {
+    format!(
+        r#"
+
+      FILTER NOT EXISTS {{
+        {} a ?other_type .
+        FILTER(?other_type NOT IN ({}))
+      }}"#,
+        var,
+        types.join(", ")
+    )
+}
 
-    Ok(s)
+#[cfg(test)]
+fn create_forward_parametrized_select_query_with_type(
+    uri: &str,
+    uri_type: &str,
+    service: Option<&str>,
+    since: Option<&TemporalFilter>,
+) -> SparqlQuery {
+    build_select(
+        Direction::Forward,
+        uri,
+        Some(uri_type),
+        None,
+        service,
+        None,
+        since,
+    )
 }
 
-async fn build_deletion_path(
+#[cfg(test)]
+fn create_backward_parametrized_select_query_with_type(
     uri: &str,
     uri_type: &str,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let file = File::open("config/config-op.json")?;
-    let reader = BufReader::new(file);
-    // let my_data: Value = serde_json::from_reader(reader)?;
-    let parsed_json_config: jsonConfig = serde_json::from_reader(reader)?;
-
-    let mut map: HashMap<&str, Vec<String>> = HashMap::new();
-
-    const SPARQL_ENDPOINT: &str = "http://localhost:8870/sparql";
-    let client = Client::new();
-
-    let mut s = String::new();
-
-    map.insert(uri_type, vec![uri.to_string()]);
-
-    // if let Some(obj) = parsed_json_config.as_object() {
-    for (key, value) in &parsed_json_config.data {
-        println!("{}", key);
-        if let Some(inner_obj) = value.as_object() {
-            if let Some(reverse) = inner_obj.get("reverse") {
-                if let Some(reverse_array) = reverse.as_array() {
-                    for item in reverse_array {
-                        // Fetch URIs belonging to the current key (type).
-                        // These URIs were placed in the hashmap in a previous step
-                        // where their type was in the reverse/forward array of a previous type.
-                        // We fetch them to get their reverse triples.
-                        if let Some(current_uris) = map.get(key.as_str()) {
-                            let values_list = current_uris
-                                .iter()
-                                .map(|v| format!("{}", v))
-                                .collect::<Vec<_>>()
-                                .join("\n");
-                            // println!("{}", values_list);
-                            let get_reverse_triples =
-                                create_backward_parametrized_select_query_with_type(
-                                    values_list.as_str(),
-                                    item.as_str().unwrap(),
-                                );
-                            // println!("{}", get_reverse_triples);
-                            let r = fetch_sparql_results(
-                                &client,
-                                SPARQL_ENDPOINT,
-                                get_reverse_triples.as_str(),
-                            )
-                            .await?;
-
-                            let results = parse_json_uris(&r, "s");
-                            let result_value_list = results
-                                .iter()
-                                .filter_map(|v| {
-                                    v["s"]["value"].as_str().map(|s| format!("<{}>", s))
-                                })
-                                .collect::<Vec<_>>();
-                            if !result_value_list.is_empty() {
-                                // if item != key {
-                                //     map.entry(key)
-                                //         .or_insert_with(Vec::new)
-                                //         .extend(result_value_list);
-                                //     // let ve = map.get(item.as_str().unwrap()).unwrap();
-                                //     // ve.extend(result_value_list);
-                                // } else {
-                                //     map.insert(item.as_str().unwrap(), result_value_list);
-                                // }
-
-                                // We first append all URIs of a specific type to that type's entry
-                                // in the hash map.
-                                //
-                                // However, there are times where we can get duplicate results.
-                                // For example:
-                                // 1. We bundle identifiers from config-op.json.
-                                // 2. We reach the identifier key in the config and start checking
-                                // its foward and backward relationships.
-                                // 3. Identifiers can point to identifiers, which means that one or more
-                                // identifier(s) will be duplicated if they are pointed to by other identifiers.
-                                map.entry(item.as_str().unwrap())
-                                    .or_insert_with(Vec::new)
-                                    .extend(result_value_list);
-
-                                // s.push_str(build_delete_snippet(&results, "s").as_str());
-                                // s.push_str("\n;\n\n");
-                            }
-                        }
-                    }
-                }
-            }
+    service: Option<&str>,
+    since: Option<&TemporalFilter>,
+) -> SparqlQuery {
+    build_select(
+        Direction::Reverse,
+        uri,
+        Some(uri_type),
+        None,
+        service,
+        None,
+        since,
+    )
+}
 
-            if let Some(forward) = inner_obj.get("forward") {
-                if let Some(forward_array) = forward.as_array() {
-                    for item in forward_array {
-                        // Fetch URIs belonging to the current key (type).
-                        // These URIs were placed in the hashmap in a previous step
-                        // where their type was in the reverse/forward array of a previous type.
-                        // We fetch them to get their forward triples.
-                        if let Some(current_uris) = map.get(key.as_str()) {
-                            let values_list = current_uris
-                                .iter()
-                                .map(|v| format!("{}", v))
-                                .collect::<Vec<_>>()
-                                .join("\n");
-                            // println!("{}", values_list);
-                            let get_forward_triples =
-                                create_forward_parametrized_select_query_with_type(
-                                    values_list.as_str(),
-                                    item.as_str().unwrap(),
-                                );
-                            // println!("{}", get_forward_triples);
-                            let r = fetch_sparql_results(
-                                &client,
-                                SPARQL_ENDPOINT,
-                                get_forward_triples.as_str(),
-                            )
-                            .await?;
-
-                            let results = parse_json_uris(&r, "o");
-                            // println!("{:?}", results);
-                            let result_value_list = results
-                                .iter()
-                                .filter_map(|v| {
-                                    v["o"]["value"].as_str().map(|s| format!("<{}>", s))
-                                })
-                                .collect::<Vec<_>>();
-                            if !result_value_list.is_empty() {
-                                // if item != key {
-                                //     map.entry(key)
-                                //         .or_insert_with(Vec::new)
-                                //         .extend(result_value_list);
-                                //     // let ve = map.get(item.as_str().unwrap()).unwrap();
-                                //     // ve.extend(result_value_list);
-                                // } else {
-                                //     map.insert(item.as_str().unwrap(), result_value_list);
-                                // }
-
-                                map.entry(item.as_str().unwrap())
-                                    .or_insert_with(Vec::new)
-                                    .extend(result_value_list);
-
-                                // s.push_str(build_delete_snippet(&results, "o").as_str());
-                                // s.push_str("\n;\n\n");
-                            }
-                        }
-                    }
-                }
-            }
+/// Evaluates `pattern` on a remote endpoint. The VALUES block goes along with it, since
+/// not every engine passes the bindings on to the remote endpoint.
+fn wrap_in_service(pattern: &str, service: Option<&str>) -> String {
+    match service {
+        None => pattern.to_string(),
+        Some(service) => {
+            let indented = pattern
+                .lines()
+                .map(|line| match line {
+                    "" => String::new(),
+                    line => format!("  {}", line),
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            format!("      SERVICE {} {{\n{}\n      }}", service, indented)
         }
     }
-    // }
+}
+
+/// Looks up the rdf:type of the seed, so the caller does not need to know it upfront.
+fn create_type_select_query(uri: &str) -> SparqlQuery {
+    let text = format!(
+        r#"
+    SELECT DISTINCT ?t WHERE {{
+      {} a ?t .
+    }}
+  "#,
+        uri
+    );
+
+    SparqlQuery {
+        text,
+        result_var: "t",
+    }
+}
 
-    for (key, value) in map {
-        // let values_list = value
-        //     .iter()
-        //     .map(|v| format!("    {}", v))
-        //     .collect::<Vec<_>>()
-        //     .join("\n");
-        let values_list: Vec<String> = value
-        .into_iter()
-        .collect::<HashSet<_>>()
-        .into_iter()
-        .collect();
+/// Selects up to `limit` instances of `uri_type`.
+fn create_instances_query(uri_type: &str, limit: usize) -> SparqlQuery {
+    let text = format!(
+        r#"SELECT ?s WHERE {{
+  ?s a {} .
+}}
+LIMIT {}"#,
+        uri_type, limit
+    );
 
-        let tmp = values_list.iter()
-        .map(|v| format!("    {}", v))
-        .collect::<Vec<_>>()
-        .join("\n");
-        s.push_str(build_parametrized_delete_query(tmp.as_str()).as_str());
-        s.push_str("\n\n;\n\n");
+    SparqlQuery {
+        text,
+        result_var: "s",
     }
+}
+
+/// Asks whether `uri` occurs as subject or object of any triple.
+fn create_seed_exists_query(uri: &str) -> String {
+    let query = format!(
+        r#"
+    ASK {{
+      {{ {} ?p ?o . }}
+      UNION
+      {{ ?s ?p {} . }}
+    }}
+  "#,
+        uri, uri
+    );
 
-    Ok(s)
+    query
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // const SPARQL_ENDPOINT: &str = "http://localhost:8870/sparql";
-    const URI: &str =
-        "<http://data.lblod.info/id/bestuurseenheden/9af828073bb4c53989fe0693526a31aec47d85a4bc6ac9d485ca6878eb3b3f1c>";
-    const URI_TYPE: &str = "<http://data.vlaanderen.be/ns/besluit#Bestuurseenheid>";
-
-    // let out = build_reverse_path(URI).await?;
-    // println!("{}", out);
-    let out = build_deletion_path(URI, URI_TYPE).await?;
-    // println!("{}", out);
-
-    //let out_forward = build_forward_path(URI).await?;
-    // println!("{}", out_forward);
-
-    // let mut file = OpenOptions::new()
-    //     .create(true)
-    //     .append(true)
-    //     .open(format!("{}/{}", "out_folder", "output.json"))?;
-
-    // let json_string = serde_json::to_string_pretty(&results)?;
-    // file.write_all(json_string.as_bytes())?;
-
-    let mut f = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(format!("{}/{}", "generated_sparql_queries", "output.txt"))?;
-    // f.write_all("<uri1> a ?type".as_bytes())?;
-    // f.write_all("# Delete reverse triples\n\n".as_bytes())?;
-    f.write_all(out.as_bytes())?;
-
-    // f.write_all("# Delete forward triples\n\n".as_bytes())?;
-    // f.write_all(out_forward.as_bytes())?;
-    // f.write_all(create_simple_forward_parametrized_delete_query(URI).as_bytes())?;
-    // f.write_all(b"\n")?;
+/// Asks whether `uri_type` has any instance, in the store behind `service` if given.
+fn create_type_exists_query(uri_type: &str, service: Option<&str>) -> String {
+    let query = format!(
+        r#"
+    ASK {{
+{}
+    }}
+  "#,
+        wrap_in_service(&format!("      ?x a {} .", uri_type), service)
+    );
 
-    Ok(())
+    query
+}
+
+async fn check_seed_exists(
+    client: &SparqlClient,
+    endpoint: &str,
+    uri: &str,
+) -> Result<(), ScratchpadError> {
+    let r = fetch_sparql_results(client, endpoint, &create_seed_exists_query(uri)).await?;
+
+    if !parse_ask(&r)? {
+        return Err(ScratchpadError::Config(format!(
+            "{} has no triples in {}, check the URI and the endpoint or skip this check \
+             with --no-precheck",
+            uri, endpoint
+        )));
+    }
+
+    Ok(())
+}
+
+/// Runs a user supplied SELECT and returns the URIs bound to `?s`.
+async fn fetch_seeds(
+    client: &SparqlClient,
+    endpoint: &str,
+    query: &str,
+    base: Option<&str>,
+) -> Result<Vec<String>, ScratchpadError> {
+    let r = fetch_sparql_results(client, endpoint, query).await?;
+    let seeds = parse_json_uris(&r, "s")?
+        .iter()
+        .filter_map(|v| v.uri("s"))
+        .map(|s| Ok(format!("<{}>", resolve_iri(s, base)?)))
+        .collect::<Result<Vec<_>, ScratchpadError>>()?;
+
+    if seeds.is_empty() {
+        return Err(ScratchpadError::Config(
+            "The seed query did not return any ?s URIs, there is nothing to delete".to_string(),
+        ));
+    }
+
+    Ok(seeds)
+}
+
+/// Reads seed URIs, one per line, with or without angle brackets. Blank lines and lines
+/// starting with `#` are skipped.
+fn read_seeds(input: impl BufRead, base: Option<&str>) -> Result<Vec<String>, ScratchpadError> {
+    let mut seeds = vec![];
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let iri = line.strip_prefix('<').unwrap_or(line);
+        let iri = iri.strip_suffix('>').unwrap_or(iri);
+        seeds.push(format!("<{}>", resolve_iri(iri, base)?));
+    }
+
+    if seeds.is_empty() {
+        return Err(ScratchpadError::Config(
+            "No seed URIs were read from stdin, there is nothing to delete".to_string(),
+        ));
+    }
+
+    Ok(seeds)
+}
+
+/// The rdf:types of `uri`, looked up at most once per run.
+async fn fetch_types(
+    client: &SparqlClient,
+    endpoint: &str,
+    uri: &str,
+) -> Result<Vec<String>, ScratchpadError> {
+    if let Some(types) = client.cached_types(uri) {
+        return Ok(types);
+    }
+
+    let query = create_type_select_query(uri);
+    let r = fetch_sparql_results(client, endpoint, &query.text).await?;
+    let types = parse_json_uris(&r, query.result_var)?
+        .iter()
+        .filter_map(|v| v.uri(query.result_var).map(|t| format!("<{}>", t)))
+        .collect::<Vec<_>>();
+    client.cache_types(uri, types.clone());
+
+    Ok(types)
+}
+
+async fn fetch_seed_type(
+    client: &SparqlClient,
+    endpoint: &str,
+    uri: &str,
+) -> Result<String, ScratchpadError> {
+    let types = fetch_types(client, endpoint, uri).await?;
+
+    match types.as_slice() {
+        [uri_type] => Ok(uri_type.clone()),
+        [] => Err(ScratchpadError::Config(format!(
+            "{} has no rdf:type in the store, pass it explicitly with --type",
+            uri
+        ))),
+        _ => Err(ScratchpadError::Config(format!(
+            "{} has multiple types ({}), pick the one to seed the deletion with using --type",
+            uri,
+            types.join(", ")
+        ))),
+    }
+}
+
+/// Refuses to run the phases against different stores unless explicitly allowed, since
+/// reading from one store and deleting from another silently produces inconsistent results.
+fn check_endpoints(
+    phases: &[(&str, &str)],
+    allow_split_endpoints: bool,
+) -> Result<(), ScratchpadError> {
+    let Some((_, first_endpoint)) = phases.first() else {
+        return Ok(());
+    };
+    if phases
+        .iter()
+        .all(|(_, endpoint)| endpoint == first_endpoint)
+    {
+        return Ok(());
+    }
+
+    warn!("The phases are configured to use different SPARQL endpoints:");
+    for (phase, endpoint) in phases {
+        warn!("  {:<16} {}", phase, endpoint);
+    }
+    warn!("URIs discovered in one store would be deleted based on another store.");
+
+    if !allow_split_endpoints {
+        return Err(ScratchpadError::Config(
+            "Endpoints differ between phases, pass --allow-split-endpoints to proceed anyway"
+                .to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Sets up logging, checks the endpoints and builds the HTTP client shared by all requests
+/// of a run. Returns the client together with the run ID it sends along.
+async fn connect(
+    args: &ConnectionArgs,
+    update_endpoint: Option<&str>,
+) -> Result<(SparqlClient, String), Box<dyn std::error::Error>> {
+    let mut logger = env_logger::Builder::from_env(Env::default().default_filter_or("warn"));
+    if args.print_queries {
+        logger.filter_module(QUERY_LOG_TARGET, LevelFilter::Debug);
+    }
+    logger.init();
+
+    let reverse_endpoint = args.reverse_endpoint.as_deref().unwrap_or(&args.endpoint);
+    let forward_endpoint = args.forward_endpoint.as_deref().unwrap_or(&args.endpoint);
+    check_endpoints(
+        &[
+            ("deletion", &args.endpoint),
+            ("reverse lookups", reverse_endpoint),
+            ("forward lookups", forward_endpoint),
+        ],
+        args.allow_split_endpoints,
+    )?;
+
+    let run_id = generate_run_id();
+    status!("Run ID: {}", run_id);
+    let client_options = ClientOptions {
+        pool_max_idle_per_host: args.pool_max_idle_per_host,
+        http2_prior_knowledge: args.http2_prior_knowledge,
+        tcp_keepalive: match args.tcp_keepalive {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        },
+        tls_backend: args.tls_backend,
+        ca_cert: args.ca_cert.clone(),
+        insecure: args.insecure,
+        proxy: args.proxy.clone(),
+        no_proxy: args.no_proxy.clone(),
+    };
+    if args.insecure {
+        warn!("--insecure is set, TLS certificates are not verified");
+    }
+    let mut client = SparqlClient::new(build_client(&args.user_agent, &run_id, &client_options)?);
+    client.max_response_bytes = Some(args.max_response_bytes);
+    client.retry_after_retries = args.retry_after_retries;
+    client.rate_limiter = args.rate_limit.map(RateLimiter::new).transpose()?;
+    client.query_field = args.query_field.clone();
+    client.update_field = args.update_field.clone();
+    client.limits = args
+        .engine
+        .limits(args.query_timeout.map(Duration::from_secs), args.max_rows)?;
+
+    if !args.skip_health_check {
+        let mut endpoints = vec![args.endpoint.as_str()];
+        let others = [
+            args.reverse_endpoint.as_deref(),
+            args.forward_endpoint.as_deref(),
+        ];
+        for endpoint in others.into_iter().flatten() {
+            if !endpoints.contains(&endpoint) {
+                endpoints.push(endpoint);
+            }
+        }
+        for endpoint in endpoints {
+            check_endpoint_health(&client, endpoint, false).await?;
+        }
+        // An update-only endpoint may refuse queries, it gets an update instead.
+        if let Some(endpoint) = update_endpoint {
+            check_endpoint_health(&client, endpoint, true).await?;
+        }
+    }
+
+    Ok((client, run_id))
+}
+
+/// Planner options for what a statement deletes, everything else is left at its default.
+fn statement_options(
+    args: &StatementArgs,
+    config: &JsonConfig,
+) -> Result<PlannerOptions, ScratchpadError> {
+    let since = match (&args.since, &args.provenance_predicate) {
+        (Some(since), Some(predicate)) => {
+            Some(TemporalFilter::new(&config.expand_iri(predicate)?, since)?)
+        }
+        _ => None,
+    };
+    let predicates = args
+        .predicates
+        .iter()
+        .map(|predicate| config.expand_iri(predicate))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut vars = QueryVars::prefixed(&args.var_prefix)?;
+    if args.no_named_graphs {
+        vars.g = None;
+    }
+    if let Some(graph) = &args.graph {
+        vars.g = None;
+        vars.graph = Some(config.expand_iri(graph)?);
+    }
+    let rename: Option<Box<dyn SubjectMapping>> = match &args.rename_to {
+        Some(iri) => Some(Box::new(RenameTo(config.expand_iri(iri)?))),
+        None => None,
+    };
+
+    Ok(PlannerOptions {
+        validate: args.validate,
+        since,
+        predicates,
+        vars,
+        rename,
+        query_pragma: args.no_inference.then(|| args.inference_pragma.clone()),
+        ..PlannerOptions::default()
+    })
+}
+
+/// The only direction in which edges are followed, `None` for both.
+fn only_direction(args: &TraversalArgs) -> Option<Direction> {
+    match (args.only_reverse, args.only_forward) {
+        (true, _) => Some(Direction::Reverse),
+        (_, true) => Some(Direction::Forward),
+        _ => None,
+    }
+}
+
+/// Planner options for a traversal, writing statements as described by `output` if given.
+fn traversal_options(
+    args: &TraversalArgs,
+    output: Option<&OutputArgs>,
+    config: &JsonConfig,
+) -> Result<PlannerOptions, ScratchpadError> {
+    let uri_filter: Box<dyn UriFilter> =
+        if args.follow_namespaces.is_empty() && args.skip_namespaces.is_empty() {
+            Box::new(FollowAll)
+        } else {
+            Box::new(NamespaceFilter {
+                allow: args.follow_namespaces.clone(),
+                deny: args.skip_namespaces.clone(),
+            })
+        };
+    let expand_filter: Box<dyn UriFilter> = if args.in_namespaces.is_empty() {
+        Box::new(FollowAll)
+    } else {
+        Box::new(NamespaceFilter {
+            allow: args.in_namespaces.clone(),
+            deny: vec![],
+        })
+    };
+    let mut options = PlannerOptions {
+        state_file: args.state_file.clone(),
+        max_statements: Some(args.max_statements),
+        uri_filter,
+        expand_filter,
+        prune_empty_blocks: args.prune_empty_blocks,
+        only_direction: only_direction(args),
+        chunk_size: args.chunk_size,
+        base: args.base.clone(),
+        continue_on_error: args.continue_on_error,
+        max_stored_uris: args.max_stored_uris,
+        max_runtime: args.max_runtime,
+        strict_types: args.strict_types,
+        traversal_seed: args.traversal_seed,
+        keyset_page_size: args.keyset_pagination.then_some(args.page_size),
+        sample: args.sample.map(|sample| sample.max(1)),
+        sample_seed: args.sample_seed,
+        ..statement_options(&args.statement, config)?
+    };
+    if let Some(output) = output {
+        options.format = output.format;
+        options.delete_style = output.delete_style;
+        options.separator = output.separator.as_deref().map(unescape_separator);
+        options.output_template = output.output_template.as_deref().map(unescape_separator);
+        if let Some(template) = &options.output_template {
+            if !template.contains(STATEMENT_PLACEHOLDER) {
+                return Err(ScratchpadError::Config(format!(
+                    "--output-template must contain the {} placeholder",
+                    STATEMENT_PLACEHOLDER
+                )));
+            }
+        }
+        options.statement_headers = output.statement_headers;
+        options.flatten_output = output.flatten_output;
+        options.head = output.head.map(|head| head.max(1));
+    }
+
+    Ok(options)
+}
+
+fn load_config(args: &StatementArgs) -> Result<JsonConfig, ScratchpadError> {
+    JsonConfig::load(&args.config)?.expand_prefixes()
+}
+
+/// Determines the seeds of a traversal and their type, from --seed-query, --seed-from-stdin
+/// or --uri.
+async fn resolve_seeds(
+    client: &SparqlClient,
+    endpoint: &str,
+    args: &TraversalArgs,
+    config: &JsonConfig,
+    metrics: &mut RunMetrics,
+) -> Result<(Vec<String>, String), Box<dyn std::error::Error>> {
+    // Subjects returned by a seed query exist by definition, so only a single seed URI
+    // needs the precheck.
+    let seeds = match &args.seed_query {
+        None if args.seed_from_stdin => {
+            let seeds = read_seeds(std::io::stdin().lock(), args.base.as_deref())?;
+            status!("Seeding the deletion with {} subject(s)", seeds.len());
+            seeds
+        }
+        Some(path) => {
+            let timer = metrics.start_phase("seed query", &client.stats);
+            let seeds = fetch_seeds(
+                client,
+                endpoint,
+                &std::fs::read_to_string(path)?,
+                args.base.as_deref(),
+            )
+            .await?;
+            metrics.finish_phase(timer, &client.stats);
+            status!("Seeding the deletion with {} subject(s)", seeds.len());
+            seeds
+        }
+        None => {
+            if !args.no_precheck {
+                let timer = metrics.start_phase("precheck", &client.stats);
+                check_seed_exists(client, endpoint, &args.uri).await?;
+                metrics.finish_phase(timer, &client.stats);
+            }
+            vec![args.uri.clone()]
+        }
+    };
+    let uri_type = match &args.uri_type {
+        Some(uri_type) => config.expand_iri(uri_type)?,
+        None => {
+            let timer = metrics.start_phase("type lookup", &client.stats);
+            let uri_type = fetch_seed_type(client, endpoint, &args.uri).await?;
+            metrics.finish_phase(timer, &client.stats);
+            status!("Using type {} of {}", uri_type, args.uri);
+            uri_type
+        }
+    };
+
+    Ok((seeds, uri_type))
+}
+
+/// Sets up everything a traversal needs: the client, the config, the seeds and the planner.
+async fn plan(
+    connection: &ConnectionArgs,
+    traversal: &TraversalArgs,
+    output: Option<&OutputArgs>,
+    update_endpoint: Option<&str>,
+    metrics: &mut RunMetrics,
+) -> Result<(DeletionPlanner, Vec<String>, String, String), Box<dyn std::error::Error>> {
+    let (client, run_id) = connect(connection, update_endpoint).await?;
+
+    let parsed_json_config = load_config(&traversal.statement)?;
+    check_config_edges(&parsed_json_config, traversal.strict_config)?;
+    let (seeds, uri_type) = resolve_seeds(
+        &client,
+        &connection.endpoint,
+        traversal,
+        &parsed_json_config,
+        metrics,
+    )
+    .await?;
+    let options = traversal_options(traversal, output, &parsed_json_config)?;
+    let mut backend = HttpBackend::new(client, &connection.endpoint);
+    backend.reverse_endpoint = connection.reverse_endpoint.clone();
+    backend.forward_endpoint = connection.forward_endpoint.clone();
+    let planner = DeletionPlanner::new(backend, parsed_json_config, options);
+    if traversal.validate_config_against_endpoint {
+        check_config_types(&planner, traversal.strict_config, metrics).await?;
+    }
+
+    Ok((planner, seeds, uri_type, run_id))
+}
+
+/// Warns about the edges of the config that are likely mistakes, and fails on the
+/// duplicate ones with `strict`.
+fn check_config_edges(config: &JsonConfig, strict: bool) -> Result<(), ScratchpadError> {
+    let mut duplicates = 0;
+    for warning in config.edge_warnings() {
+        if matches!(warning, EdgeWarning::Duplicate { .. }) {
+            duplicates += 1;
+        }
+        warn!("{}", warning);
+    }
+    if strict && duplicates > 0 {
+        return Err(ScratchpadError::Config(format!(
+            "The config lists {} edge(s) more than once",
+            duplicates
+        )));
+    }
+
+    Ok(())
+}
+
+/// Warns about the types of the config without instances in the store, and fails on them
+/// with `strict`.
+async fn check_config_types(
+    planner: &DeletionPlanner,
+    strict: bool,
+    metrics: &mut RunMetrics,
+) -> Result<(), ScratchpadError> {
+    let timer = metrics.start_phase("config check", &planner.client().stats);
+    let missing = planner.types_without_instances().await?;
+    metrics.finish_phase(timer, &planner.client().stats);
+
+    for type_iri in &missing {
+        warn!(
+            "{} occurs in the config but has no instances in the store, check it for typos",
+            type_iri
+        );
+    }
+    if strict && !missing.is_empty() {
+        return Err(ScratchpadError::Config(format!(
+            "{} type(s) of the config have no instances: {}",
+            missing.len(),
+            missing.join(", ")
+        )));
+    }
+
+    Ok(())
+}
+
+/// Runs the traversal, writing every statement to `target` as soon as it is generated.
+/// Returns why the traversal stopped early, if it did, after which the run ends.
+async fn generate(
+    planner: &DeletionPlanner,
+    seeds: &[String],
+    uri_type: &str,
+    target: &mut dyn OutputSink,
+    subjects_out: Option<SubjectsOut>,
+    metrics: &mut RunMetrics,
+) -> Result<Option<EarlyStop>, Box<dyn std::error::Error>> {
+    let timer = metrics.start_phase("deletion", &planner.client().stats);
+    let (written, stopped) = run_traversal(planner, seeds, uri_type, target, subjects_out).await?;
+    if let Some(stop) = stopped {
+        stop_early(planner, target, written, stop.reason())?;
+        return Ok(Some(stop));
+    }
+    metrics.finish_phase(timer, &planner.client().stats);
+    metrics.statements = written as u64;
+
+    Ok(None)
+}
+
+/// Why the traversal stopped before it completed.
+#[derive(Debug, PartialEq)]
+enum EarlyStop {
+    Interrupted,
+    OutOfTime,
+}
+
+impl EarlyStop {
+    fn reason(&self) -> &'static str {
+        match self {
+            EarlyStop::Interrupted => "Interrupted",
+            EarlyStop::OutOfTime => "Out of --max-runtime",
+        }
+    }
+
+    /// The status of a shell killed by SIGINT, and the one of timeout(1).
+    fn exit_code(&self) -> i32 {
+        match self {
+            EarlyStop::Interrupted => 130,
+            EarlyStop::OutOfTime => 124,
+        }
+    }
+}
+
+/// Writes the statements of the traversal to `target` until it completes, is interrupted or
+/// runs out of --max-runtime. Returns the number of statements written and why it stopped
+/// early, if it did. No new query is sent once it stopped.
+async fn run_traversal(
+    planner: &DeletionPlanner,
+    seeds: &[String],
+    uri_type: &str,
+    target: &mut dyn OutputSink,
+    mut subjects_out: Option<SubjectsOut>,
+) -> Result<(usize, Option<EarlyStop>), Box<dyn std::error::Error>> {
+    let statements = planner.delete_statements(seeds, uri_type);
+    pin_mut!(statements);
+    let ctrl_c = tokio::signal::ctrl_c();
+    pin_mut!(ctrl_c);
+    // Never completes without a budget.
+    let out_of_time = async {
+        match planner.options().max_runtime {
+            Some(max_runtime) => tokio::time::sleep(max_runtime).await,
+            None => std::future::pending().await,
+        }
+    };
+    pin_mut!(out_of_time);
+
+    let mut written = 0;
+    loop {
+        tokio::select! {
+            statement = statements.next() => match statement {
+                Some(statement) => {
+                    let statement = statement?;
+                    target.write_statement(&statement)?;
+                    if let (Some(out), Some(type_iri)) = (&mut subjects_out, &statement.type_iri) {
+                        out.write(&statement.subjects, type_iri, statement.depth)?;
+                    }
+                    written += 1;
+                    if planner.options().head == Some(written) {
+                        return Ok((written, None));
+                    }
+                }
+                None => return Ok((written, None)),
+            },
+            _ = &mut ctrl_c => return Ok((written, Some(EarlyStop::Interrupted))),
+            _ = &mut out_of_time => return Ok((written, Some(EarlyStop::OutOfTime))),
+        }
+    }
+}
+
+/// Flushes the statements generated so far before the run exits early because of `reason`.
+/// Statements are written whole, so the output is a valid prefix of the complete one.
+fn stop_early(
+    planner: &DeletionPlanner,
+    target: &mut dyn OutputSink,
+    written: usize,
+    reason: &str,
+) -> std::io::Result<()> {
+    target.finish()?;
+    eprintln!(
+        "{}, stopped generating after {} complete statement(s) written to {}",
+        reason,
+        written,
+        target.location()
+    );
+    if planner.options().state_file.is_some() {
+        eprintln!("Run again with the same --state-file to resume");
+    }
+
+    Ok(())
+}
+
+/// The statements deleting the reification nodes of the subjects of `planned`.
+async fn delete_reifications(
+    planner: &DeletionPlanner,
+    planned: &[PlannedStatement],
+    metrics: &mut RunMetrics,
+) -> Result<Vec<PlannedStatement>, Box<dyn std::error::Error>> {
+    let timer = metrics.start_phase("reifications", &planner.client().stats);
+    let mut subjects = planned
+        .iter()
+        .flat_map(|statement| statement.subjects.iter().cloned())
+        .collect::<Vec<_>>();
+    subjects.sort();
+    subjects.dedup();
+    let (statements, nodes) = planner.reification_statements(&subjects).await?;
+    metrics.finish_phase(timer, &planner.client().stats);
+
+    status!(
+        "{} reification node(s) of deleted subjects, deleted in {} statement(s)",
+        nodes,
+        statements.len()
+    );
+
+    Ok(statements)
+}
+
+/// A statement dropping every named graph that `planned` leaves empty.
+async fn drop_empty_graphs(
+    planner: &DeletionPlanner,
+    planned: &[PlannedStatement],
+    metrics: &mut RunMetrics,
+) -> Result<Vec<PlannedStatement>, Box<dyn std::error::Error>> {
+    let timer = metrics.start_phase("empty graphs", &planner.client().stats);
+    let mut deleted = HashMap::new();
+    for statement in planned.iter() {
+        planner
+            .count_graph_triples(&statement.subjects, &mut deleted)
+            .await?;
+    }
+    let graphs = planner.emptied_graphs(&deleted).await?;
+    metrics.finish_phase(timer, &planner.client().stats);
+
+    status!(
+        "{} of {} touched graph(s) left empty and dropped",
+        graphs.len(),
+        deleted.len()
+    );
+
+    Ok(graphs
+        .iter()
+        .map(|graph| planner.drop_graph_statement(graph))
+        .collect())
+}
+
+/// Opens the file or directory statements are written to, creating the directory if needed.
+fn output_sink(args: &OutputArgs) -> Result<Box<dyn OutputSink>, ScratchpadError> {
+    if args.stdout {
+        return Ok(Box::new(StdoutSink::new(args.line_ending)));
+    }
+    if let Some(dir) = &args.per_type_output {
+        prepare_output_dir(dir)?;
+        return Ok(Box::new(PerTypeOutput::new(dir.clone(), args.line_ending)?));
+    }
+
+    // Write every statement as soon as it is generated, so an interrupted run still leaves
+    // all complete statements on disk.
+    let target = match &args.split_output {
+        Some(dir) => {
+            prepare_output_dir(dir)?;
+            OutputTarget::split(dir.clone(), args.line_ending)?
+        }
+        None => {
+            let dir = Path::new("generated_sparql_queries");
+            prepare_output_dir(dir)?;
+            let path = dir.join("output.txt");
+            OutputTarget::combined(
+                path.clone(),
+                args.append_output,
+                args.line_ending,
+                args.compress,
+            )
+            .map_err(|e| {
+                ScratchpadError::Config(format!("Cannot open {}: {}", path.display(), e))
+            })?
+        }
+    };
+
+    Ok(Box::new(target))
+}
+
+/// Counts the triples per predicate of `planned`, prints the summary and writes the report
+/// to `path` if given.
+#[allow(clippy::too_many_arguments)]
+async fn report(
+    planner: &DeletionPlanner,
+    planned: &[PlannedStatement],
+    run_id: &str,
+    seeds: &[String],
+    uri_type: &str,
+    path: Option<&Path>,
+    metrics: &mut RunMetrics,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let timer = metrics.start_phase("report", &planner.client().stats);
+    let mut counts = HashMap::new();
+    let mut graphs = HashMap::new();
+    for statement in planned {
+        planner
+            .count_predicates(&statement.subjects, &mut counts, &mut graphs)
+            .await?;
+    }
+    metrics.finish_phase(timer, &planner.client().stats);
+
+    let mut report = Report {
+        run_id: run_id.to_string(),
+        seeds: seeds.to_vec(),
+        seed_type: uri_type.to_string(),
+        statements: planned.len(),
+        subjects: planned
+            .iter()
+            .flat_map(|statement| statement.subjects.iter().cloned())
+            .collect(),
+        predicates: vec![],
+        graphs: vec![],
+    };
+    report.set_predicate_counts(counts);
+    report.set_graph_counts(graphs);
+    if let Some(path) = path {
+        report.save(path)?;
+    }
+    status!("{}", report.summary().trim_end());
+
+    Ok(())
+}
+
+/// Writes the triples every statement of `planned` deletes to `path` as N-Quads.
+async fn write_triples(
+    planner: &DeletionPlanner,
+    planned: &[PlannedStatement],
+    path: &Path,
+    metrics: &mut RunMetrics,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let timer = metrics.start_phase("triples", &planner.client().stats);
+    let mut out = BufWriter::new(File::create(path)?);
+    let mut written = 0;
+    for statement in planned {
+        for quad in planner.deleted_quads(&statement.subjects).await? {
+            writeln!(out, "{}", quad)?;
+            written += 1;
+        }
+    }
+    out.flush()?;
+    metrics.finish_phase(timer, &planner.client().stats);
+    status!("Wrote {} deleted triple(s) to {}", written, path.display());
+
+    Ok(())
+}
+
+/// Lists the edges skipped with --continue-on-error and fails if there are any, since the
+/// deletion is incomplete then.
+fn check_failures(planner: &DeletionPlanner) -> Result<(), ScratchpadError> {
+    let failures = planner.failures();
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    eprintln!(
+        "{} edge(s) failed, nothing behind them was deleted:",
+        failures.len()
+    );
+    for failure in &failures {
+        eprintln!("  {}", failure);
+    }
+    let resume = match &planner.options().state_file {
+        Some(_) => ", run again with the same --state-file to retry them",
+        None => "",
+    };
+
+    Err(ScratchpadError::Sparql(format!(
+        "The deletion is incomplete because {} edge(s) failed{}",
+        failures.len(),
+        resume
+    )))
+}
+
+/// Prints how the subjects of `planned` differ from the ones in the report at `path`.
+fn diff(planned: &[PlannedStatement], path: &Path) -> Result<(), ScratchpadError> {
+    let previous = Report::load(path)?;
+    let current = planned
+        .iter()
+        .flat_map(|statement| statement.subjects.iter().cloned())
+        .collect::<Vec<_>>();
+    let diff = SubjectDiff::new(&previous.subjects, &current);
+    status!("{}", diff.summary().trim_end());
+
+    Ok(())
+}
+
+/// Checks every subject with an ASK query and prints the ones that still have triples.
+async fn verify(
+    planner: &DeletionPlanner,
+    subjects: &[String],
+    metrics: &mut RunMetrics,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let timer = metrics.start_phase("verification", &planner.client().stats);
+    let remaining = planner.remaining_subjects(subjects).await?;
+    metrics.finish_phase(timer, &planner.client().stats);
+
+    status!(
+        "{} of {} subjects fully removed",
+        subjects.len() - remaining.len(),
+        subjects.len()
+    );
+    for subject in &remaining {
+        status!("  still has triples: {}", subject);
+    }
+
+    Ok(())
+}
+
+/// Where the statements are kept for the steps after the traversal, if anywhere.
+fn kept_sink<'a>(
+    queue: &'a mut Option<HttpSink>,
+    memory: &'a mut Option<MemorySink>,
+) -> Option<&'a mut dyn OutputSink> {
+    match (queue, memory) {
+        (Some(queue), _) => Some(queue),
+        (None, Some(memory)) => Some(memory),
+        (None, None) => None,
+    }
+}
+
+/// The statements kept so far, empty if no step after the traversal needs them.
+fn kept<'a>(queue: &'a Option<HttpSink>, memory: &'a Option<MemorySink>) -> &'a [PlannedStatement] {
+    match (queue, memory) {
+        (Some(queue), _) => queue.statements(),
+        (None, Some(memory)) => memory.statements(),
+        (None, None) => &[],
+    }
+}
+
+/// Writes statements generated after the traversal to the output and keeps them with the
+/// others.
+fn write_statements<'a>(
+    output: &'a mut dyn OutputSink,
+    kept: Option<&'a mut dyn OutputSink>,
+    statements: &[PlannedStatement],
+) -> std::io::Result<()> {
+    let mut target = Tee::new(output, kept);
+    for statement in statements {
+        target.write_statement(statement)?;
+    }
+
+    Ok(())
+}
+
+/// `generate`, and `execute` when `execution` is given. Returns why the traversal stopped
+/// early, if it did.
+async fn run_generate(
+    args: &GenerateArgs,
+    execution: Option<&ExecutionArgs>,
+    metrics: &mut RunMetrics,
+) -> Result<Option<EarlyStop>, Box<dyn std::error::Error>> {
+    // Refused before any request is sent, --head is shared with generate so clap cannot.
+    if execution.is_some() && args.output.head.is_some() {
+        return Err(ScratchpadError::Config(
+            "--head only previews the statements, use generate instead of execute".to_string(),
+        )
+        .into());
+    }
+    let update_endpoint = execution
+        .and_then(|execution| execution.update_endpoint.as_deref())
+        .unwrap_or(&args.connection.endpoint);
+    let deadlock = execution
+        .map(|execution| {
+            DeadlockRetry::new(
+                &execution.deadlock_pattern,
+                execution.deadlock_retries,
+                Duration::from_millis(execution.deadlock_backoff_ms),
+            )
+        })
+        .transpose()?;
+    let (planner, seeds, uri_type, run_id) = plan(
+        &args.connection,
+        &args.traversal,
+        Some(&args.output),
+        execution.map(|_| update_endpoint),
+        metrics,
+    )
+    .await?;
+
+    let keep = args.output.report.is_some()
+        || args.output.diff.is_some()
+        || args.output.drop_empty_graphs
+        || args.output.delete_reifications
+        || args.output.triples_out.is_some();
+    let mut f = output_sink(&args.output)?;
+    // Every statement is also kept for the steps after the traversal, `execute` queues
+    // them to be sent once everything is generated.
+    let mut queue = execution.map(|_| HttpSink::new(update_endpoint));
+    let mut memory = (queue.is_none() && keep).then(MemorySink::default);
+    let subjects_out = args
+        .output
+        .subjects_out
+        .as_deref()
+        .map(SubjectsOut::open)
+        .transpose()?;
+    let stopped = generate(
+        &planner,
+        &seeds,
+        &uri_type,
+        &mut Tee::new(f.as_mut(), kept_sink(&mut queue, &mut memory)),
+        subjects_out,
+        metrics,
+    )
+    .await?;
+    if stopped.is_some() {
+        return Ok(stopped);
+    }
+    if let Some(sample) = planner.options().sample {
+        f.write_note(&format!(
+            "# Sample: at most {} URI(s) of every lookup (--sample-seed {}), not a complete \
+             deletion\n",
+            sample,
+            planner.options().sample_seed
+        ))?;
+        status!(
+            "Only a sample of at most {} URI(s) per lookup was kept, the output is incomplete",
+            sample
+        );
+    }
+    if let Some(head) = planner.options().head.filter(|&head| f.written() >= head) {
+        f.write_note(&format!(
+            "# Preview: only the first {} statement(s), run without --head for all of them\n",
+            head
+        ))?;
+        f.finish()?;
+        status!(
+            "Preview of the first {} statement(s) written to {}, the output is incomplete",
+            head,
+            f.location()
+        );
+        return Ok(None);
+    }
+    // Renamed seeds keep the triples pointing to them, like every other renamed subject.
+    if planner.options().rename.is_none() {
+        let statements = seeds
+            .iter()
+            .map(|seed| planner.incoming_statement(seed))
+            .collect::<Vec<_>>();
+        write_statements(f.as_mut(), kept_sink(&mut queue, &mut memory), &statements)?;
+    }
+    if args.output.delete_reifications {
+        let statements = delete_reifications(&planner, kept(&queue, &memory), metrics).await?;
+        write_statements(f.as_mut(), kept_sink(&mut queue, &mut memory), &statements)?;
+    }
+    if args.output.drop_empty_graphs {
+        let statements = drop_empty_graphs(&planner, kept(&queue, &memory), metrics).await?;
+        write_statements(f.as_mut(), kept_sink(&mut queue, &mut memory), &statements)?;
+    }
+    f.finish()?;
+    metrics.statements = f.written() as u64;
+    if let Some(path) = f.manifest_path() {
+        let manifest = Manifest::new(
+            &run_id,
+            &args.connection.endpoint,
+            &seeds,
+            &uri_type,
+            f.written(),
+            f.files(),
+        )?;
+        manifest.save(&path)?;
+    }
+    let planned = kept(&queue, &memory);
+    // The report has to be made before executing, afterwards there is nothing left to count.
+    if let Some(path) = &args.output.report {
+        report(
+            &planner,
+            planned,
+            &run_id,
+            &seeds,
+            &uri_type,
+            Some(path),
+            metrics,
+        )
+        .await?;
+    }
+    if let Some(path) = &args.output.triples_out {
+        write_triples(&planner, planned, path, metrics).await?;
+    }
+    if let Some(path) = &args.output.diff {
+        diff(planned, path)?;
+    }
+    check_failures(&planner)?;
+
+    let (Some(execution), Some(deadlock), Some(queue)) = (execution, deadlock, &queue) else {
+        return Ok(None);
+    };
+    if !confirm_execution(queue.written(), queue.endpoint(), execution.yes)? {
+        return Ok(None);
+    }
+    let timer = metrics.start_phase("execution", &planner.client().stats);
+    execute_statements(
+        planner.client(),
+        queue,
+        execution.exec_batch_size.max(1),
+        &deadlock,
+    )
+    .await?;
+    metrics.finish_phase(timer, &planner.client().stats);
+    status!(
+        "Executed {} statement(s) against {}",
+        queue.written(),
+        queue.location()
+    );
+
+    if execution.verify_after {
+        let subjects = queue
+            .statements()
+            .iter()
+            .flat_map(|statement| statement.subjects.iter().cloned())
+            .collect::<Vec<_>>();
+        verify(&planner, &subjects, metrics).await?;
+    }
+
+    Ok(None)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = profile::parse_args()?;
+    let metrics_out = cli.metrics_out;
+    let command = cli.command.unwrap_or(Command::Generate(cli.generate));
+    let subjects_out = match &command {
+        Command::Generate(args) => args.output.subjects_out.as_deref(),
+        Command::Execute(args) => args.generate.output.subjects_out.as_deref(),
+        Command::Count(args) => args.subjects_out.as_deref(),
+        Command::GenerateConfig(args) if args.out.is_none() => Some(Path::new("-")),
+        Command::Verify(_)
+        | Command::Estimate(_)
+        | Command::ListTypes(_)
+        | Command::GenerateConfig(_) => None,
+    };
+    let traversal = match &command {
+        Command::Generate(args) => Some(&args.traversal),
+        Command::Execute(args) => Some(&args.generate.traversal),
+        Command::Count(args) => Some(&args.traversal),
+        Command::Estimate(args) | Command::ListTypes(args) => Some(&args.traversal),
+        Command::Verify(_) | Command::GenerateConfig(_) => None,
+    };
+    if let Some(traversal) = traversal.filter(|traversal| traversal.explain) {
+        let config = load_config(&traversal.statement)?;
+        let seed_type = traversal
+            .uri_type
+            .as_deref()
+            .map(|uri_type| config.expand_iri(uri_type))
+            .transpose()?;
+        print!(
+            "{}",
+            explain(&config, seed_type.as_deref(), only_direction(traversal))
+        );
+        return Ok(());
+    }
+    let stdout_output = match &command {
+        Command::Generate(args) => args.output.stdout,
+        Command::Execute(args) => args.generate.output.stdout,
+        _ => false,
+    };
+    if stdout_output || subjects_out == Some(Path::new("-")) {
+        STDOUT_IS_DATA.store(true, Ordering::Relaxed);
+    }
+    let mut metrics = RunMetrics::default();
+
+    let result = run(&command, &mut metrics).await;
+    if let Some(path) = &metrics_out {
+        std::fs::write(path, metrics.prometheus(matches!(result, Ok(None))))?;
+    }
+    if let Some(stop) = result? {
+        std::process::exit(stop.exit_code());
+    }
+    status!("{}", metrics.summary().trim_end());
+
+    Ok(())
+}
+
+/// Runs `command`, recording the phases it goes through in `metrics`. Returns why the
+/// traversal stopped early, if it did.
+async fn run(
+    command: &Command,
+    metrics: &mut RunMetrics,
+) -> Result<Option<EarlyStop>, Box<dyn std::error::Error>> {
+    match command {
+        Command::Generate(args) => return run_generate(args, None, metrics).await,
+        Command::Execute(args) => {
+            return run_generate(&args.generate, Some(&args.execution), metrics).await
+        }
+        Command::Count(args) => {
+            let (planner, seeds, uri_type, run_id) =
+                plan(&args.connection, &args.traversal, None, None, metrics).await?;
+            let subjects_out = args
+                .subjects_out
+                .as_deref()
+                .map(SubjectsOut::open)
+                .transpose()?;
+            let mut planned = MemorySink::default();
+            let stopped = generate(
+                &planner,
+                &seeds,
+                &uri_type,
+                &mut planned,
+                subjects_out,
+                metrics,
+            )
+            .await?;
+            if stopped.is_some() {
+                return Ok(stopped);
+            }
+            let planned = planned.statements();
+            report(
+                &planner,
+                planned,
+                &run_id,
+                &seeds,
+                &uri_type,
+                args.report.as_deref(),
+                metrics,
+            )
+            .await?;
+            if let Some(path) = &args.diff {
+                diff(planned, path)?;
+            }
+            check_failures(&planner)?;
+        }
+        Command::Verify(args) => {
+            let (client, _) = connect(&args.connection, None).await?;
+            let config = load_config(&args.statement)?;
+            let options = statement_options(&args.statement, &config)?;
+            let planner = DeletionPlanner::new(
+                HttpBackend::new(client, &args.connection.endpoint),
+                config,
+                options,
+            );
+            let report = Report::load(&args.report)?;
+            verify(&planner, &report.subjects, metrics).await?;
+        }
+        Command::GenerateConfig(args) => {
+            let (client, _) = connect(&args.connection, None).await?;
+            let uri_type = match args.uri_type.starts_with('<') {
+                true => args.uri_type.clone(),
+                false => format!("<{}>", args.uri_type),
+            };
+            let timer = metrics.start_phase("sampling", &client.stats);
+            let config = scaffold_config(
+                &client,
+                &args.connection.endpoint,
+                &uri_type,
+                args.sample_size,
+                args.depth,
+            )
+            .await?;
+            metrics.finish_phase(timer, &client.stats);
+
+            let json = serde_json::to_string_pretty(&config)?;
+            match &args.out {
+                Some(path) => {
+                    std::fs::write(path, format!("{}\n", json))?;
+                    status!("Wrote {} type(s) to {}", config.len(), path.display());
+                }
+                None => println!("{}", json),
+            }
+        }
+        Command::ListTypes(args) => {
+            let (planner, seeds, uri_type, _) =
+                plan(&args.connection, &args.traversal, None, None, metrics).await?;
+            let timer = metrics.start_phase("list types", &planner.client().stats);
+            let linked = planner.linked_types(&seeds, &uri_type).await?;
+            metrics.finish_phase(timer, &planner.client().stats);
+            status!("{}", linked.summary().trim_end());
+        }
+        Command::Estimate(args) => {
+            let (planner, seeds, uri_type, _) =
+                plan(&args.connection, &args.traversal, None, None, metrics).await?;
+            let timer = metrics.start_phase("estimate", &planner.client().stats);
+            let estimate = planner.estimate(&seeds, &uri_type).await?;
+            metrics.finish_phase(timer, &planner.client().stats);
+            status!("{}", estimate.summary().trim_end());
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{Engine, DEFAULT_DEADLOCK_PATTERN};
+    use crate::output::{LineEnding, OutputFormat};
+    use crate::test_server::{json_response, response, TestServer};
+
+    const ONE_URI: &str = r#"{"head":{"vars":["s"]},"results":{"bindings":[
+        {"s":{"type":"uri","value":"http://x/a"}}]}}"#;
+
+    #[tokio::test]
+    async fn gzipped_results_parse_like_plain_ones() {
+        let mut gzipped = flate2::write::GzEncoder::new(vec![], flate2::Compression::best());
+        gzipped.write_all(ONE_URI.as_bytes()).unwrap();
+        let server = TestServer::start(vec![
+            json_response(ONE_URI),
+            response(
+                "200 OK",
+                &[
+                    ("Content-Type", SPARQL_RESULTS_JSON),
+                    ("Content-Encoding", "gzip"),
+                ],
+                &gzipped.finish().unwrap(),
+            ),
+        ]);
+        let client = SparqlClient::new(reqwest::Client::new());
+
+        let query = "SELECT ?s WHERE { ?s ?p ?o }";
+        let plain = fetch_sparql_results(&client, &server.endpoint, query).await;
+        let decompressed = fetch_sparql_results(&client, &server.endpoint, query).await;
+
+        assert_eq!(plain.unwrap(), decompressed.unwrap());
+        for request in server.requests() {
+            assert!(request
+                .to_lowercase()
+                .contains("\r\naccept-encoding: gzip, deflate\r\n"));
+        }
+    }
+
+    #[test]
+    fn empty_results_are_told_apart_from_unexpected_shapes() {
+        let empty = serde_json::json!({ "head": { "vars": ["s"] }, "results": { "bindings": [] } });
+        let ask = serde_json::json!({ "head": {}, "boolean": true });
+        let error = serde_json::json!({ "error": "Virtuoso 37000 Error SP030" });
+
+        assert!(parse_json_uris(&empty, "s").unwrap().is_empty());
+        let Err(ScratchpadError::Sparql(msg)) = parse_json_uris(&ask, "s") else {
+            panic!("an ASK answer has no bindings to read");
+        };
+        assert_eq!(msg, "Expected SELECT results binding ?s, got an ASK result");
+        let Err(ScratchpadError::Sparql(msg)) = parse_json_uris(&error, "s") else {
+            panic!("an error object is not an empty result");
+        };
+        assert!(msg.contains("Virtuoso 37000 Error SP030"));
+        assert!(parse_json_uris(&Value::Null, "s").is_err());
+    }
+
+    #[test]
+    fn only_iris_bound_to_the_target_are_read() {
+        let results: Value = serde_json::from_str(
+            r#"{"head":{"vars":["s"]},"results":{"bindings":[
+                {"s":{"type":"uri","value":"http://x/a"}},
+                {"s":{"type":"literal","value":"http://x/b"}},
+                {"s":{"type":"bnode","value":"b0"}},
+                {"o":{"type":"uri","value":"http://x/c"}}]}}"#,
+        )
+        .unwrap();
+
+        let uris = parse_json_uris(&results, "s").unwrap();
+
+        assert_eq!(uris.len(), 1);
+        assert_eq!(uris[0].uri("s"), Some("http://x/a"));
+    }
+
+    fn types_response(types: &[&str]) -> Vec<u8> {
+        let bindings = types
+            .iter()
+            .map(|t| format!(r#"{{"t":{{"type":"uri","value":"{}"}}}}"#, t))
+            .collect::<Vec<_>>();
+        json_response(&format!(
+            r#"{{"head":{{"vars":["t"]}},"results":{{"bindings":[{}]}}}}"#,
+            bindings.join(",")
+        ))
+    }
+
+    #[tokio::test]
+    async fn seed_type_is_looked_up_when_not_given() {
+        let server = TestServer::start(vec![types_response(&["http://t/Org"])]);
+        let client = SparqlClient::new(reqwest::Client::new());
+
+        let seed_type = fetch_seed_type(&client, &server.endpoint, "<http://x/org>").await;
+
+        assert_eq!(seed_type.unwrap(), "<http://t/Org>");
+        let request = server.requests().remove(0);
+        assert!(request.contains("%3Chttp%3A%2F%2Fx%2Forg%3E"));
+    }
+
+    #[tokio::test]
+    async fn ambiguous_or_missing_seed_type_asks_for_type() {
+        let server = TestServer::start(vec![
+            types_response(&["http://t/Org", "http://t/Agent"]),
+            types_response(&[]),
+        ]);
+        let client = SparqlClient::new(reqwest::Client::new());
+
+        let ambiguous = fetch_seed_type(&client, &server.endpoint, "<http://x/a>").await;
+        let missing = fetch_seed_type(&client, &server.endpoint, "<http://x/b>").await;
+
+        let Err(ScratchpadError::Config(ambiguous)) = ambiguous else {
+            panic!("two types must not be picked from");
+        };
+        assert!(ambiguous.contains("<http://t/Org>, <http://t/Agent>"));
+        assert!(ambiguous.contains("--type"));
+        assert!(matches!(missing, Err(ScratchpadError::Config(msg)) if msg.contains("--type")));
+    }
+
+    #[test]
+    fn seeds_piped_in_skip_blank_lines_and_comments() {
+        let stdin = "# exported from the admin panel\r\n\
+                     <http://data.lblod.info/id/bestuurseenheden/1>\r\n\
+                     \r\n\
+                     http://data.lblod.info/id/bestuurseenheden/2\n   \n\
+                     \t<bestuurseenheden/3>  \n\
+                     #<http://data.lblod.info/id/bestuurseenheden/4>\n";
+
+        let seeds = read_seeds(stdin.as_bytes(), Some("http://data.lblod.info/id/")).unwrap();
+
+        assert_eq!(
+            seeds,
+            [
+                "<http://data.lblod.info/id/bestuurseenheden/1>",
+                "<http://data.lblod.info/id/bestuurseenheden/2>",
+                "<http://data.lblod.info/id/bestuurseenheden/3>"
+            ]
+        );
+        assert!(matches!(
+            read_seeds("# nothing yet\n\n".as_bytes(), None),
+            Err(ScratchpadError::Config(_))
+        ));
+        let parse = |extra: &[&str]| {
+            let args = [
+                "delete-organization",
+                "--seed-from-stdin",
+                "--type",
+                "<http://t/Org>",
+            ];
+            <cli::Cli as clap::Parser>::try_parse_from(args.iter().chain(extra))
+        };
+        assert!(parse(&[]).is_ok());
+        assert!(parse(&["--uri", "<http://x/org>"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn types_of_a_uri_are_queried_once_per_run() {
+        let server = TestServer::start(vec![
+            types_response(&["http://t/Bestuurseenheid"]),
+            types_response(&["http://t/Gemeente", "http://t/Bestuurseenheid"]),
+        ]);
+        let client = SparqlClient::new(reqwest::Client::new());
+        let mut metrics = RunMetrics::default();
+
+        let timer = metrics.start_phase("seed types", &client.stats);
+        for _ in 0..3 {
+            let seed_type = fetch_seed_type(&client, &server.endpoint, "<http://x/bestuur/1>");
+            assert_eq!(seed_type.await.unwrap(), "<http://t/Bestuurseenheid>");
+        }
+        let gemeente = fetch_types(&client, &server.endpoint, "<http://x/gemeente/1>").await;
+        let again = fetch_types(&client, &server.endpoint, "<http://x/gemeente/1>").await;
+        metrics.finish_phase(timer, &client.stats);
+
+        assert_eq!(gemeente.unwrap(), again.unwrap());
+        assert_eq!(server.requests().len(), 2);
+        assert_eq!((metrics.type_cache_hits, metrics.type_cache_misses), (3, 2));
+        assert!(metrics
+            .summary()
+            .ends_with("  type cache: 3 hit(s), 2 miss(es), 60% hit rate\n"));
+    }
+
+    #[tokio::test]
+    async fn seed_without_triples_stops_the_run() {
+        let server = TestServer::start(vec![
+            json_response(r#"{"head":{},"boolean":false}"#),
+            json_response(r#"{"head":{},"boolean":true}"#),
+        ]);
+        let client = SparqlClient::new(reqwest::Client::new());
+
+        let missing = check_seed_exists(&client, &server.endpoint, "<http://x/gone>").await;
+        let present = check_seed_exists(&client, &server.endpoint, "<http://x/org>").await;
+
+        let Err(ScratchpadError::Config(msg)) = missing else {
+            panic!("a seed without triples must not be traversed");
+        };
+        assert!(msg.starts_with("<http://x/gone> has no triples in http://127.0.0.1:"));
+        assert!(msg.contains("--no-precheck"));
+        assert!(present.is_ok());
+        let request = server.requests().remove(0);
+        assert!(request.contains("UNION"));
+    }
+
+    #[tokio::test]
+    async fn seed_query_results_become_the_seeds() {
+        let organizations = r#"{"head":{"vars":["s","y"]},"results":{"bindings":[
+            {"s":{"type":"uri","value":"http://x/org/1"},"y":{"type":"literal","value":"2019"}},
+            {"s":{"type":"uri","value":"http://x/org/2"},"y":{"type":"literal","value":"2018"}},
+            {"s":{"type":"uri","value":"http://x/org/3"},"y":{"type":"literal","value":"2017"}}
+        ]}}"#;
+        let server = TestServer::start(vec![
+            json_response(organizations),
+            json_response(r#"{"head":{"vars":["s"]},"results":{"bindings":[]}}"#),
+        ]);
+        let client = SparqlClient::new(reqwest::Client::new());
+        let query = "SELECT ?s ?y WHERE { ?s <http://purl.org/dc/terms/created> ?y }";
+
+        let seeds = fetch_seeds(&client, &server.endpoint, query, None).await;
+        let none = fetch_seeds(&client, &server.endpoint, query, None).await;
+
+        assert_eq!(
+            seeds.unwrap(),
+            ["<http://x/org/1>", "<http://x/org/2>", "<http://x/org/3>"]
+        );
+        assert!(
+            matches!(none, Err(ScratchpadError::Config(msg)) if msg.contains("nothing to delete"))
+        );
+    }
+
+    #[test]
+    fn seed_queries_refuse_an_explicit_uri() {
+        let parse = |extra: &[&str]| {
+            let args = [
+                "delete-organization",
+                "--seed-query",
+                "oldest-councils.rq",
+                "--type",
+                "besluit:Bestuursorgaan",
+            ];
+            <cli::Cli as clap::Parser>::try_parse_from(args.iter().chain(extra))
+        };
+
+        assert!(parse(&[]).is_ok());
+        let Err(error) = parse(&["--uri", "<http://x/council/9>"]) else {
+            panic!("--uri would be ignored next to --seed-query");
+        };
+        assert_eq!(error.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[tokio::test]
+    async fn execute_refuses_head_before_sending_anything() {
+        let server = TestServer::start(vec![]);
+        let args = [
+            "delete-organization",
+            "execute",
+            "--head",
+            "5",
+            "--endpoint",
+            &server.endpoint,
+            "--uri",
+            "<http://x/org/12>",
+            "--type",
+            "<http://t/Org>",
+        ];
+        let cli = <cli::Cli as clap::Parser>::try_parse_from(args).unwrap();
+        let Some(Command::Execute(execute)) = &cli.command else {
+            panic!("execute is a subcommand");
+        };
+
+        let result = run_generate(
+            &execute.generate,
+            Some(&execute.execution),
+            &mut RunMetrics::default(),
+        )
+        .await;
+
+        let Err(error) = result else {
+            panic!("a preview must not be executed");
+        };
+        assert!(error
+            .to_string()
+            .contains("--head only previews the statements"));
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn responses_over_the_size_limit_are_refused() {
+        // Without a Content-Length, the limit is only noticed while reading.
+        let chunked = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nTransfer-Encoding: chunked\r\n\
+             Connection: close\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+            SPARQL_RESULTS_JSON,
+            ONE_URI.len(),
+            ONE_URI
+        );
+        let server = TestServer::start(vec![
+            json_response(ONE_URI),
+            chunked.into_bytes(),
+            json_response(ONE_URI),
+        ]);
+        let mut client = SparqlClient::new(reqwest::Client::new());
+        client.max_response_bytes = Some(ONE_URI.len() - 1);
+
+        let query = "SELECT ?s WHERE { ?s ?p ?o }";
+        let announced = fetch_sparql_results(&client, &server.endpoint, query).await;
+        let streamed = fetch_sparql_results(&client, &server.endpoint, query).await;
+        client.max_response_bytes = Some(ONE_URI.len());
+        let within = fetch_sparql_results(&client, &server.endpoint, query).await;
+
+        assert!(matches!(announced, Err(ScratchpadError::Limit(msg)) if msg.contains("exceeds")));
+        assert!(matches!(streamed, Err(ScratchpadError::Limit(msg)) if msg.contains("exceeds")));
+        assert!(within.is_ok());
+    }
+
+    #[test]
+    fn every_builder_reports_the_variable_it_selects() {
+        let allowed = ["<http://t/Site>".to_string()];
+        let queries = [
+            build_select(
+                Direction::Forward,
+                "<http://x/a>",
+                None,
+                None,
+                None,
+                None,
+                None,
+            ),
+            build_select(
+                Direction::Reverse,
+                "<http://x/a>",
+                Some("<http://t/Site>"),
+                Some(&allowed),
+                Some("<http://remote/sparql>"),
+                Some("<http://x/p>+"),
+                None,
+            ),
+            build_linked_types_query(Direction::Reverse, "<http://x/a>"),
+            build_linked_types_query(Direction::Forward, "<http://x/a>"),
+            create_type_select_query("<http://x/a>"),
+            create_instances_query("<http://t/Site>", 5),
+        ];
+
+        let selected = queries
+            .iter()
+            .map(|query| {
+                validate::validate_query(&query.text).unwrap();
+                let (_, projection) = query.text.split_once("SELECT").unwrap();
+                let first = projection
+                    .split_whitespace()
+                    .find(|token| *token != "DISTINCT")
+                    .unwrap();
+                (first.to_string(), format!("?{}", query.result_var))
+            })
+            .collect::<Vec<_>>();
+
+        for (first, result_var) in &selected {
+            assert_eq!(first, result_var);
+        }
+        assert_eq!(
+            selected
+                .iter()
+                .map(|(_, var)| var.as_str())
+                .collect::<Vec<_>>(),
+            ["?o", "?s", "?t", "?t", "?t", "?s"]
+        );
+    }
+
+    #[test]
+    fn typed_lookup_wrappers_generate_the_queries_they_always_did() {
+        let forward = create_forward_parametrized_select_query_with_type(
+            "<http://x/a>",
+            "<http://t/T>",
+            None,
+            None,
+        );
+        let backward = create_backward_parametrized_select_query_with_type(
+            "<http://x/a>",
+            "<http://t/T>",
+            None,
+            None,
+        );
+
+        assert_eq!(forward.result_var, "o");
+        assert_eq!(
+            forward.text,
+            concat!(
+                "\n    SELECT DISTINCT ?o WHERE {\n",
+                "      VALUES ?values {\n        <http://x/a>\n      }\n\n",
+                "      ?values ?p ?o .\n      ?o a <http://t/T> .\n",
+                "    }\n  "
+            )
+        );
+        assert_eq!(backward.result_var, "s");
+        assert_eq!(
+            backward.text,
+            concat!(
+                "\n    SELECT DISTINCT ?s WHERE {\n",
+                "      VALUES ?values {\n        <http://x/a>\n      }\n\n",
+                "      ?s a <http://t/T> ;\n        ?p ?values .\n",
+                "    }\n  "
+            )
+        );
+    }
+
+    #[test]
+    fn typed_lookup_wrappers_pass_service_and_cutoff_on() {
+        let since =
+            TemporalFilter::new("<http://purl.org/dc/terms/created>", "2024-01-01").unwrap();
+        let service = Some("<http://remote/sparql>");
+
+        let forward = create_forward_parametrized_select_query_with_type(
+            "<http://x/a>",
+            "<http://t/T>",
+            service,
+            Some(&since),
+        );
+        let backward = create_backward_parametrized_select_query_with_type(
+            "<http://x/a>",
+            "<http://t/T>",
+            service,
+            Some(&since),
+        );
+
+        for (query, var) in [(forward, "?o"), (backward, "?s")] {
+            validate::validate_query(&query.text).unwrap();
+            assert!(query.text.contains("SERVICE <http://remote/sparql> {"));
+            assert!(query.text.contains(&format!(
+                "{} <http://purl.org/dc/terms/created> ?created_at .",
+                var
+            )));
+        }
+    }
+
+    #[test]
+    fn lookups_only_filter_by_creation_time_when_asked() {
+        let since =
+            TemporalFilter::new("<http://purl.org/dc/terms/created>", "2024-01-01").unwrap();
+        let select = |since| {
+            build_select(
+                Direction::Forward,
+                "<http://x/site>",
+                Some("<http://t/Addr>"),
+                None,
+                None,
+                None,
+                since,
+            )
+            .text
+        };
+
+        let filtered = select(Some(&since));
+        assert!(filtered.contains("?o <http://purl.org/dc/terms/created> ?created_at ."));
+        assert!(filtered.contains("FILTER(?created_at > \"2024-01-01T00:00:00Z\""));
+        assert!(!select(None).contains("created_at"));
+    }
+
+    #[test]
+    fn lookups_over_a_service_edge_are_federated() {
+        let select = |service| {
+            build_select(
+                Direction::Forward,
+                "<http://x/site>",
+                Some("<http://t/Addr>"),
+                None,
+                service,
+                None,
+                None,
+            )
+            .text
+        };
+
+        let federated = select(Some("<http://remote.example/sparql>"));
+        let local = select(None);
+
+        validate::validate_query(&federated).unwrap();
+        let (_, remote) = federated
+            .split_once("SERVICE <http://remote.example/sparql> {")
+            .expect("the edge pattern is evaluated remotely");
+        assert!(remote.contains("VALUES ?values {"));
+        assert!(remote.contains("?o a <http://t/Addr> ."));
+        assert!(!local.contains("SERVICE"));
+    }
+
+    #[test]
+    fn predicate_values_only_appear_when_predicates_are_given() {
+        let vars = QueryVars::default();
+        let predicates = [
+            "<http://xmlns.com/foaf/0.1/name>".to_string(),
+            "<http://xmlns.com/foaf/0.1/mbox>".to_string(),
+        ];
+
+        let restricted =
+            build_parametrized_delete_query("    <http://x/a>", &predicates, None, &vars);
+        let everything = build_parametrized_delete_query("    <http://x/a>", &[], None, &vars);
+
+        validate::validate_update(&restricted).unwrap();
+        assert!(restricted.contains(
+            "VALUES ?p { <http://xmlns.com/foaf/0.1/name> <http://xmlns.com/foaf/0.1/mbox> }"
+        ));
+        assert!(!everything.contains("VALUES ?p"));
+        assert!(
+            build_ask_query("    <http://x/a>", &predicates, None, &vars).contains("VALUES ?p {")
+        );
+    }
+
+    /// Keeps the messages logged for the queries and updates sent.
+    struct QueryLog(std::sync::Mutex<Vec<String>>);
+
+    impl log::Log for QueryLog {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.target() == QUERY_LOG_TARGET && metadata.level() == log::Level::Debug
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static QUERY_LOG: QueryLog = QueryLog(std::sync::Mutex::new(vec![]));
+
+    #[tokio::test]
+    async fn queries_and_updates_are_logged_before_they_are_sent() {
+        log::set_logger(&QUERY_LOG).unwrap();
+        log::set_max_level(LevelFilter::Debug);
+        let server = TestServer::start(vec![json_response(ONE_URI), response("200 OK", &[], b"")]);
+        let client = SparqlClient::new(reqwest::Client::new());
+        let deadlock = DeadlockRetry::new("SR172", 0, Duration::ZERO).unwrap();
+
+        fetch_sparql_results(&client, &server.endpoint, "SELECT ?s WHERE { ?s ?p ?o }")
+            .await
+            .unwrap();
+        execute_update(
+            &client,
+            &server.endpoint,
+            "CLEAR GRAPH <http://g>",
+            &deadlock,
+        )
+        .await
+        .unwrap();
+
+        // Other tests send requests too, so only look at the ones to this server.
+        let logged = QUERY_LOG
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|message| message.contains(&server.endpoint))
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            logged,
+            [
+                format!(
+                    "Query to {}:\nSELECT ?s WHERE {{ ?s ?p ?o }}",
+                    server.endpoint
+                ),
+                format!("Update to {}:\nCLEAR GRAPH <http://g>", server.endpoint),
+            ]
+        );
+    }
+
+    #[test]
+    fn default_graph_mode_drops_the_graph_blocks() {
+        let quads = QueryVars::default();
+        let triples = QueryVars {
+            g: None,
+            ..QueryVars::default()
+        };
+        let values = "    <http://x/a>\n    <http://x/b>";
+        let subjects = ["<http://x/a>".to_string()];
+
+        let named = build_parametrized_delete_query(values, &[], None, &quads);
+        let default = build_parametrized_delete_query(values, &[], None, &triples);
+
+        assert_eq!(
+            named,
+            concat!(
+                "DELETE {\n  GRAPH ?g {\n    ?s ?p ?o .\n  }\n}\nWHERE {\n",
+                "  VALUES ?s {\n    <http://x/a>\n    <http://x/b>\n  }\n\n",
+                "  GRAPH ?g {\n    ?s ?p ?o .\n  }\n}"
+            )
+        );
+        assert_eq!(
+            default,
+            concat!(
+                "DELETE {\n  ?s ?p ?o .\n}\nWHERE {\n",
+                "  VALUES ?s {\n    <http://x/a>\n    <http://x/b>\n  }\n\n",
+                "  ?s ?p ?o .\n}"
+            )
+        );
+        for update in [
+            default,
+            build_delete_where_query(&subjects, &triples),
+            create_simple_reverse_parametrized_delete_query("<http://x/a>", &triples),
+        ] {
+            validate::validate_update(&update).unwrap();
+            assert!(!update.contains("GRAPH"));
+        }
+    }
+
+    #[test]
+    fn a_single_graph_is_named_once_with_with() {
+        let every_graph = QueryVars::default();
+        let one_graph = QueryVars {
+            g: None,
+            graph: Some("<http://mu.semte.ch/graphs/public>".to_string()),
+            ..QueryVars::default()
+        };
+        let values = "    <http://x/unit/7>\n    <http://x/unit/8>";
+
+        let matched = build_parametrized_delete_query(values, &[], None, &every_graph);
+        let scoped = build_parametrized_delete_query(values, &[], None, &one_graph);
+
+        assert!(matched.starts_with("DELETE {\n  GRAPH ?g {"));
+        assert!(!matched.contains("WITH"));
+        assert_eq!(
+            scoped,
+            concat!(
+                "WITH <http://mu.semte.ch/graphs/public>\n",
+                "DELETE {\n  ?s ?p ?o .\n}\nWHERE {\n",
+                "  VALUES ?s {\n    <http://x/unit/7>\n    <http://x/unit/8>\n  }\n\n",
+                "  ?s ?p ?o .\n}"
+            )
+        );
+        let reverse =
+            create_simple_reverse_parametrized_delete_query("<http://x/unit/7>", &one_graph);
+        for update in [scoped, reverse] {
+            validate::validate_update(&update).unwrap();
+            assert!(update.starts_with("WITH <http://mu.semte.ch/graphs/public>\nDELETE"));
+            assert!(!update.contains("GRAPH"));
+        }
+    }
+
+    #[test]
+    fn strict_config_only_fails_on_edges_listed_twice() {
+        let self_reference: JsonConfig =
+            serde_json::from_str(r#"{ "<http://t/Unit>": { "reverse": ["<http://t/Unit>"] } }"#)
+                .unwrap();
+        let duplicate: JsonConfig = serde_json::from_str(
+            r#"{ "<http://t/Unit>": { "forward": ["<http://t/Seat>", "<http://t/Seat>"] } }"#,
+        )
+        .unwrap();
+
+        assert!(check_config_edges(&self_reference, true).is_ok());
+        assert!(check_config_edges(&duplicate, false).is_ok());
+        let Err(ScratchpadError::Config(msg)) = check_config_edges(&duplicate, true) else {
+            panic!("a duplicate edge must fail under --strict-config");
+        };
+        assert_eq!(msg, "The config lists 1 edge(s) more than once");
+    }
+
+    #[test]
+    fn output_templates_need_the_statement_placeholder() {
+        let config: JsonConfig = serde_json::from_str("{}").unwrap();
+        let options = |template: &str| {
+            let args = [
+                "delete-organization",
+                "--output-template",
+                template,
+                "--uri",
+                "<http://x/org>",
+                "--type",
+                "<http://t/Org>",
+            ];
+            let cli = <cli::Cli as clap::Parser>::try_parse_from(args).unwrap();
+            traversal_options(&cli.generate.traversal, Some(&cli.generate.output), &config)
+        };
+
+        let wrapped = options("LOAD <urn:pre>;\\n{{statement}}").unwrap();
+        assert_eq!(
+            wrapped.output_template.as_deref(),
+            Some("LOAD <urn:pre>;\n{{statement}}")
+        );
+        let Err(ScratchpadError::Config(msg)) = options("LOAD <urn:pre>;\\n{statement}") else {
+            panic!("a template without the placeholder must be refused");
+        };
+        assert_eq!(
+            msg,
+            "--output-template must contain the {{statement}} placeholder"
+        );
+    }
+
+    #[tokio::test]
+    async fn every_query_is_counted_and_timed() {
+        let server = TestServer::start(vec![json_response(ONE_URI), json_response(ONE_URI)]);
+        let client = SparqlClient::new(reqwest::Client::new());
+        let mut metrics = RunMetrics::default();
+
+        let timer = metrics.start_phase("precheck", &client.stats);
+        for _ in 0..2 {
+            fetch_sparql_results(&client, &server.endpoint, "ASK {}")
+                .await
+                .unwrap();
+        }
+        metrics.finish_phase(timer, &client.stats);
+
+        assert_eq!(metrics.phases[0].requests, 2);
+        assert!(metrics.phases[0].request_time > Duration::ZERO);
+        assert_eq!(client.stats.response_bytes(), 2 * ONE_URI.len() as u64);
+    }
+
+    #[test]
+    fn split_endpoints_need_to_be_allowed() {
+        let same = [
+            ("reverse lookups", "http://a/sparql"),
+            ("forward lookups", "http://a/sparql"),
+        ];
+        let split = [
+            ("reverse lookups", "http://a/sparql"),
+            ("forward lookups", "http://b/sparql"),
+        ];
+
+        assert!(check_endpoints(&same, false).is_ok());
+        assert!(check_endpoints(&[], false).is_ok());
+        assert!(matches!(
+            check_endpoints(&split, false),
+            Err(ScratchpadError::Config(_))
+        ));
+        assert!(check_endpoints(&split, true).is_ok());
+    }
+
+    #[test]
+    fn output_of_an_interrupted_run_is_valid_sparql() {
+        let dir = std::env::temp_dir().join(format!("interrupted-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, format) in [
+            ("pretty", OutputFormat::Pretty),
+            ("minified", OutputFormat::Minified),
+        ] {
+            let options = PlannerOptions {
+                format,
+                statement_headers: true,
+                ..PlannerOptions::default()
+            };
+            let backend = HttpBackend::new(SparqlClient::new(reqwest::Client::new()), "unused");
+            let config = serde_json::from_str("{}").unwrap();
+            let planner = DeletionPlanner::new(backend, config, options);
+            let path = dir.join(format!("{}.txt", name));
+            let mut output =
+                OutputTarget::combined(path.clone(), false, LineEnding::Lf, None).unwrap();
+
+            for uri in ["<http://x/a>", "<http://x/b>"] {
+                output
+                    .write_statement(&planner.incoming_statement(uri))
+                    .unwrap();
+            }
+            stop_early(&planner, &mut output, 2, "Interrupted").unwrap();
+
+            let written = std::fs::read_to_string(&path).unwrap();
+            assert_eq!(written.matches("# Triples pointing to").count(), 2);
+            validate::validate_update(&written).unwrap();
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn an_early_stop_is_returned_instead_of_exiting() {
+        let server = TestServer::slow(vec![json_response(ONE_URI)], Duration::from_secs(5));
+        let backend = HttpBackend::new(SparqlClient::new(reqwest::Client::new()), &server.endpoint);
+        let config = serde_json::from_str(
+            r#"{ "<http://t/Unit>": { "reverse": [], "forward": ["<http://t/Seat>"] } }"#,
+        )
+        .unwrap();
+        let options = PlannerOptions {
+            max_runtime: Some(Duration::from_millis(50)),
+            ..PlannerOptions::default()
+        };
+        let planner = DeletionPlanner::new(backend, config, options);
+        let mut metrics = RunMetrics::default();
+        let seeds = ["<http://x/unit/3>".to_string()];
+
+        let stopped = generate(
+            &planner,
+            &seeds,
+            "<http://t/Unit>",
+            &mut MemorySink::default(),
+            None,
+            &mut metrics,
+        )
+        .await
+        .unwrap();
+
+        // Exiting right away would skip writing --metrics-out.
+        assert_eq!(stopped, Some(EarlyStop::OutOfTime));
+    }
+
+    #[tokio::test]
+    async fn out_of_runtime_stops_cleanly_after_the_complete_statements() {
+        let empty = json_response(r#"{"head":{"vars":["s"]},"results":{"bindings":[]}}"#);
+        let server = TestServer::slow(vec![empty], Duration::from_secs(5));
+        let backend = HttpBackend::new(SparqlClient::new(reqwest::Client::new()), &server.endpoint);
+        let config = serde_json::from_str(
+            r#"{ "<http://t/Org>": { "reverse": ["<http://t/Site>"], "forward": [] } }"#,
+        )
+        .unwrap();
+        let options = PlannerOptions {
+            max_runtime: Some(Duration::from_millis(100)),
+            ..PlannerOptions::default()
+        };
+        let planner = DeletionPlanner::new(backend, config, options);
+        let mut target = MemorySink::default();
+
+        let started = Instant::now();
+        let seeds = ["<http://x/org>".to_string(), "<http://x/org2>".to_string()];
+        let (written, stopped) =
+            run_traversal(&planner, &seeds, "<http://t/Org>", &mut target, None)
+                .await
+                .unwrap();
+
+        assert_eq!(stopped, Some(EarlyStop::OutOfTime));
+        assert_eq!(stopped.unwrap().exit_code(), 124);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        // The seeds are deleted before the lookup of their sites that never came back.
+        assert_eq!(written, 1);
+        assert_eq!(
+            target.statements()[0].subjects,
+            ["<http://x/org2>", "<http://x/org>"]
+        );
+        validate::validate_update(&target.statements()[0].text).unwrap();
+    }
+
+    #[tokio::test]
+    async fn head_writes_exactly_the_first_statements() {
+        let rows = |var: &str, uri: &str| {
+            let binding = serde_json::json!({ var: { "type": "uri", "value": uri } });
+            let results = serde_json::json!({ "bindings": [binding] });
+            json_response(
+                &serde_json::json!({ "head": { "vars": [var] }, "results": results }).to_string(),
+            )
+        };
+        // Enough for three statements: the seed, its mandate and the mandate's person.
+        let server = TestServer::start(vec![
+            rows("s", "http://x/mandate/7"),
+            rows("o", "http://x/person/an"),
+        ]);
+        let backend = HttpBackend::new(SparqlClient::new(reqwest::Client::new()), &server.endpoint);
+        let config = serde_json::from_str(
+            r#"{
+                "<http://t/Council>": { "reverse": ["<http://t/Mandate>"], "forward": [] },
+                "<http://t/Mandate>": { "reverse": [], "forward": ["<http://t/Person>"] }
+            }"#,
+        )
+        .unwrap();
+        let options = PlannerOptions {
+            head: Some(2),
+            ..PlannerOptions::default()
+        };
+        let planner = DeletionPlanner::new(backend, config, options);
+        let mut target = MemorySink::default();
+
+        let seeds = ["<http://x/council>".to_string()];
+        let (written, stopped) =
+            run_traversal(&planner, &seeds, "<http://t/Council>", &mut target, None)
+                .await
+                .unwrap();
+
+        // A preview ends like a complete run, not like an interrupted one.
+        assert_eq!((written, stopped), (2, None));
+        let subjects = target
+            .statements()
+            .iter()
+            .map(|statement| statement.subjects.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            subjects,
+            [vec!["<http://x/council>"], vec!["<http://x/mandate/7>"]]
+        );
+    }
+
+    #[tokio::test]
+    async fn html_pages_from_a_proxy_are_reported_with_a_snippet() {
+        let page = format!(
+            "<!DOCTYPE html>\n<html>\n  <head><title>Gateway login</title></head>\n  \
+             <body>{}</body>\n</html>\n",
+            "Please sign in. ".repeat(30)
+        );
+        let server = TestServer::start(vec![response(
+            "200 OK",
+            &[("Content-Type", "text/html; charset=utf-8")],
+            page.as_bytes(),
+        )]);
+        let client = SparqlClient::new(reqwest::Client::new());
+
+        let result = fetch_sparql_results(&client, &server.endpoint, "ASK {}").await;
+
+        let Err(ScratchpadError::Sparql(msg)) = result else {
+            panic!("an HTML page must be a SPARQL error, not a JSON one");
+        };
+        assert!(msg.starts_with(&format!(
+            "{} did not return JSON (content-type text/html; charset=utf-8, ",
+            server.endpoint
+        )));
+        assert!(msg.contains(
+            "): <!DOCTYPE html> <html> <head><title>Gateway login</title></head> <body>Please"
+        ));
+        assert!(msg.ends_with("..."));
+        assert_eq!(
+            body_snippet("ÿ".repeat(250).as_bytes()).chars().count(),
+            203
+        );
+    }
+
+    #[tokio::test]
+    async fn iris_with_accents_make_the_round_trip_as_utf8() {
+        let server = TestServer::start(vec![json_response(
+            r#"{"head":{"vars":["s"]},"results":{"bindings":[
+                {"s":{"type":"uri","value":"http://data.example/vestiging/Liège-Café"}}
+            ]}}"#,
+        )]);
+        let client = SparqlClient::new(reqwest::Client::new());
+        let query = create_backward_parametrized_select_query_with_type(
+            "<http://data.example/gemeente/Élouges>",
+            "<http://t/Vestiging>",
+            None,
+            None,
+        );
+
+        let r = fetch_sparql_results(&client, &server.endpoint, &query.text)
+            .await
+            .unwrap();
+
+        let sites = parse_json_uris(&r, query.result_var).unwrap();
+        assert_eq!(
+            sites[0].uri(query.result_var),
+            Some("http://data.example/vestiging/Liège-Café")
+        );
+        let request = server.requests().remove(0);
+        let (headers, body) = request.split_once("\r\n\r\n").unwrap();
+        let content_types = headers
+            .lines()
+            .filter(|line| line.to_lowercase().starts_with("content-type:"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            content_types,
+            ["content-type: application/x-www-form-urlencoded; charset=utf-8"]
+        );
+        assert!(body.contains("%2Fgemeente%2F%C3%89louges%3E"));
+    }
+
+    #[tokio::test]
+    async fn rejected_batches_surface_as_query_too_large() {
+        let server = TestServer::start(vec![response("413 Payload Too Large", &[], b"")]);
+        let client = SparqlClient::new(reqwest::Client::new());
+
+        let refused = fetch_sparql_results(&client, &server.endpoint, "ASK {}").await;
+
+        assert!(matches!(refused, Err(ScratchpadError::QueryTooLarge(_))));
+    }
+
+    #[tokio::test]
+    async fn virtuoso_limits_go_along_with_every_query() {
+        let rows = |n: usize| {
+            let bindings = vec![r#"{"s":{"type":"uri","value":"http://x/s"}}"#; n].join(",");
+            json_response(&format!(
+                r#"{{"head":{{"vars":["s"]}},"results":{{"bindings":[{}]}}}}"#,
+                bindings
+            ))
+        };
+        let server = TestServer::start(vec![rows(2), rows(3)]);
+        let mut client = SparqlClient::new(reqwest::Client::new());
+        client.limits = Engine::Virtuoso
+            .limits(Some(Duration::from_secs(30)), Some(3))
+            .unwrap();
+        let select = create_backward_parametrized_select_query_with_type(
+            "<http://x/org>",
+            "<http://t/Site>",
+            None,
+            None,
+        );
+
+        let below = fetch_sparql_results(&client, &server.endpoint, &select.text).await;
+        let reached = fetch_sparql_results(&client, &server.endpoint, &select.text).await;
+
+        assert!(below.is_ok());
+        let Err(ScratchpadError::Limit(msg)) = reached else {
+            panic!("a result as large as --max-rows may be cut off");
+        };
+        assert!(msg.contains("returned 3 rows"));
+        let request = server.requests().remove(0);
+        assert!(request.contains("query=DEFINE+sql%3Amax-rows+3%0A%0A++++SELECT+DISTINCT"));
+        assert!(request.contains("timeout=30000"));
+    }
+
+    #[tokio::test]
+    async fn virtuoso_is_asked_for_json_in_a_format_field() {
+        let server = TestServer::start(vec![json_response(ONE_URI), json_response(ONE_URI)]);
+        let generic = SparqlClient::new(reqwest::Client::new());
+        let mut virtuoso = SparqlClient::new(reqwest::Client::new());
+        virtuoso.limits = Engine::Virtuoso.limits(None, None).unwrap();
+        let query = "SELECT ?s WHERE { ?s a <http://t/Bestuurseenheid> }";
+
+        for client in [&generic, &virtuoso] {
+            fetch_sparql_results(client, &server.endpoint, query)
+                .await
+                .unwrap();
+        }
+
+        let requests = server.requests();
+        let format = "format=application%2Fsparql-results%2Bjson";
+        assert!(!requests[0].contains(format));
+        assert!(requests[1].contains(format));
+        assert!(requests[1].contains("query=SELECT+%3Fs+WHERE"));
+    }
+
+    #[tokio::test]
+    async fn virtuoso_partial_results_after_its_timeout_are_an_error() {
+        let partial = response(
+            "200 OK",
+            &[
+                ("Content-Type", "application/sparql-results+json"),
+                ("X-SQL-State", "S1TAT"),
+            ],
+            br#"{"head":{"vars":["s"]},"results":{"bindings":[]}}"#,
+        );
+        let server = TestServer::start(vec![partial]);
+        let client = SparqlClient::new(reqwest::Client::new());
+
+        let result = fetch_sparql_results(&client, &server.endpoint, "SELECT * {}").await;
+
+        assert!(matches!(result, Err(ScratchpadError::Limit(_))));
+        server.requests();
+    }
+
+    #[tokio::test]
+    async fn health_check_reports_status_and_body_of_a_failing_endpoint() {
+        let page = format!("<html>{}</html>", "No such service. ".repeat(20));
+        let server = TestServer::start(vec![
+            response("404 Not Found", &[], page.as_bytes()),
+            json_response(r#"{"head":{},"boolean":true}"#),
+        ]);
+        let client = SparqlClient::new(reqwest::Client::new());
+        // Nothing listens on the port once the listener is gone.
+        let closed = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/sparql", listener.local_addr().unwrap())
+        };
+
+        let not_found = check_endpoint_health(&client, &server.endpoint, false).await;
+        let healthy = check_endpoint_health(&client, &server.endpoint, false).await;
+        let unreachable = check_endpoint_health(&client, &closed, false).await;
+
+        let Err(ScratchpadError::Config(msg)) = not_found else {
+            panic!("a 404 must fail the health check");
+        };
+        assert_eq!(
+            msg,
+            format!(
+                "{} answered the health check with 404 Not Found: {}...",
+                server.endpoint,
+                &page[..200]
+            )
+        );
+        assert!(healthy.is_ok());
+        let Err(ScratchpadError::Config(msg)) = unreachable else {
+            panic!("a closed port must fail the health check");
+        };
+        assert!(msg.starts_with(&format!("Cannot reach {}", closed)));
+    }
+
+    #[test]
+    fn execution_without_a_terminal_needs_yes() {
+        let endpoint = "http://db.internal:8890/sparql";
+        let never_read = "yes\n".as_bytes();
+
+        let refused = ask_confirmation(3, endpoint, false, false, never_read);
+        let forced = ask_confirmation(3, endpoint, true, false, never_read);
+
+        let Err(ScratchpadError::Config(msg)) = refused else {
+            panic!("nobody can confirm without a terminal");
+        };
+        assert!(msg.contains("--yes"));
+        assert!(forced.unwrap());
+    }
+
+    #[test]
+    fn execution_is_confirmed_with_yes_or_the_host() {
+        let endpoint = "http://db.internal:8890/sparql";
+        let answer = |input: &str| ask_confirmation(3, endpoint, false, true, input.as_bytes());
+
+        assert!(answer("yes\n").unwrap());
+        assert!(answer("  db.internal \n").unwrap());
+        assert!(!answer("y\n").unwrap());
+        assert!(!answer("").unwrap());
+    }
+
+    /// An update endpoint queue holding one DELETE WHERE per subject.
+    fn queued(endpoint: &str, subjects: &[&str]) -> HttpSink {
+        let mut sink = HttpSink::new(endpoint);
+        for subject in subjects {
+            let update = format!("DELETE WHERE {{ {} ?p ?o }}", subject);
+            sink.write_statement(&PlannedStatement {
+                text: format!("{};\n", update),
+                update,
+                subjects: vec![subject.to_string()],
+                type_iri: None,
+                depth: None,
+            })
+            .unwrap();
+        }
+        sink
+    }
+
+    fn no_deadlock_retries() -> DeadlockRetry {
+        DeadlockRetry::new("SR172", 0, Duration::ZERO).unwrap()
+    }
+
+    #[tokio::test]
+    async fn update_endpoints_are_checked_with_a_no_op_update() {
+        let server = TestServer::start(vec![response("204 No Content", &[], b"")]);
+        let mut client = SparqlClient::new(reqwest::Client::new());
+        client.update_field = "sparql".to_string();
+
+        check_endpoint_health(&client, &server.endpoint, true)
+            .await
+            .unwrap();
+
+        let request = server.requests().remove(0);
+        assert!(request.ends_with("\r\n\r\nsparql=INSERT+DATA+%7B%7D"));
+    }
+
+    #[tokio::test]
+    async fn statements_are_executed_in_batches() {
+        let ok = || response("200 OK", &[], b"");
+        let server = TestServer::start(vec![ok(), ok(), ok()]);
+        let client = SparqlClient::new(reqwest::Client::new());
+        let subjects = [
+            "<http://x/a>",
+            "<http://x/b>",
+            "<http://x/c>",
+            "<http://x/d>",
+            "<http://x/e>",
+        ];
+        let sink = queued(&server.endpoint, &subjects);
+
+        execute_statements(&client, &sink, 2, &no_deadlock_retries())
+            .await
+            .unwrap();
+
+        let per_request = server
+            .requests()
+            .iter()
+            .map(|request| request.matches("DELETE+WHERE").count())
+            .collect::<Vec<_>>();
+        assert_eq!(per_request, [2, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn failing_batch_is_retried_one_statement_at_a_time() {
+        let rejected = || response("400 Bad Request", &[], b"Permission denied");
+        let server = TestServer::start(vec![rejected(), response("200 OK", &[], b""), rejected()]);
+        let client = SparqlClient::new(reqwest::Client::new());
+        let sink = queued(
+            &server.endpoint,
+            &["<http://x/a>", "<http://x/b>", "<http://x/c>"],
+        );
+
+        let result = execute_statements(&client, &sink, 3, &no_deadlock_retries()).await;
+
+        let Err(ScratchpadError::Sparql(msg)) = result else {
+            panic!("the rejected statement must fail the execution");
+        };
+        assert!(msg.starts_with("Statement 2 (deleting <http://x/b>) failed: "));
+        assert!(msg.ends_with("rejected the update with 400 Bad Request: Permission denied"));
+        // The third statement is never sent once the second one failed.
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[2].matches("DELETE+WHERE").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn deadlocked_update_is_sent_again_until_it_goes_through() {
+        let deadlocked = response(
+            "200 OK",
+            &[("Content-Type", "text/plain")],
+            b"Virtuoso 40001 Error SR172: Transaction deadlocked",
+        );
+        let server = TestServer::start(vec![deadlocked, response("200 OK", &[], b"")]);
+        let client = SparqlClient::new(reqwest::Client::new());
+        let deadlock =
+            DeadlockRetry::new(DEFAULT_DEADLOCK_PATTERN, 3, Duration::from_millis(1)).unwrap();
+        let update = "DELETE WHERE { <http://x/bestuur/4> ?p ?o }";
+
+        execute_update_retrying(&client, &server.endpoint, update, &deadlock)
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0].split("\r\n\r\n").last(),
+            requests[1].split("\r\n\r\n").last()
+        );
+        assert_eq!(client.stats.retries(), 1);
+    }
+
+    #[tokio::test]
+    async fn deadlock_fails_once_the_retries_are_used_up() {
+        let deadlocked = || response("500 Internal Server Error", &[], b"Error SR171: timeout");
+        let server = TestServer::start(vec![deadlocked(), deadlocked(), deadlocked()]);
+        let client = SparqlClient::new(reqwest::Client::new());
+        let deadlock = DeadlockRetry::new("SR17[12]", 2, Duration::from_millis(1)).unwrap();
+
+        let result =
+            execute_update_retrying(&client, &server.endpoint, "CLEAR ALL", &deadlock).await;
+
+        let Err(ScratchpadError::Deadlock(msg)) = result else {
+            panic!("a lasting deadlock must fail the update");
+        };
+        assert!(msg.ends_with("Error SR171: timeout (gave up after 2 retries)"));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn updates_go_in_the_update_field_and_queries_in_the_query_field() {
+        let server = TestServer::start(vec![
+            response("204 No Content", &[], b""),
+            json_response(r#"{ "head": { "vars": [] }, "boolean": true }"#),
+            response("200 OK", &[], b""),
+        ]);
+        let mut client = SparqlClient::new(reqwest::Client::new());
+        let deadlock = DeadlockRetry::new(DEFAULT_DEADLOCK_PATTERN, 0, Duration::ZERO).unwrap();
+        let update = "CLEAR GRAPH <http://x/g>";
+
+        execute_update(&client, &server.endpoint, update, &deadlock)
+            .await
+            .unwrap();
+        fetch_sparql_results(&client, &server.endpoint, "ASK {}")
+            .await
+            .unwrap();
+        // A gateway expecting its own field name.
+        client.update_field = "sparql".to_string();
+        execute_update(&client, &server.endpoint, update, &deadlock)
+            .await
+            .unwrap();
+
+        let bodies = server
+            .requests()
+            .iter()
+            .map(|request| request.split("\r\n\r\n").last().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            bodies,
+            [
+                "update=CLEAR+GRAPH+%3Chttp%3A%2F%2Fx%2Fg%3E",
+                "query=ASK+%7B%7D",
+                "sparql=CLEAR+GRAPH+%3Chttp%3A%2F%2Fx%2Fg%3E",
+            ]
+        );
+    }
+
+    #[test]
+    fn query_too_large_by_status_or_engine_message() {
+        assert!(is_query_too_large(StatusCode::PAYLOAD_TOO_LARGE, ""));
+        assert!(is_query_too_large(StatusCode::URI_TOO_LONG, ""));
+        assert!(is_query_too_large(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Virtuoso 37000 Error SQ200: Query too large, variables in the query > 10000"
+        ));
+        assert!(is_query_too_large(
+            StatusCode::BAD_REQUEST,
+            "Query Too Large"
+        ));
+    }
+
+    #[test]
+    fn other_errors_are_not_query_too_large() {
+        assert!(!is_query_too_large(
+            StatusCode::GATEWAY_TIMEOUT,
+            "<html>The request took too long</html>"
+        ));
+        assert!(!is_query_too_large(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Virtuoso 42000 Error SR172: Transaction deadlocked"
+        ));
+    }
 }
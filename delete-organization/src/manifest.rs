@@ -0,0 +1,130 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::ScratchpadError;
+
+/// A file of the generated script together with its SHA-256.
+#[derive(Serialize)]
+pub struct OutputFile {
+    pub path: PathBuf,
+    pub sha256: String,
+}
+
+/// Written next to the output, so auditors can confirm which script was generated by
+/// which run and that it has not been changed since.
+#[derive(Serialize)]
+pub struct Manifest {
+    pub run_id: String,
+    pub tool_version: String,
+    /// When the output was finalized, as RFC 3339 timestamp in UTC.
+    pub generated_at: String,
+    pub endpoint: String,
+    pub seeds: Vec<String>,
+    pub seed_type: String,
+    pub statements: usize,
+    pub files: Vec<OutputFile>,
+}
+
+impl Manifest {
+    /// Creates a manifest for `files`, which have to be complete since they are hashed now.
+    pub fn new(
+        run_id: &str,
+        endpoint: &str,
+        seeds: &[String],
+        seed_type: &str,
+        statements: usize,
+        files: Vec<PathBuf>,
+    ) -> Result<Self, ScratchpadError> {
+        let files = files
+            .into_iter()
+            .map(|path| {
+                Ok(OutputFile {
+                    sha256: sha256_file(&path)?,
+                    path,
+                })
+            })
+            .collect::<Result<Vec<_>, ScratchpadError>>()?;
+
+        Ok(Manifest {
+            run_id: run_id.to_string(),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            generated_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            endpoint: endpoint.to_string(),
+            seeds: seeds.to_vec(),
+            seed_type: seed_type.to_string(),
+            statements,
+            files,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ScratchpadError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Hex-encoded SHA-256 of a file's contents.
+pub fn sha256_file(path: &Path) -> Result<String, ScratchpadError> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_checksum_is_the_hash_of_the_finished_file() {
+        let dir = std::env::temp_dir().join(format!("manifest-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("output.txt");
+        std::fs::write(&output, "abc").unwrap();
+        let seeds = ["<http://x/org>".to_string()];
+
+        let manifest = Manifest::new(
+            "run",
+            "http://db/sparql",
+            &seeds,
+            "<http://t/Org>",
+            1,
+            vec![output.clone()],
+        )
+        .unwrap();
+        manifest.save(&dir.join("manifest.json")).unwrap();
+
+        let saved: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(dir.join("manifest.json")).unwrap()).unwrap();
+        // The SHA-256 test vector of "abc" from FIPS 180-2.
+        assert_eq!(
+            saved["files"][0]["sha256"],
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(saved["files"][0]["path"], output.to_str().unwrap());
+        assert_eq!(saved["statements"], 1);
+        assert_eq!(saved["tool_version"], env!("CARGO_PKG_VERSION"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_output_files_cannot_be_hashed() {
+        let missing = std::env::temp_dir()
+            .join("manifest-missing")
+            .join("output.txt");
+
+        assert!(sha256_file(&missing).is_err());
+    }
+}
@@ -0,0 +1,359 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Counters shared by every SPARQL request of a run.
+#[derive(Default)]
+pub struct RequestStats {
+    requests: AtomicU64,
+    request_nanos: AtomicU64,
+    retries: AtomicU64,
+    errors: AtomicU64,
+    response_bytes: AtomicU64,
+    type_cache_hits: AtomicU64,
+    type_cache_misses: AtomicU64,
+}
+
+impl RequestStats {
+    pub fn count_request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Adds the time spent waiting on the endpoint for a completed request.
+    pub fn add_request_time(&self, elapsed: Duration) {
+        self.request_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Counts a request that is sent again, after a Retry-After or a deadlock.
+    pub fn count_retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a request that failed to send or was answered with an error status.
+    pub fn count_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_response_bytes(&self, bytes: usize) {
+        self.response_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    pub fn retries(&self) -> u64 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    pub fn response_bytes(&self) -> u64 {
+        self.response_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn request_time(&self) -> Duration {
+        Duration::from_nanos(self.request_nanos.load(Ordering::Relaxed))
+    }
+
+    /// Counts a type lookup that was answered from the cache, or had to be sent if `hit` is
+    /// not set.
+    pub fn count_type_lookup(&self, hit: bool) {
+        let counter = match hit {
+            true => &self.type_cache_hits,
+            false => &self.type_cache_misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn type_cache_hits(&self) -> u64 {
+        self.type_cache_hits.load(Ordering::Relaxed)
+    }
+
+    pub fn type_cache_misses(&self) -> u64 {
+        self.type_cache_misses.load(Ordering::Relaxed)
+    }
+}
+
+/// A phase that is being measured, see [`RunMetrics::start_phase`].
+pub struct PhaseTimer {
+    phase: String,
+    started: Instant,
+    requests: u64,
+    request_time: Duration,
+    type_cache_hits: u64,
+    type_cache_misses: u64,
+}
+
+pub struct PhaseMetrics {
+    pub phase: String,
+    pub elapsed: Duration,
+    pub requests: u64,
+    /// Part of `elapsed` spent waiting on the endpoint.
+    pub request_time: Duration,
+}
+
+/// Wall-clock time and number of SPARQL requests per phase of a run, to tell whether a
+/// slow run is caused by the server or by the number of round trips.
+#[derive(Default)]
+pub struct RunMetrics {
+    pub phases: Vec<PhaseMetrics>,
+    /// Type lookups of all phases answered from the cache and sent to the endpoint.
+    pub type_cache_hits: u64,
+    pub type_cache_misses: u64,
+    /// Statements generated by the run, whether or not they were written.
+    pub statements: u64,
+    /// Stats of the clients the phases ran with, read again at the end of the run since a
+    /// phase that fails is never finished.
+    clients: Vec<Arc<RequestStats>>,
+}
+
+impl RunMetrics {
+    pub fn start_phase(&mut self, phase: &str, stats: &Arc<RequestStats>) -> PhaseTimer {
+        if !self.clients.iter().any(|client| Arc::ptr_eq(client, stats)) {
+            self.clients.push(stats.clone());
+        }
+        PhaseTimer {
+            phase: phase.to_string(),
+            started: Instant::now(),
+            requests: stats.requests(),
+            request_time: stats.request_time(),
+            type_cache_hits: stats.type_cache_hits(),
+            type_cache_misses: stats.type_cache_misses(),
+        }
+    }
+
+    pub fn finish_phase(&mut self, timer: PhaseTimer, stats: &RequestStats) {
+        self.type_cache_hits += stats.type_cache_hits() - timer.type_cache_hits;
+        self.type_cache_misses += stats.type_cache_misses() - timer.type_cache_misses;
+        self.phases.push(PhaseMetrics {
+            phase: timer.phase,
+            elapsed: timer.started.elapsed(),
+            requests: stats.requests() - timer.requests,
+            request_time: stats.request_time().saturating_sub(timer.request_time),
+        });
+    }
+
+    pub fn summary(&self) -> String {
+        let mut s = String::from("Phase timings:\n");
+
+        for metrics in &self.phases {
+            let average = match metrics.requests {
+                0 => Duration::ZERO,
+                n => metrics.request_time / n as u32,
+            };
+            s.push_str(&format!(
+                "  {:<16} {:>10.3?} total, {:>6} request(s), {:>10.3?} waiting on the endpoint ({:.3?} avg)\n",
+                metrics.phase, metrics.elapsed, metrics.requests, metrics.request_time, average
+            ));
+        }
+        let lookups = self.type_cache_hits + self.type_cache_misses;
+        if lookups > 0 {
+            s.push_str(&format!(
+                "  type cache: {} hit(s), {} miss(es), {:.0}% hit rate\n",
+                self.type_cache_hits,
+                self.type_cache_misses,
+                self.type_cache_hits as f64 * 100.0 / lookups as f64
+            ));
+        }
+
+        s
+    }
+
+    /// The counters of the run in the Prometheus text exposition format. The phase timings
+    /// only cover the phases that completed.
+    pub fn prometheus(&self, success: bool) -> String {
+        let total = |value: fn(&RequestStats) -> u64| {
+            self.clients.iter().map(|client| value(client)).sum::<u64>()
+        };
+        let mut s = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+            s.push_str(&format!("# HELP delete_organization_{} {}\n", name, help));
+            s.push_str(&format!("# TYPE delete_organization_{} {}\n", name, kind));
+            for (labels, value) in samples {
+                s.push_str(&format!(
+                    "delete_organization_{}{} {}\n",
+                    name, labels, value
+                ));
+            }
+        };
+        let single = |value: u64| [(String::new(), value.to_string())];
+
+        metric(
+            "requests_total",
+            "counter",
+            "SPARQL requests sent, including retries.",
+            &single(total(RequestStats::requests)),
+        );
+        metric(
+            "retries_total",
+            "counter",
+            "Requests sent again after a Retry-After or a deadlock.",
+            &single(total(RequestStats::retries)),
+        );
+        metric(
+            "errors_total",
+            "counter",
+            "Requests that failed to send or were answered with an error status.",
+            &single(total(RequestStats::errors)),
+        );
+        metric(
+            "response_bytes_total",
+            "counter",
+            "Bytes of response bodies received, after decompression.",
+            &single(total(RequestStats::response_bytes)),
+        );
+        metric(
+            "statements_total",
+            "counter",
+            "DELETE statements generated.",
+            &single(self.statements),
+        );
+        metric(
+            "type_cache_lookups_total",
+            "counter",
+            "rdf:type lookups, by whether the cache answered them.",
+            &[
+                (
+                    "{result=\"hit\"}".to_string(),
+                    total(RequestStats::type_cache_hits).to_string(),
+                ),
+                (
+                    "{result=\"miss\"}".to_string(),
+                    total(RequestStats::type_cache_misses).to_string(),
+                ),
+            ],
+        );
+        let phases = |value: fn(&PhaseMetrics) -> String| {
+            self.phases
+                .iter()
+                .map(|phase| (format!("{{phase=\"{}\"}}", phase.phase), value(phase)))
+                .collect::<Vec<_>>()
+        };
+        metric(
+            "phase_duration_seconds",
+            "gauge",
+            "Wall-clock time of each phase of the run.",
+            &phases(|phase| phase.elapsed.as_secs_f64().to_string()),
+        );
+        metric(
+            "phase_requests",
+            "gauge",
+            "SPARQL requests sent in each phase of the run.",
+            &phases(|phase| phase.requests.to_string()),
+        );
+        metric(
+            "success",
+            "gauge",
+            "1 if the run completed, 0 if it failed.",
+            &single(success as u64),
+        );
+
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn phases_count_only_their_own_requests() {
+        let stats = Arc::new(RequestStats::default());
+        let mut metrics = RunMetrics::default();
+        stats.count_request();
+
+        let timer = metrics.start_phase("reverse lookups", &stats);
+        stats.count_request();
+        stats.count_request();
+        stats.add_request_time(Duration::from_millis(40));
+        metrics.finish_phase(timer, &stats);
+        let timer = metrics.start_phase("forward lookups", &stats);
+        metrics.finish_phase(timer, &stats);
+
+        let phases = metrics
+            .phases
+            .iter()
+            .map(|phase| (phase.phase.as_str(), phase.requests, phase.request_time))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            phases,
+            [
+                ("reverse lookups", 2, Duration::from_millis(40)),
+                ("forward lookups", 0, Duration::ZERO),
+            ]
+        );
+    }
+
+    #[test]
+    fn summary_lists_every_phase_with_its_average() {
+        let metrics = RunMetrics {
+            phases: vec![PhaseMetrics {
+                phase: "deletion".to_string(),
+                elapsed: Duration::from_secs(2),
+                requests: 4,
+                request_time: Duration::from_secs(1),
+            }],
+            ..RunMetrics::default()
+        };
+
+        let summary = metrics.summary();
+
+        assert!(summary.starts_with("Phase timings:\n  deletion "));
+        assert!(summary.contains("4 request(s)"));
+        assert!(summary.contains("(250.000ms avg)"));
+        assert!(!summary.contains("type cache"));
+    }
+
+    #[test]
+    fn prometheus_export_has_every_counter_with_a_numeric_value() {
+        let query = Arc::new(RequestStats::default());
+        let update = Arc::new(RequestStats::default());
+        let mut metrics = RunMetrics::default();
+
+        let timer = metrics.start_phase("lookups", &query);
+        for _ in 0..3 {
+            query.count_request();
+        }
+        query.add_response_bytes(2048);
+        query.count_type_lookup(false);
+        metrics.finish_phase(timer, &query);
+        metrics.statements = 12;
+        // The execution fails halfway, its phase is never finished.
+        let _timer = metrics.start_phase("execution", &update);
+        update.count_request();
+        update.count_retry();
+        update.count_error();
+
+        let text = metrics.prometheus(false);
+
+        let samples = text
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| line.rsplit_once(' ').unwrap())
+            .collect::<HashMap<_, _>>();
+        for value in samples.values() {
+            assert!(value.parse::<f64>().is_ok(), "{} is not a number", value);
+        }
+        assert_eq!(samples["delete_organization_requests_total"], "4");
+        assert_eq!(samples["delete_organization_retries_total"], "1");
+        assert_eq!(samples["delete_organization_errors_total"], "1");
+        assert_eq!(samples["delete_organization_response_bytes_total"], "2048");
+        assert_eq!(samples["delete_organization_statements_total"], "12");
+        assert_eq!(
+            samples["delete_organization_type_cache_lookups_total{result=\"miss\"}"],
+            "1"
+        );
+        assert_eq!(
+            samples["delete_organization_phase_requests{phase=\"lookups\"}"],
+            "3"
+        );
+        assert_eq!(samples["delete_organization_success"], "0");
+        assert!(text.contains("# TYPE delete_organization_requests_total counter\n"));
+    }
+}
@@ -0,0 +1,1249 @@
+use std::borrow::Cow;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+use indexmap::IndexMap;
+use serde::Serialize;
+
+use crate::error::ScratchpadError;
+use crate::planner::PlannedStatement;
+
+/// How generated statements are laid out in the output.
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Indented, one triple pattern per line.
+    #[default]
+    Pretty,
+    /// Each statement on a single line with redundant whitespace removed.
+    Minified,
+}
+
+/// Which form of DELETE is generated.
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum DeleteStyle {
+    /// `DELETE { GRAPH ?g { ... } } WHERE { VALUES ?s { ... } GRAPH ?g { ... } }`.
+    #[default]
+    Full,
+    /// One `DELETE WHERE { GRAPH ?g { <s> ?p ?o } }` per subject, which some engines optimize
+    /// better. SPARQL does not allow VALUES inside `DELETE WHERE`, so every subject gets its
+    /// own operation. Falls back to `full` when extra WHERE clauses (e.g. --since or
+    /// --predicate) are used.
+    DeleteWhere,
+}
+
+/// Line ending of the written output.
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    /// `\r\n`, for tools on Windows that do not accept bare `\n`.
+    Crlf,
+}
+
+impl LineEnding {
+    /// `text` with every line ending turned into this one. Existing `\r\n` are kept as
+    /// they are, so they do not end up as `\r\r\n`.
+    pub fn apply(self, text: &str) -> Cow<'_, str> {
+        match self {
+            LineEnding::Lf => Cow::Borrowed(text),
+            LineEnding::Crlf => {
+                let mut out = String::with_capacity(text.len() + text.len() / 16);
+                let mut previous = None;
+                for c in text.chars() {
+                    if c == '\n' && previous != Some('\r') {
+                        out.push('\r');
+                    }
+                    out.push(c);
+                    previous = Some(c);
+                }
+                Cow::Owned(out)
+            }
+        }
+    }
+}
+
+/// Compression of the combined output file.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Compression {
+    /// gzip, written to `output.txt.gz`.
+    Gzip,
+}
+
+impl Compression {
+    fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Formats a single statement, puts it in place of the `{{statement}}` placeholder of
+    /// `template` if one is given and appends the separator that goes between statements,
+    /// the format's default one unless `separator` is given.
+    pub fn terminate(
+        self,
+        statement: &str,
+        template: Option<&str>,
+        separator: Option<&str>,
+    ) -> String {
+        let mut statement = match self {
+            OutputFormat::Pretty => statement.trim_end().to_string(),
+            OutputFormat::Minified => minify_sparql(statement),
+        };
+        if let Some(template) = template {
+            statement = template.replace(STATEMENT_PLACEHOLDER, &statement);
+        }
+
+        format!(
+            "{}{}",
+            statement,
+            separator.unwrap_or(self.default_separator())
+        )
+    }
+
+    fn default_separator(self) -> &'static str {
+        match self {
+            OutputFormat::Pretty => "\n\n;\n\n",
+            OutputFormat::Minified => " ;\n",
+        }
+    }
+}
+
+/// Placeholder of `--output-template` that every statement is put in place of.
+pub const STATEMENT_PLACEHOLDER: &str = "{{statement}}";
+
+/// Turns the `\n`, `\r`, `\t` and `\\` escapes of a separator given on the command line
+/// into the characters they stand for.
+pub fn unescape_separator(separator: &str) -> String {
+    let mut out = String::with_capacity(separator.len());
+    let mut chars = separator.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+
+    out
+}
+
+/// Collapses whitespace runs into a single space and drops whitespace around braces.
+/// IRIs and string literals are copied verbatim, comments are removed.
+pub fn minify_sparql(query: &str) -> String {
+    let mut out = String::with_capacity(query.len());
+    let mut chars = query.chars().peekable();
+    let mut pending_space = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => pending_space = true,
+            '#' => {
+                while chars.next_if(|&n| n != '\n').is_some() {}
+                pending_space = true;
+            }
+            '{' | '}' => {
+                out.push(c);
+                pending_space = false;
+            }
+            _ => {
+                if pending_space && !out.is_empty() && !out.ends_with(['{', '}']) {
+                    out.push(' ');
+                }
+                pending_space = false;
+                out.push(c);
+
+                // Copy IRIs and literals up to and including their closing delimiter.
+                let closing = match c {
+                    '<' => '>',
+                    '"' | '\'' => c,
+                    _ => continue,
+                };
+                while let Some(n) = chars.next() {
+                    out.push(n);
+                    if n == '\\' && closing != '>' {
+                        if let Some(escaped) = chars.next() {
+                            out.push(escaped);
+                        }
+                    } else if n == closing {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// Where generated statements are delivered, one statement at a time as soon as it is
+/// generated.
+pub trait OutputSink {
+    /// Writes one statement in one go, so the output never contains half of one.
+    fn write_block(&mut self, sparql: &str) -> std::io::Result<()>;
+
+    /// Writes one statement deleting subjects of the given config type, `None` for
+    /// statements that do not belong to a single type. Only sinks that group statements by
+    /// type need it.
+    fn write_typed_block(&mut self, sparql: &str, _type_iri: Option<&str>) -> std::io::Result<()> {
+        self.write_block(sparql)
+    }
+
+    /// Writes a generated statement. Sinks that need more than its text, e.g. the bare
+    /// update to execute it, get it from here.
+    fn write_statement(&mut self, statement: &PlannedStatement) -> std::io::Result<()> {
+        self.write_typed_block(&statement.text, statement.type_iri.as_deref())
+    }
+
+    /// Appends `note`, e.g. a comment, to the output without counting it as a statement.
+    fn write_note(&mut self, note: &str) -> std::io::Result<()>;
+
+    /// Completes the output, e.g. to compute the checksums of its files. Nothing can be
+    /// written afterwards.
+    fn finish(&mut self) -> std::io::Result<()>;
+
+    /// Number of statements written so far.
+    fn written(&self) -> usize;
+
+    /// Where the statements end up, for messages.
+    fn location(&self) -> String;
+
+    /// Every file statements were written to, in order.
+    fn files(&self) -> Vec<PathBuf> {
+        vec![]
+    }
+
+    /// Where the manifest of the output goes, `None` for output that is not a file.
+    fn manifest_path(&self) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// The file of combined output, compressed while it is written if requested.
+pub enum CombinedWriter {
+    Plain(BufWriter<File>),
+    /// Every flush ends a deflate block, so the statements written so far can be
+    /// decompressed even if the run is killed before the end of the stream is written.
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl CombinedWriter {
+    fn finish(&mut self) -> std::io::Result<()> {
+        match self {
+            CombinedWriter::Plain(writer) => writer.flush(),
+            CombinedWriter::Gzip(encoder) => {
+                encoder.try_finish()?;
+                encoder.get_mut().flush()
+            }
+        }
+    }
+}
+
+impl Write for CombinedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            CombinedWriter::Plain(writer) => writer.write(buf),
+            CombinedWriter::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            CombinedWriter::Plain(writer) => writer.flush(),
+            CombinedWriter::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Creates `dir` if it is missing and checks that files can be created in it, so an
+/// unusable output location fails the run before the traversal starts.
+pub fn prepare_output_dir(dir: &Path) -> Result<(), ScratchpadError> {
+    let unusable = |what: &str, e: std::io::Error| {
+        ScratchpadError::Config(format!(
+            "Output directory {} {}: {}",
+            dir.display(),
+            what,
+            e
+        ))
+    };
+    fs::create_dir_all(dir).map_err(|e| unusable("cannot be created", e))?;
+
+    let probe = dir.join(format!(".write-test-{}", std::process::id()));
+    File::create(&probe)
+        .and_then(|_| fs::remove_file(&probe))
+        .map_err(|e| unusable("is not writable", e))
+}
+
+/// Where generated statements are written.
+pub enum OutputTarget {
+    /// All statements appended to a single file.
+    Combined {
+        path: PathBuf,
+        writer: CombinedWriter,
+        written: usize,
+        line_ending: LineEnding,
+    },
+    /// Every statement in its own numbered `.rq` file, in generation order.
+    Split {
+        dir: PathBuf,
+        written: usize,
+        line_ending: LineEnding,
+    },
+}
+
+impl OutputTarget {
+    /// Writes to `path`, replacing its contents unless `append` is set. Compressed output
+    /// goes to `path` with the extension of the compression added. Appending to it adds
+    /// another gzip member, which decompresses to the concatenation of both runs.
+    pub fn combined(
+        path: PathBuf,
+        append: bool,
+        line_ending: LineEnding,
+        compression: Option<Compression>,
+    ) -> std::io::Result<Self> {
+        let path = match compression {
+            Some(compression) => {
+                let mut name = path.into_os_string();
+                name.push(".");
+                name.push(compression.extension());
+                PathBuf::from(name)
+            }
+            None => path,
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&path)?;
+        let writer = match compression {
+            Some(Compression::Gzip) => CombinedWriter::Gzip(GzEncoder::new(
+                BufWriter::new(file),
+                flate2::Compression::default(),
+            )),
+            None => CombinedWriter::Plain(BufWriter::new(file)),
+        };
+
+        Ok(OutputTarget::Combined {
+            path,
+            writer,
+            written: 0,
+            line_ending,
+        })
+    }
+
+    pub fn split(dir: PathBuf, line_ending: LineEnding) -> std::io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+
+        Ok(OutputTarget::Split {
+            dir,
+            written: 0,
+            line_ending,
+        })
+    }
+}
+
+impl OutputSink for OutputTarget {
+    /// Flushes every statement right away, so even a killed run leaves every completed
+    /// statement on disk.
+    fn write_block(&mut self, sparql: &str) -> std::io::Result<()> {
+        match self {
+            OutputTarget::Combined {
+                writer,
+                written,
+                line_ending,
+                ..
+            } => {
+                *written += 1;
+                writer.write_all(line_ending.apply(sparql).as_bytes())?;
+                writer.flush()
+            }
+            OutputTarget::Split {
+                dir,
+                written,
+                line_ending,
+            } => {
+                *written += 1;
+                fs::write(
+                    dir.join(format!("{:04}.rq", written)),
+                    line_ending.apply(sparql).as_bytes(),
+                )
+            }
+        }
+    }
+
+    /// Split output gets the note at the end of the last statement's file.
+    fn write_note(&mut self, note: &str) -> std::io::Result<()> {
+        match self {
+            OutputTarget::Combined {
+                writer,
+                line_ending,
+                ..
+            } => {
+                writer.write_all(line_ending.apply(note).as_bytes())?;
+                writer.flush()
+            }
+            OutputTarget::Split {
+                dir,
+                written,
+                line_ending,
+            } => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(dir.join(format!("{:04}.rq", written)))?
+                .write_all(line_ending.apply(note).as_bytes()),
+        }
+    }
+
+    /// For compressed output, this writes the end of the stream.
+    fn finish(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputTarget::Combined { writer, .. } => writer.finish(),
+            OutputTarget::Split { .. } => Ok(()),
+        }
+    }
+
+    fn written(&self) -> usize {
+        match self {
+            OutputTarget::Combined { written, .. } | OutputTarget::Split { written, .. } => {
+                *written
+            }
+        }
+    }
+
+    fn location(&self) -> String {
+        match self {
+            OutputTarget::Combined { path, .. } => path.display().to_string(),
+            OutputTarget::Split { dir, .. } => dir.display().to_string(),
+        }
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        match self {
+            OutputTarget::Combined { path, .. } => vec![path.clone()],
+            OutputTarget::Split { dir, written, .. } => (1..=*written)
+                .map(|i| dir.join(format!("{:04}.rq", i)))
+                .collect(),
+        }
+    }
+
+    /// Next to the output file, or in the directory of split output.
+    fn manifest_path(&self) -> Option<PathBuf> {
+        match self {
+            OutputTarget::Combined { path, .. } => Some(path.with_file_name("manifest.json")),
+            OutputTarget::Split { dir, .. } => Some(dir.join("manifest.json")),
+        }
+    }
+}
+
+/// Statements grouped by the config type of their subjects, one file per type named after
+/// the type's local name (`Bestuurseenheid.rq`), in the order they were generated.
+/// Statements without a single type, like the ones deleting the triples pointing to the
+/// seeds, go to `other.rq`.
+pub struct PerTypeOutput {
+    dir: PathBuf,
+    /// The file of every type seen so far, in the order they were created.
+    files: IndexMap<Option<String>, (PathBuf, BufWriter<File>)>,
+    /// Index in `files` of the file written to last.
+    last: Option<usize>,
+    written: usize,
+    line_ending: LineEnding,
+}
+
+impl PerTypeOutput {
+    pub fn new(dir: PathBuf, line_ending: LineEnding) -> std::io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+
+        Ok(PerTypeOutput {
+            dir,
+            files: IndexMap::new(),
+            last: None,
+            written: 0,
+            line_ending,
+        })
+    }
+
+    /// The file of `type_iri`, created on first use. Types whose local names clash get a
+    /// numbered suffix, e.g. `Identifier-2.rq`.
+    fn file(&mut self, type_iri: Option<&str>) -> std::io::Result<&mut BufWriter<File>> {
+        let key = type_iri.map(str::to_string);
+        if !self.files.contains_key(&key) {
+            let name = type_iri.map_or("other".to_string(), local_file_name);
+            let mut path = self.dir.join(format!("{}.rq", name));
+            let mut n = 1;
+            while self.files.values().any(|(taken, _)| *taken == path) {
+                n += 1;
+                path = self.dir.join(format!("{}-{}.rq", name, n));
+            }
+            let writer = BufWriter::new(File::create(&path)?);
+            self.files.insert(key.clone(), (path, writer));
+        }
+        let (index, _, (_, writer)) = self.files.get_full_mut(&key).unwrap();
+        self.last = Some(index);
+
+        Ok(writer)
+    }
+}
+
+/// The part of an IRI after its last `#` or `/`, with everything but letters, digits, `-`
+/// and `_` replaced, so it can be used as a file name.
+fn local_file_name(type_iri: &str) -> String {
+    let iri = strip_brackets(type_iri);
+    let local = iri.rsplit(['#', '/', ':']).next().unwrap_or(iri);
+    let name = local
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() || c == '-' || c == '_' => c,
+            _ => '_',
+        })
+        .collect::<String>();
+
+    match name.is_empty() {
+        true => "type".to_string(),
+        false => name,
+    }
+}
+
+impl OutputSink for PerTypeOutput {
+    fn write_block(&mut self, sparql: &str) -> std::io::Result<()> {
+        self.write_typed_block(sparql, None)
+    }
+
+    /// Flushes every statement right away, like the other file outputs.
+    fn write_typed_block(&mut self, sparql: &str, type_iri: Option<&str>) -> std::io::Result<()> {
+        self.written += 1;
+        let line_ending = self.line_ending;
+        let writer = self.file(type_iri)?;
+        writer.write_all(line_ending.apply(sparql).as_bytes())?;
+        writer.flush()
+    }
+
+    /// The note goes to the end of the file written to last.
+    fn write_note(&mut self, note: &str) -> std::io::Result<()> {
+        let Some((_, writer)) = self.last.and_then(|i| self.files.get_index_mut(i)) else {
+            return Ok(());
+        };
+        writer
+            .1
+            .write_all(self.line_ending.apply(note).as_bytes())?;
+        writer.1.flush()
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        for (_, writer) in self.files.values_mut() {
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn written(&self) -> usize {
+        self.written
+    }
+
+    fn location(&self) -> String {
+        self.dir.display().to_string()
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        self.files.values().map(|(path, _)| path.clone()).collect()
+    }
+
+    fn manifest_path(&self) -> Option<PathBuf> {
+        Some(self.dir.join("manifest.json"))
+    }
+}
+
+/// Statements written to stdout, to pipe them straight into another tool.
+pub struct StdoutSink {
+    written: usize,
+    line_ending: LineEnding,
+}
+
+impl StdoutSink {
+    pub fn new(line_ending: LineEnding) -> Self {
+        StdoutSink {
+            written: 0,
+            line_ending,
+        }
+    }
+}
+
+impl OutputSink for StdoutSink {
+    fn write_block(&mut self, sparql: &str) -> std::io::Result<()> {
+        self.written += 1;
+        self.write_note(sparql)
+    }
+
+    fn write_note(&mut self, note: &str) -> std::io::Result<()> {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(self.line_ending.apply(note).as_bytes())?;
+        stdout.flush()
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        std::io::stdout().flush()
+    }
+
+    fn written(&self) -> usize {
+        self.written
+    }
+
+    fn location(&self) -> String {
+        "stdout".to_string()
+    }
+}
+
+/// Statements kept in memory, for the steps that need all of them once the traversal is
+/// complete. Notes are not statements and are dropped.
+#[derive(Default)]
+pub struct MemorySink {
+    statements: Vec<PlannedStatement>,
+}
+
+impl MemorySink {
+    pub fn statements(&self) -> &[PlannedStatement] {
+        &self.statements
+    }
+}
+
+impl OutputSink for MemorySink {
+    /// A block that is not a planned statement is kept as one deleting no subjects.
+    fn write_block(&mut self, sparql: &str) -> std::io::Result<()> {
+        self.statements.push(PlannedStatement {
+            text: sparql.to_string(),
+            update: sparql.to_string(),
+            subjects: vec![],
+            type_iri: None,
+            depth: None,
+        });
+        Ok(())
+    }
+
+    fn write_statement(&mut self, statement: &PlannedStatement) -> std::io::Result<()> {
+        self.statements.push(statement.clone());
+        Ok(())
+    }
+
+    fn write_note(&mut self, _note: &str) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn written(&self) -> usize {
+        self.statements.len()
+    }
+
+    fn location(&self) -> String {
+        "memory".to_string()
+    }
+}
+
+/// Statements POSTed to a SPARQL update endpoint, for `execute`. Executing a statement as
+/// soon as it is generated would remove triples that later hops of the traversal still
+/// need to follow, so they are queued until the traversal is complete and then sent by
+/// `execute_statements`.
+pub struct HttpSink {
+    endpoint: String,
+    queued: MemorySink,
+}
+
+impl HttpSink {
+    pub fn new(endpoint: &str) -> Self {
+        HttpSink {
+            endpoint: endpoint.to_string(),
+            queued: MemorySink::default(),
+        }
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// The statements queued so far, in the order they are executed.
+    pub fn statements(&self) -> &[PlannedStatement] {
+        self.queued.statements()
+    }
+}
+
+impl OutputSink for HttpSink {
+    fn write_block(&mut self, sparql: &str) -> std::io::Result<()> {
+        self.queued.write_block(sparql)
+    }
+
+    fn write_statement(&mut self, statement: &PlannedStatement) -> std::io::Result<()> {
+        self.queued.write_statement(statement)
+    }
+
+    fn write_note(&mut self, _note: &str) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn written(&self) -> usize {
+        self.queued.written()
+    }
+
+    fn location(&self) -> String {
+        self.endpoint.clone()
+    }
+}
+
+/// Writes every statement to `output` and a copy of it to `copy`, e.g. to keep the
+/// statements that were written to a file for the steps after the traversal. Everything
+/// else, like notes and where the statements end up, is about `output`.
+pub struct Tee<'a> {
+    output: &'a mut dyn OutputSink,
+    copy: Option<&'a mut dyn OutputSink>,
+}
+
+impl<'a> Tee<'a> {
+    pub fn new(output: &'a mut dyn OutputSink, copy: Option<&'a mut dyn OutputSink>) -> Self {
+        Tee { output, copy }
+    }
+}
+
+impl OutputSink for Tee<'_> {
+    fn write_block(&mut self, sparql: &str) -> std::io::Result<()> {
+        self.write_typed_block(sparql, None)
+    }
+
+    fn write_typed_block(&mut self, sparql: &str, type_iri: Option<&str>) -> std::io::Result<()> {
+        self.output.write_typed_block(sparql, type_iri)?;
+        match &mut self.copy {
+            Some(copy) => copy.write_typed_block(sparql, type_iri),
+            None => Ok(()),
+        }
+    }
+
+    fn write_statement(&mut self, statement: &PlannedStatement) -> std::io::Result<()> {
+        self.output.write_statement(statement)?;
+        match &mut self.copy {
+            Some(copy) => copy.write_statement(statement),
+            None => Ok(()),
+        }
+    }
+
+    fn write_note(&mut self, note: &str) -> std::io::Result<()> {
+        self.output.write_note(note)
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.output.finish()?;
+        match &mut self.copy {
+            Some(copy) => copy.finish(),
+            None => Ok(()),
+        }
+    }
+
+    fn written(&self) -> usize {
+        self.output.written()
+    }
+
+    fn location(&self) -> String {
+        self.output.location()
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        self.output.files()
+    }
+
+    fn manifest_path(&self) -> Option<PathBuf> {
+        self.output.manifest_path()
+    }
+}
+
+/// One line of --subjects-out.
+#[derive(Serialize)]
+struct SubjectLine<'a> {
+    uri: &'a str,
+    #[serde(rename = "type")]
+    type_iri: &'a str,
+    depth: Option<usize>,
+}
+
+/// Deleted subjects as JSON lines, written as soon as they are discovered so other tools
+/// can process them while the traversal is still running.
+pub struct SubjectsOut {
+    writer: Box<dyn Write>,
+}
+
+impl SubjectsOut {
+    /// Appends to `path`, or writes to stdout for `-`.
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let writer: Box<dyn Write> = if path == Path::new("-") {
+            Box::new(std::io::stdout())
+        } else {
+            Box::new(BufWriter::new(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            ))
+        };
+
+        Ok(SubjectsOut { writer })
+    }
+
+    /// Writes one line per subject and flushes them right away.
+    pub fn write(
+        &mut self,
+        subjects: &[String],
+        type_iri: &str,
+        depth: Option<usize>,
+    ) -> Result<(), ScratchpadError> {
+        for subject in subjects {
+            let line = SubjectLine {
+                uri: strip_brackets(subject),
+                type_iri: strip_brackets(type_iri),
+                depth,
+            };
+            serde_json::to_writer(&mut self.writer, &line)?;
+            self.writer.write_all(b"\n")?;
+        }
+
+        Ok(self.writer.flush()?)
+    }
+}
+
+fn strip_brackets(iri: &str) -> &str {
+    iri.trim_start_matches('<').trim_end_matches('>')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statement(uri: &str, type_iri: &str) -> PlannedStatement {
+        let update = format!("DELETE WHERE {{ {} ?p ?o }}", uri);
+        PlannedStatement {
+            text: format!("{};\n", update),
+            update,
+            subjects: vec![uri.to_string()],
+            type_iri: Some(type_iri.to_string()),
+            depth: Some(1),
+        }
+    }
+
+    /// A fresh directory per test, the tests run in parallel.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("output-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn minify_keeps_iris_and_literals_but_drops_layout_and_comments() {
+        let query = "DELETE {\n  ?s <http://x/p> \"a  # b\" .\n}  # trailing\nWHERE { ?s ?p ?o }";
+
+        assert_eq!(
+            minify_sparql(query),
+            "DELETE{?s <http://x/p> \"a  # b\" .}WHERE{?s ?p ?o}"
+        );
+    }
+
+    #[test]
+    fn minify_keeps_escaped_quotes_inside_literals() {
+        assert_eq!(
+            minify_sparql("FILTER(?o  !=  'it\\'s   here')"),
+            "FILTER(?o != 'it\\'s   here')"
+        );
+    }
+
+    #[test]
+    fn each_format_has_its_own_separator() {
+        let statement = "DELETE {\n  ?s ?p ?o\n}\nWHERE {\n  ?s ?p ?o\n}\n";
+
+        assert_eq!(
+            OutputFormat::Pretty.terminate(statement, None, None),
+            "DELETE {\n  ?s ?p ?o\n}\nWHERE {\n  ?s ?p ?o\n}\n\n;\n\n"
+        );
+        assert_eq!(
+            OutputFormat::Minified.terminate(statement, None, None),
+            "DELETE{?s ?p ?o}WHERE{?s ?p ?o} ;\n"
+        );
+        assert_eq!(
+            OutputFormat::Minified.terminate(statement, None, Some("\n")),
+            "DELETE{?s ?p ?o}WHERE{?s ?p ?o}\n"
+        );
+    }
+
+    #[test]
+    fn templates_wrap_the_formatted_statement_before_the_separator() {
+        let statement = "DELETE WHERE {\n  <http://x/site/4> ?p ?o .\n}\n";
+        let template = "DEFINE sql:log-enable 3\n{{statement}}\n# end";
+
+        assert_eq!(
+            OutputFormat::Pretty.terminate(statement, Some(template), None),
+            concat!(
+                "DEFINE sql:log-enable 3\nDELETE WHERE {\n  <http://x/site/4> ?p ?o .\n}\n",
+                "# end\n\n;\n\n"
+            )
+        );
+        assert_eq!(
+            OutputFormat::Minified.terminate(statement, Some("BEGIN {{statement}} END"), None),
+            "BEGIN DELETE WHERE{<http://x/site/4> ?p ?o .} END ;\n"
+        );
+    }
+
+    #[test]
+    fn separators_from_the_command_line_are_unescaped() {
+        assert_eq!(unescape_separator("\\n;\\n"), "\n;\n");
+        assert_eq!(unescape_separator("\\r\\n\\t"), "\r\n\t");
+        assert_eq!(unescape_separator("a\\\\b"), "a\\b");
+        assert_eq!(unescape_separator(";\\"), ";\\");
+        assert_eq!(unescape_separator("\\;"), ";");
+    }
+
+    #[test]
+    fn crlf_output_differs_from_lf_only_in_the_line_endings() {
+        let dir = scratch_dir("line-endings");
+        let update = "DELETE {\n  GRAPH ?g {\n    ?s ?p ?o .\n  }\n}\nWHERE {\n  VALUES ?s {\n    \
+                      <http://x/org>\n  }\n  GRAPH ?g {\n    ?s ?p ?o .\n  }\n}";
+        let text =
+            OutputFormat::Pretty.terminate(update, Some("# Organization\r\n{{statement}}"), None);
+        let write = |line_ending, name: &str| {
+            let path = dir.join(name);
+            let mut sink = OutputTarget::combined(path.clone(), false, line_ending, None).unwrap();
+            sink.write_block(&text).unwrap();
+            sink.write_note("# 1 statement\n").unwrap();
+            sink.finish().unwrap();
+            fs::read(path).unwrap()
+        };
+
+        let lf = write(LineEnding::Lf, "lf.rq");
+        let crlf = write(LineEnding::Crlf, "crlf.rq");
+
+        assert!(lf.ends_with(b"}\n\n;\n\n# 1 statement\n"));
+        assert!(crlf.ends_with(b"}\r\n\r\n;\r\n\r\n# 1 statement\r\n"));
+        // The \r\n the template already had is not doubled.
+        assert!(crlf.starts_with(b"# Organization\r\nDELETE {\r\n"));
+        let lf_lines = lf.split(|b| *b == b'\n').count();
+        assert_eq!(crlf.len(), lf.len() + lf_lines - 2);
+        assert_eq!(
+            String::from_utf8(crlf).unwrap().replace("\r\n", "\n"),
+            String::from_utf8(lf).unwrap().replace("\r\n", "\n")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn file_sink_writes_statements_and_notes_in_order() {
+        let dir = scratch_dir("combined");
+        let path = dir.join("output.txt");
+        let mut sink = OutputTarget::combined(path.clone(), false, LineEnding::Crlf, None).unwrap();
+
+        sink.write_statement(&statement("<http://x/a>", "<http://t/A>"))
+            .unwrap();
+        sink.write_block("DROP SILENT GRAPH <http://g>;\n").unwrap();
+        sink.write_note("# done\n").unwrap();
+        sink.finish().unwrap();
+
+        assert_eq!(sink.written(), 2);
+        assert_eq!(sink.files(), vec![path.clone()]);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "DELETE WHERE { <http://x/a> ?p ?o };\r\nDROP SILENT GRAPH <http://g>;\r\n# done\r\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_output_directories_are_created_and_unusable_ones_named() {
+        let dir = scratch_dir("prepare");
+        let nested = dir.join("runs").join("2026-10-15");
+        let occupied = dir.join("notes.txt");
+        fs::write(&occupied, "not a directory").unwrap();
+
+        prepare_output_dir(&nested).unwrap();
+        let below_a_file = occupied.join("sparql");
+        let refused = prepare_output_dir(&below_a_file);
+
+        assert!(nested.is_dir());
+        assert_eq!(fs::read_dir(&nested).unwrap().count(), 0);
+        let Err(ScratchpadError::Config(msg)) = refused else {
+            panic!("a directory below a file cannot be used");
+        };
+        let prefix = format!(
+            "Output directory {} cannot be created: ",
+            below_a_file.display()
+        );
+        assert!(msg.starts_with(&prefix), "{}", msg);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rerunning_into_the_same_file_replaces_it_unless_appending() {
+        let dir = scratch_dir("rerun");
+        let path = dir.join("output.txt");
+        let run = |append| {
+            let mut sink =
+                OutputTarget::combined(path.clone(), append, LineEnding::Lf, None).unwrap();
+            sink.write_statement(&statement("<http://x/a>", "<http://t/A>"))
+                .unwrap();
+            sink.finish().unwrap();
+            fs::read(&path).unwrap()
+        };
+
+        let first = run(false);
+        let second = run(false);
+        let appended = run(true);
+
+        assert_eq!(first, second);
+        assert_eq!(appended, [first.clone(), first].concat());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn gzip_output_decompresses_to_the_plain_output() {
+        use std::io::Read;
+
+        let dir = scratch_dir("gzip");
+        let run = |compression, append| {
+            let path = dir.join("output.txt");
+            let mut sink =
+                OutputTarget::combined(path, append, LineEnding::Lf, compression).unwrap();
+            for n in 0..50 {
+                let uri = format!("<http://data.example/mandataris/{}>", n);
+                sink.write_statement(&statement(&uri, "<http://t/Mandataris>"))
+                    .unwrap();
+            }
+            sink.write_note("# 50 statements\n").unwrap();
+            sink.finish().unwrap();
+            fs::read(&sink.files()[0]).unwrap()
+        };
+        let gunzip = |bytes: &[u8]| {
+            let mut out = vec![];
+            flate2::read::MultiGzDecoder::new(bytes)
+                .read_to_end(&mut out)
+                .unwrap();
+            out
+        };
+
+        let plain = run(None, false);
+        let gzipped = run(Some(Compression::Gzip), false);
+        let appended = run(Some(Compression::Gzip), true);
+
+        assert!(dir.join("output.txt.gz").exists());
+        assert!(gzipped.len() < plain.len());
+        assert_eq!(gunzip(&gzipped), plain);
+        assert_eq!(gunzip(&appended), [plain.clone(), plain].concat());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn per_type_output_groups_the_statements_by_the_type_of_their_edge() {
+        let dir = scratch_dir("per-type");
+        let besluit = "http://data.vlaanderen.be/ns/besluit#";
+        let mandaat = "http://data.vlaanderen.be/ns/mandaat#";
+        let mut sink = PerTypeOutput::new(dir.clone(), LineEnding::Lf).unwrap();
+
+        for (uri, type_iri) in [
+            (
+                "<http://x/bestuur>",
+                format!("<{}Bestuurseenheid>", besluit),
+            ),
+            (
+                "<http://x/mandataris/1>",
+                format!("<{}Mandataris>", mandaat),
+            ),
+            (
+                "<http://x/id/1>",
+                "<http://www.w3.org/ns/adms#Identifier>".to_string(),
+            ),
+            (
+                "<http://x/mandataris/2>",
+                format!("<{}Mandataris>", mandaat),
+            ),
+            (
+                "<http://x/id/2>",
+                "<http://data.example/Identifier>".to_string(),
+            ),
+        ] {
+            sink.write_statement(&statement(uri, &type_iri)).unwrap();
+        }
+        sink.write_block("DROP SILENT GRAPH <http://g/bestuur>;\n")
+            .unwrap();
+        sink.write_note("# done\n").unwrap();
+        sink.finish().unwrap();
+
+        let names = sink
+            .files()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "Bestuurseenheid.rq",
+                "Mandataris.rq",
+                "Identifier.rq",
+                "Identifier-2.rq",
+                "other.rq"
+            ]
+        );
+        assert_eq!(sink.written(), 6);
+        assert_eq!(
+            fs::read_to_string(dir.join("Mandataris.rq")).unwrap(),
+            "DELETE WHERE { <http://x/mandataris/1> ?p ?o };\n\
+             DELETE WHERE { <http://x/mandataris/2> ?p ?o };\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("other.rq")).unwrap(),
+            "DROP SILENT GRAPH <http://g/bestuur>;\n# done\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn statements_are_on_disk_before_the_run_ends() {
+        let dir = scratch_dir("partial");
+        let path = dir.join("output.txt");
+        let run = std::panic::catch_unwind(|| {
+            let mut sink =
+                OutputTarget::combined(path.clone(), false, LineEnding::Lf, None).unwrap();
+            sink.write_statement(&statement("<http://org/1>", "<http://t/Org>"))
+                .unwrap();
+            sink.write_statement(&statement("<http://org/1/site>", "<http://t/Site>"))
+                .unwrap();
+            // A killed process runs no destructors, so nothing may be left to flush on drop.
+            std::mem::forget(sink);
+            panic!("interrupted halfway through the traversal");
+        });
+
+        assert!(run.is_err());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "DELETE WHERE { <http://org/1> ?p ?o };\nDELETE WHERE { <http://org/1/site> ?p ?o };\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn subjects_out_appends_one_json_line_per_subject() {
+        let dir = scratch_dir("subjects");
+        let path = dir.join("subjects.jsonl");
+        let sites = [
+            "<http://x/site1>".to_string(),
+            "<http://x/site2>".to_string(),
+        ];
+
+        let mut out = SubjectsOut::open(&path).unwrap();
+        out.write(&["<http://x/org>".to_string()], "<http://t/Org>", Some(0))
+            .unwrap();
+        out.write(&sites, "<http://t/Site>", Some(1)).unwrap();
+        drop(out);
+        // A resumed run knows no depth for the subjects restored from its state file.
+        SubjectsOut::open(&path)
+            .unwrap()
+            .write(&["<http://x/addr1>".to_string()], "<http://t/Addr>", None)
+            .unwrap();
+
+        let lines = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            lines.lines().collect::<Vec<_>>(),
+            [
+                r#"{"uri":"http://x/org","type":"http://t/Org","depth":0}"#,
+                r#"{"uri":"http://x/site1","type":"http://t/Site","depth":1}"#,
+                r#"{"uri":"http://x/site2","type":"http://t/Site","depth":1}"#,
+                r#"{"uri":"http://x/addr1","type":"http://t/Addr","depth":null}"#,
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn split_file_sink_writes_one_file_per_statement() {
+        let dir = scratch_dir("split");
+        let mut sink = OutputTarget::split(dir.clone(), LineEnding::Lf).unwrap();
+
+        sink.write_block("first;\n").unwrap();
+        sink.write_block("second;\n").unwrap();
+        sink.write_note("# note\n").unwrap();
+
+        assert_eq!(sink.files(), [dir.join("0001.rq"), dir.join("0002.rq")]);
+        assert_eq!(fs::read_to_string(dir.join("0001.rq")).unwrap(), "first;\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("0002.rq")).unwrap(),
+            "second;\n# note\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn split_files_sort_in_the_order_the_statements_were_generated() {
+        let dir = scratch_dir("split-order");
+        let mut sink = OutputTarget::split(dir.clone(), LineEnding::Lf).unwrap();
+        let statements = (1..=12)
+            .map(|i| statement(&format!("<http://x/{}>", i), "<http://t/A>"))
+            .collect::<Vec<_>>();
+
+        for statement in &statements {
+            sink.write_statement(statement).unwrap();
+        }
+        sink.finish().unwrap();
+
+        let mut files = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(files.len(), statements.len());
+        assert_eq!(files, sink.files());
+        for (file, statement) in files.iter().zip(&statements) {
+            assert_eq!(fs::read_to_string(file).unwrap(), statement.text);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn memory_sink_keeps_whole_statements_but_no_notes() {
+        let mut sink = MemorySink::default();
+
+        sink.write_statement(&statement("<http://x/a>", "<http://t/A>"))
+            .unwrap();
+        sink.write_note("# not a statement\n").unwrap();
+        sink.write_block("CLEAR GRAPH <http://g>").unwrap();
+
+        let kept = sink.statements();
+        assert_eq!(sink.written(), 2);
+        assert_eq!(kept[0].update, "DELETE WHERE { <http://x/a> ?p ?o }");
+        assert_eq!(kept[0].subjects, ["<http://x/a>"]);
+        assert_eq!(kept[1].update, "CLEAR GRAPH <http://g>");
+        assert!(kept[1].subjects.is_empty());
+    }
+
+    #[test]
+    fn tee_copies_statements_but_reports_on_the_output() {
+        let mut output = MemorySink::default();
+        let mut queue = HttpSink::new("http://localhost/update");
+
+        let mut tee = Tee::new(&mut output, Some(&mut queue));
+        tee.write_statement(&statement("<http://x/a>", "<http://t/A>"))
+            .unwrap();
+        tee.write_statement(&statement("<http://x/b>", "<http://t/B>"))
+            .unwrap();
+        tee.finish().unwrap();
+        assert_eq!(tee.location(), "memory");
+
+        assert_eq!(output.written(), 2);
+        assert_eq!(queue.written(), 2);
+        assert_eq!(queue.location(), "http://localhost/update");
+        assert_eq!(queue.statements()[1].subjects, ["<http://x/b>"]);
+    }
+}
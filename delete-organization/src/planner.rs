@@ -0,0 +1,3545 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_stream::try_stream;
+use futures::{pin_mut, Stream, TryStreamExt};
+use indexmap::IndexMap;
+use log::{debug, warn};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::client::{HttpBackend, SparqlBackend, SparqlClient};
+use crate::config::JsonConfig;
+use crate::error::ScratchpadError;
+use crate::filter::{FollowAll, TemporalFilter, UriFilter};
+use crate::output::{DeleteStyle, OutputFormat};
+use crate::sparql_results::{parse_ask, parse_count};
+use crate::state::{TraversalState, VisitedSet};
+use crate::uri::resolve_iri;
+use crate::validate::{validate_query, validate_update};
+use crate::{
+    build_ask_query, build_delete_where_query, build_deleted_triples_query, build_drop_graph_query,
+    build_graph_size_query, build_linked_types_query, build_parametrized_delete_query,
+    build_reification_delete_query, build_rename_query, build_select, build_triple_count_query,
+    create_count_query, create_simple_reverse_parametrized_delete_query, create_type_exists_query,
+    keyset_page, parse_json_uris, reification_pattern, QueryVars,
+};
+
+/// Gives the subject that the triples of a discovered subject are moved to, for statements
+/// that rewrite the subjects instead of deleting them.
+pub trait SubjectMapping: Send + Sync {
+    /// `subject` is passed and the replacement returned as a `<...>` IRI.
+    fn replacement(&self, subject: &str) -> String;
+}
+
+/// Moves the triples of every subject to the same IRI, e.g. a tombstone.
+pub struct RenameTo(pub String);
+
+impl SubjectMapping for RenameTo {
+    fn replacement(&self, _subject: &str) -> String {
+        self.0.clone()
+    }
+}
+
+/// Options that change how a deletion is planned and emitted.
+pub struct PlannerOptions {
+    /// Periodically save the traversal state here and resume from it if it exists.
+    pub state_file: Option<PathBuf>,
+    pub format: OutputFormat,
+    pub delete_style: DeleteStyle,
+    /// Parse every generated query and statement before using it.
+    pub validate: bool,
+    /// Abort once more than this many DELETE statements would be emitted.
+    pub max_statements: Option<usize>,
+    /// Only follow and delete resources created after a cutoff.
+    pub since: Option<TemporalFilter>,
+    /// Consulted for every discovered URI before it is deleted and expanded.
+    pub uri_filter: Box<dyn UriFilter>,
+    /// Consulted for every URI that passed `uri_filter` before its edges are expanded. The
+    /// URIs it rejects are still deleted, as leaves of the traversal.
+    pub expand_filter: Box<dyn UriFilter>,
+    /// Leave out statements whose ASK counterpart says they would not delete anything.
+    pub prune_empty_blocks: bool,
+    /// Only delete triples with one of these <...> predicates, all triples when empty.
+    pub predicates: Vec<String>,
+    /// Variable names used in generated statements.
+    pub vars: QueryVars,
+    /// Rewrite the triples of every subject to the subject it maps to instead of deleting
+    /// them.
+    pub rename: Option<Box<dyn SubjectMapping>>,
+    /// Store-specific pragma put before every query, e.g. to disable reasoning. Validation
+    /// happens without it, since it is not SPARQL.
+    pub query_pragma: Option<String>,
+    /// Only follow edges in this direction, both are followed when `None`.
+    pub only_direction: Option<Direction>,
+    /// Maximum number of URIs per lookup query, unlimited when `None`.
+    pub chunk_size: Option<usize>,
+    /// Written after every statement instead of the format's default separator.
+    pub separator: Option<String>,
+    /// Text every statement is wrapped in, with the statement in place of its
+    /// `{{statement}}` placeholder.
+    pub output_template: Option<String>,
+    /// Precede every statement with a comment naming the edge that led to it.
+    pub statement_headers: bool,
+    /// Base relative IRIs in query results are resolved against.
+    pub base: Option<String>,
+    /// Record edges whose lookup fails and carry on with the others instead of aborting,
+    /// see [`DeletionPlanner::failures`].
+    pub continue_on_error: bool,
+    /// Soft cap on the URIs kept in the per-type lists of the traversal, see
+    /// [`TraversalState::flush_processed`].
+    pub max_stored_uris: Option<usize>,
+    /// Wall-clock budget of the generation, checked by the caller consuming the statements.
+    pub max_runtime: Option<Duration>,
+    /// Number of statements after which the caller stops, for a preview of the output.
+    pub head: Option<usize>,
+    /// Only follow resources that have no types besides the ones in the config.
+    pub strict_types: bool,
+    /// Collect the subjects of the whole traversal and delete them in as few statements as
+    /// the chunk size allows, instead of one statement per lookup.
+    pub flatten_output: bool,
+    /// Shuffle the edges of every type with this seed before following them, to check that
+    /// the deleted subjects do not depend on the order of the config.
+    pub traversal_seed: Option<u64>,
+    /// Fetch the results of lookups in pages of this many rows, see [`keyset_page`].
+    pub keyset_page_size: Option<usize>,
+    /// Only keep this many randomly chosen URIs of every lookup, to try a run on part of
+    /// the data. The others are neither deleted nor expanded.
+    pub sample: Option<usize>,
+    /// Seed of the random choice of `sample`, the same seed picks the same URIs.
+    pub sample_seed: u64,
+}
+
+impl Default for PlannerOptions {
+    fn default() -> Self {
+        PlannerOptions {
+            state_file: None,
+            format: OutputFormat::default(),
+            delete_style: DeleteStyle::default(),
+            validate: false,
+            max_statements: None,
+            since: None,
+            uri_filter: Box::new(FollowAll),
+            expand_filter: Box::new(FollowAll),
+            prune_empty_blocks: false,
+            predicates: vec![],
+            vars: QueryVars::default(),
+            rename: None,
+            query_pragma: None,
+            only_direction: None,
+            chunk_size: None,
+            separator: None,
+            output_template: None,
+            statement_headers: false,
+            base: None,
+            continue_on_error: false,
+            max_stored_uris: None,
+            max_runtime: None,
+            head: None,
+            strict_types: false,
+            flatten_output: false,
+            traversal_seed: None,
+            keyset_page_size: None,
+            sample: None,
+            sample_seed: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Follow triples pointing *to* the current URIs, i.e. `?s ?p ?values`.
+    Reverse,
+    /// Follow triples pointing *from* the current URIs, i.e. `?values ?p ?o`.
+    Forward,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Direction::Reverse => "reverse",
+            Direction::Forward => "forward",
+        }
+    }
+
+    /// Variable the lookup query in this direction selects the linked URIs as.
+    pub fn selected_var(self) -> &'static str {
+        match self {
+            Direction::Reverse => "s",
+            Direction::Forward => "o",
+        }
+    }
+}
+
+/// The config edge through which a subject was reached.
+#[derive(Clone, Serialize)]
+pub struct EdgeRef {
+    pub direction: Direction,
+    /// Type of the already known subjects the edge was followed from.
+    pub from_type: String,
+}
+
+#[derive(Serialize)]
+pub struct DiscoveredSubject {
+    pub type_iri: String,
+    /// `None` for the seed and for subjects restored from a state file.
+    pub via: Option<EdgeRef>,
+}
+
+/// Every subject a traversal would delete, keyed by URI in discovery order.
+#[derive(Default, Serialize)]
+pub struct DeletionGraph {
+    pub subjects: IndexMap<String, DiscoveredSubject>,
+}
+
+/// Projected amount of work of a deletion, see [`DeletionPlanner::estimate`].
+#[derive(Default)]
+pub struct Estimate {
+    /// Config types with the number of URIs they will have once they are processed.
+    pub types: Vec<(String, u64)>,
+    /// Lookup queries the traversal will send, including every chunk.
+    pub requests: u64,
+    /// Upper bound of DELETE statements, at most one per followed edge and one for the seeds.
+    pub statements: u64,
+}
+
+impl Estimate {
+    pub fn summary(&self) -> String {
+        let mut s = String::from("Estimate:\n");
+        for (type_iri, count) in &self.types {
+            s.push_str(&format!("  {:>10} URI(s) of type {}\n", count, type_iri));
+        }
+        s.push_str(&format!(
+            "  {} lookup request(s), at most {} DELETE statement(s)\n",
+            self.requests, self.statements
+        ));
+
+        s
+    }
+}
+
+/// The types of the resources linked to the seeds, see [`DeletionPlanner::linked_types`].
+#[derive(Default)]
+pub struct LinkedTypes {
+    /// Per direction and type, the number of linked URIs and whether the config already
+    /// follows that edge from the seed type. Sorted by direction, then type.
+    pub types: Vec<(Direction, String, u64, bool)>,
+}
+
+impl LinkedTypes {
+    pub fn summary(&self) -> String {
+        let mut s = String::from("Linked types (* = followed by the config):\n");
+        for (direction, type_iri, count, followed) in &self.types {
+            s.push_str(&format!(
+                "  {} {:<7} {:>10}  {}\n",
+                if *followed { "*" } else { " " },
+                direction.as_str(),
+                count,
+                type_iri
+            ));
+        }
+        if self.types.is_empty() {
+            s.push_str("  none, the seeds are not linked to any typed resource\n");
+        }
+
+        s
+    }
+}
+
+/// A DELETE statement together with the subjects it deletes.
+#[derive(Clone)]
+pub struct PlannedStatement {
+    /// The statement as it is written to the output.
+    pub text: String,
+    /// The bare statement, for executing it.
+    pub update: String,
+    pub subjects: Vec<String>,
+    /// Config type of the subjects, `None` for statements that do not delete subjects and
+    /// for the mixed subjects of --flatten-output.
+    pub type_iri: Option<String>,
+    /// Number of edges followed from the seeds to reach the subjects, see [`Discovery`].
+    pub depth: Option<usize>,
+}
+
+/// URIs of one type that were discovered for the first time in the same step.
+struct Discovery {
+    uris: Vec<String>,
+    type_iri: String,
+    via: Option<EdgeRef>,
+    /// 0 for the seeds, otherwise one more than the shallowest URI of the type the edge was
+    /// followed from. `None` for subjects restored from a state file, whose depth is not
+    /// saved.
+    depth: Option<usize>,
+}
+
+impl Discovery {
+    /// Describes where the URIs came from, for error messages.
+    fn origin(&self) -> String {
+        match &self.via {
+            Some(via) => format!(
+                "the {} edge {} -> {}",
+                via.direction.as_str(),
+                via.from_type,
+                self.type_iri
+            ),
+            None => format!("the URIs of type {}", self.type_iri),
+        }
+    }
+}
+
+/// An edge whose lookup failed with --continue-on-error. Nothing behind it was deleted.
+pub struct EdgeFailure {
+    pub edge: EdgeRef,
+    pub type_iri: String,
+    pub error: ScratchpadError,
+}
+
+impl fmt::Display for EdgeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the {} edge {} -> {}: {}",
+            self.edge.direction.as_str(),
+            self.edge.from_type,
+            self.type_iri,
+            self.error
+        )
+    }
+}
+
+/// Walks the config starting from one or more seed URIs and produces the DELETE statements
+/// for everything reachable through the configured forward/reverse types, sending its
+/// queries to `backend`.
+pub struct DeletionPlanner<B = HttpBackend> {
+    backend: B,
+    config: JsonConfig,
+    options: PlannerOptions,
+    /// Current maximum number of URIs per lookup query. Starts at the configured chunk size
+    /// and shrinks whenever the endpoint rejects a query as too large.
+    batch_size: AtomicUsize,
+    failures: Mutex<Vec<EdgeFailure>>,
+    /// Every type of the config, when discovered resources may not have any other type.
+    deletable_types: Option<Vec<String>>,
+}
+
+impl DeletionPlanner {
+    pub fn client(&self) -> &SparqlClient {
+        &self.backend.client
+    }
+}
+
+impl<B: SparqlBackend> DeletionPlanner<B> {
+    pub fn new(backend: B, config: JsonConfig, options: PlannerOptions) -> Self {
+        let deletable_types = options.strict_types.then(|| {
+            let mut types = config
+                .data
+                .iter()
+                .flat_map(|(key, value)| {
+                    edges(value)
+                        .into_iter()
+                        .map(|edge| edge.type_iri.to_string())
+                        .chain([key.clone()])
+                })
+                .collect::<Vec<_>>();
+            types.sort();
+            types.dedup();
+            types
+        });
+
+        DeletionPlanner {
+            backend,
+            config,
+            batch_size: AtomicUsize::new(options.chunk_size.unwrap_or(usize::MAX).max(1)),
+            options,
+            failures: Mutex::new(vec![]),
+            deletable_types,
+        }
+    }
+
+    pub fn options(&self) -> &PlannerOptions {
+        &self.options
+    }
+
+    /// Takes the edges that failed so far with --continue-on-error.
+    pub fn failures(&self) -> Vec<EdgeFailure> {
+        std::mem::take(&mut self.failures.lock().unwrap())
+    }
+
+    /// Runs the whole traversal and returns all DELETE statements as one string.
+    #[allow(dead_code)]
+    pub async fn build_deletion_path(
+        &self,
+        seeds: &[String],
+        uri_type: &str,
+    ) -> Result<String, ScratchpadError> {
+        let statements: Vec<PlannedStatement> = self
+            .delete_statements(seeds, uri_type)
+            .try_collect()
+            .await?;
+
+        Ok(statements
+            .into_iter()
+            .map(|statement| statement.text)
+            .collect())
+    }
+
+    /// Yields every DELETE statement as soon as the URIs it targets are discovered, so a
+    /// consumer can write or execute them without buffering the whole output.
+    ///
+    /// Each URI ends up in exactly one statement, even if several edges lead to it.
+    pub fn delete_statements<'a>(
+        &'a self,
+        seeds: &'a [String],
+        uri_type: &'a str,
+    ) -> impl Stream<Item = Result<PlannedStatement, ScratchpadError>> + 'a {
+        try_stream! {
+            let discoveries = self.discoveries(seeds, uri_type);
+            pin_mut!(discoveries);
+
+            let mut emitted = 0;
+            let mut flattened = vec![];
+            while let Some(discovery) = discoveries.try_next().await? {
+                if self.options.flatten_output {
+                    flattened.extend(discovery.uris);
+                    continue;
+                }
+                if !self.keep_statement(&discovery.uris).await? {
+                    continue;
+                }
+
+                emitted += 1;
+                self.check_statement_limit(emitted, || discovery.origin())?;
+                let origin = discovery.origin();
+                yield self.planned_statement(
+                    discovery.uris,
+                    Some(discovery.type_iri),
+                    discovery.depth,
+                    &origin,
+                )?;
+            }
+
+            flattened.sort();
+            let chunk_size = self.options.chunk_size.unwrap_or(flattened.len()).max(1);
+            for uris in flattened.chunks(chunk_size) {
+                if !self.keep_statement(uris).await? {
+                    continue;
+                }
+
+                emitted += 1;
+                self.check_statement_limit(emitted, || "the flattened output".to_string())?;
+                yield self.planned_statement(uris.to_vec(), None, None, "the whole traversal")?;
+            }
+        }
+    }
+
+    fn planned_statement(
+        &self,
+        subjects: Vec<String>,
+        type_iri: Option<String>,
+        depth: Option<usize>,
+        origin: &str,
+    ) -> Result<PlannedStatement, ScratchpadError> {
+        let update = self.delete_statement(&subjects)?;
+        let mut text = self.options.format.terminate(
+            &update,
+            self.options.output_template.as_deref(),
+            self.options.separator.as_deref(),
+        );
+        if self.options.statement_headers {
+            text.insert_str(0, &format!("# From {}\n", origin));
+        }
+
+        Ok(PlannedStatement {
+            text,
+            update,
+            subjects,
+            type_iri,
+            depth,
+        })
+    }
+
+    /// Runs the whole traversal and returns every discovered subject together with how it
+    /// was reached, e.g. to render the neighbourhood of the seed as a graph.
+    #[allow(dead_code)]
+    pub async fn deletion_graph(
+        &self,
+        seeds: &[String],
+        uri_type: &str,
+    ) -> Result<DeletionGraph, ScratchpadError> {
+        let discoveries = self.discoveries(seeds, uri_type);
+        pin_mut!(discoveries);
+
+        let mut graph = DeletionGraph::default();
+        while let Some(discovery) = discoveries.try_next().await? {
+            for uri in discovery.uris {
+                graph.subjects.insert(
+                    uri,
+                    DiscoveredSubject {
+                        type_iri: discovery.type_iri.clone(),
+                        via: discovery.via.clone(),
+                    },
+                );
+            }
+        }
+
+        Ok(graph)
+    }
+
+    /// Projects how many lookup requests a traversal from `seeds` needs without running it.
+    ///
+    /// For every config type, a COUNT query over the chain of edges leading to it tells how
+    /// many URIs the traversal will have for it, and therefore how many chunks its edges
+    /// are looked up in. Filters that are applied to the results (namespaces, --since) are
+    /// not taken into account, so the result is an upper bound.
+    pub async fn estimate(
+        &self,
+        seeds: &[String],
+        uri_type: &str,
+    ) -> Result<Estimate, ScratchpadError> {
+        let chunk_size = self.batch_size.load(Ordering::Relaxed) as u64;
+
+        // Per type, the graph patterns binding ?x to the URIs that reach it through one of
+        // the edges processed so far.
+        let mut branches: HashMap<String, Vec<String>> = HashMap::new();
+        branches.insert(
+            uri_type.to_string(),
+            vec![format!("VALUES ?x {{ {} }}", seeds.join(" "))],
+        );
+
+        let mut estimate = Estimate {
+            statements: 1,
+            ..Estimate::default()
+        };
+        for (key, value) in &self.config.data {
+            let Some(key_branches) = branches.get(key) else {
+                continue;
+            };
+            let pattern = union(key_branches);
+
+            let query = create_count_query(&pattern);
+            let r = self.query(&query).await?;
+            let count = parse_count(&r, "count")?;
+            estimate.types.push((key.clone(), count));
+            if count == 0 {
+                continue;
+            }
+
+            for edge in edges(value) {
+                if self
+                    .options
+                    .only_direction
+                    .is_some_and(|only| only != edge.direction)
+                {
+                    continue;
+                }
+
+                estimate.requests += count.div_ceil(chunk_size);
+                estimate.statements += 1;
+
+                // The subquery keeps the variables of the key's own pattern out of scope.
+                let predicate = edge.path.unwrap_or("?p");
+                let link = match edge.direction {
+                    Direction::Reverse => {
+                        format!("?x a {} ; {} ?from .", edge.type_iri, predicate)
+                    }
+                    Direction::Forward => {
+                        format!("?from {} ?x .\n?x a {} .", predicate, edge.type_iri)
+                    }
+                };
+                let link = match edge.service {
+                    Some(service) => format!("SERVICE {} {{ {} }}", service, link),
+                    None => link,
+                };
+                branches
+                    .entry(edge.type_iri.to_string())
+                    .or_default()
+                    .push(format!(
+                        "{{ SELECT DISTINCT (?x AS ?from) WHERE {{ {} }} }}\n{}",
+                        pattern, link
+                    ));
+            }
+        }
+
+        Ok(estimate)
+    }
+
+    /// Counts the URIs linked to the seeds in either direction per rdf:type, with any
+    /// predicate. Untyped resources are left out, since the config cannot name them.
+    pub async fn linked_types(
+        &self,
+        seeds: &[String],
+        uri_type: &str,
+    ) -> Result<LinkedTypes, ScratchpadError> {
+        let config_edges = self
+            .config
+            .data
+            .get(uri_type)
+            .map(edges)
+            .unwrap_or_default();
+        let values = seeds.join(" ");
+
+        let mut linked = LinkedTypes::default();
+        for direction in [Direction::Reverse, Direction::Forward] {
+            let query = build_linked_types_query(direction, &values);
+            let r = self.lookup(direction, &query.text).await?;
+            for binding in parse_json_uris(&r, query.result_var)? {
+                let (Some(type_iri), Some(count)) =
+                    (binding.uri(query.result_var), binding.value("count"))
+                else {
+                    continue;
+                };
+                let count = count.parse::<u64>().map_err(|_| {
+                    ScratchpadError::Sparql(format!(
+                        "Expected a number of instances of <{}>, got {}",
+                        type_iri, count
+                    ))
+                })?;
+                let type_iri = format!("<{}>", type_iri);
+                let followed = config_edges
+                    .iter()
+                    .any(|edge| edge.direction == direction && edge.type_iri == type_iri);
+                linked.types.push((direction, type_iri, count, followed));
+            }
+        }
+        linked
+            .types
+            .sort_by(|a, b| (a.0.as_str(), &a.1).cmp(&(b.0.as_str(), &b.1)));
+
+        Ok(linked)
+    }
+
+    /// Walks the config and yields the URIs that were not discovered before, grouped by
+    /// the edge that led to them.
+    fn discoveries<'a>(
+        &'a self,
+        seeds: &'a [String],
+        uri_type: &'a str,
+    ) -> impl Stream<Item = Result<Discovery, ScratchpadError>> + 'a {
+        try_stream! {
+            let seeds = seeds
+                .iter()
+                .map(|seed| format!("<{}>", strip_iri(seed)))
+                .collect::<Vec<_>>();
+
+            // Resume from a previous, interrupted run if a state file is present; otherwise
+            // every type in the config still needs to be processed.
+            let saved_state = match &self.options.state_file {
+                Some(path) => TraversalState::load(path)?,
+                None => None,
+            };
+            let mut state = match saved_state {
+                Some(state) => {
+                    state.check_seed(&seeds, uri_type)?;
+                    status!(
+                        "Resuming from state file, {} type(s) left to process",
+                        state.pending.len()
+                    );
+                    state
+                }
+                None => TraversalState::new(
+                    &seeds,
+                    uri_type,
+                    self.config.data.keys().cloned().collect(),
+                ),
+            };
+
+            let mut depths = seeds
+                .iter()
+                .map(|seed| (seed.clone(), 0))
+                .collect::<HashMap<_, _>>();
+
+            // Everything discovered before an interruption has to end up in the output again,
+            // for a fresh run this is just the seeds. The state keeps the types unordered, they
+            // are restored in config order so resuming always produces the same output.
+            let visited = VisitedSet::default();
+            let mut restored = state.visited.iter().chain(&state.leaves).collect::<Vec<_>>();
+            restored.sort_by_key(|(visited_type, _)| {
+                let index = self.config.data.get_index_of(*visited_type);
+                (index.unwrap_or(usize::MAX), *visited_type)
+            });
+            for (visited_type, uris) in restored {
+                let mut new_uris = visited.claim(uris);
+                new_uris.sort();
+                if !new_uris.is_empty() {
+                    let depth = new_uris
+                        .iter()
+                        .all(|uri| depths.contains_key(uri))
+                        .then_some(0);
+                    yield Discovery {
+                        uris: new_uris,
+                        type_iri: visited_type.clone(),
+                        via: None,
+                        depth,
+                    };
+                }
+            }
+
+            for (index, (key, value)) in self.config.data.iter().enumerate() {
+                if !state.pending.contains(key) {
+                    continue;
+                }
+
+                status!("{}", key);
+                let mut failed = false;
+                let mut type_edges = edges(value);
+                if let Some(seed) = self.options.traversal_seed {
+                    shuffle(&mut type_edges, seed.wrapping_add(index as u64));
+                }
+                // Edges from a type to itself add to the URIs the other edges start from, so
+                // they go first and are repeated for as long as they find new URIs. Otherwise
+                // the subjects found would depend on the order of the edges in the config.
+                let (self_edges, other_edges): (Vec<_>, Vec<_>) =
+                    type_edges.into_iter().partition(|edge| edge.type_iri == key);
+                let mut queue = self_edges
+                    .iter()
+                    .copied()
+                    .chain(other_edges)
+                    .collect::<VecDeque<_>>();
+                while let Some(Edge {
+                    direction,
+                    type_iri: item,
+                    service,
+                    path,
+                }) = queue.pop_front()
+                {
+                    if self.options.only_direction.is_some_and(|only| only != direction) {
+                        continue;
+                    }
+
+                    // Fetch URIs belonging to the current key (type).
+                    // These URIs were placed in the map in a previous step
+                    // where their type was in the reverse/forward array of a previous type.
+                    let Some(current_uris) = state.visited.get(key) else {
+                        continue;
+                    };
+
+                    // There are times where we can get duplicate results.
+                    // For example:
+                    // 1. We bundle identifiers from config-op.json.
+                    // 2. We reach the identifier key in the config and start checking
+                    // its foward and backward relationships.
+                    // 3. Identifiers can point to identifiers, which means that one or more
+                    // identifier(s) will be duplicated if they are pointed to by other identifiers.
+                    //
+                    // Deduplicate them so each URI only appears once in the VALUES list, and
+                    // spelling variants of a URI only under the first spelling, sorted so the
+                    // same inputs always produce the same queries.
+                    let first_spellings = visited.first_spellings(current_uris);
+                    let values_list = first_spellings
+                        .iter()
+                        .map(|v| v.as_str())
+                        .collect::<Vec<_>>();
+                    let depth = values_list
+                        .iter()
+                        .filter_map(|uri| depths.get(*uri))
+                        .min()
+                        .map(|depth| depth + 1);
+
+                    let neighbours =
+                        self.fetch_neighbours(direction, &values_list, item, service, path);
+                    let mut discovered = match neighbours.await {
+                        Ok(discovered) => discovered,
+                        Err(error) if self.options.continue_on_error => {
+                            let failure = EdgeFailure {
+                                edge: EdgeRef {
+                                    direction,
+                                    from_type: key.clone(),
+                                },
+                                type_iri: item.to_string(),
+                                error,
+                            };
+                            warn!("Skipping {}", failure);
+                            self.failures.lock().unwrap().push(failure);
+                            failed = true;
+                            continue;
+                        }
+                        Err(error) => Err(error)?,
+                    };
+                    discovered.retain(|uri| {
+                        self.options.uri_filter.should_follow(strip_iri(uri), strip_iri(item))
+                    });
+                    // A reflexive triple (`?s ?p ?s`) links a URI to itself. Over an edge
+                    // within one type, the URI is already being expanded under that type,
+                    // so it is not recorded for it again.
+                    if item == key {
+                        discovered.retain(|uri| values_list.binary_search(&uri.as_str()).is_err());
+                    }
+                    if let Some(sample) = self.options.sample {
+                        if discovered.len() > sample {
+                            // Sorted first, since the endpoint's order is not stable.
+                            discovered.sort();
+                            let seed = self.options.sample_seed.wrapping_add(index as u64);
+                            shuffle(&mut discovered, seed);
+                            discovered.truncate(sample);
+                        }
+                    }
+
+                    // Endpoints return results in no particular order, sort them so
+                    // re-running on the same data produces byte-identical output, and the
+                    // same spelling of a URI is expanded when the store holds several.
+                    discovered.sort();
+                    let new_uris = visited.claim(&discovered);
+
+                    // URIs that were already visited through another type still need this
+                    // type's edges expanded, so every discovered URI is recorded under it,
+                    // apart from the leaves that are not expanded at all.
+                    let (expanded, leaves): (Vec<_>, Vec<_>) =
+                        discovered.into_iter().partition(|uri| {
+                            self.options
+                                .expand_filter
+                                .should_follow(strip_iri(uri), strip_iri(item))
+                        });
+                    if item == key && !expanded.is_empty() {
+                        for edge in self_edges.iter().rev() {
+                            if !queue.contains(edge) {
+                                queue.push_front(*edge);
+                            }
+                        }
+                    }
+                    state
+                        .visited
+                        .entry(item.to_string())
+                        .or_default()
+                        .extend(expanded);
+                    if !leaves.is_empty() {
+                        state
+                            .leaves
+                            .entry(item.to_string())
+                            .or_default()
+                            .extend(leaves);
+                    }
+
+                    if !new_uris.is_empty() {
+                        if let Some(depth) = depth {
+                            for uri in &new_uris {
+                                depths.insert(uri.clone(), depth);
+                            }
+                        }
+                        yield Discovery {
+                            uris: new_uris,
+                            type_iri: item.to_string(),
+                            via: Some(EdgeRef {
+                                direction,
+                                from_type: key.clone(),
+                            }),
+                            depth,
+                        };
+                    }
+                }
+
+                // Checkpoint after every processed type so an interrupted run only redoes
+                // the type it was working on. A type with a failed edge stays pending, so
+                // resuming retries it.
+                if !failed {
+                    state.pending.retain(|pending_type| pending_type != key);
+                }
+                // The deduplication set keeps every URI regardless, only the lists are dropped.
+                if let Some(max) = self.options.max_stored_uris {
+                    let stored = state.stored_uris();
+                    if stored > max {
+                        let dropped = state.flush_processed();
+                        debug!(
+                            "{} stored URIs exceed {}, dropped the {} of processed types",
+                            stored, max, dropped
+                        );
+                    }
+                }
+                if let Some(path) = &self.options.state_file {
+                    state.save(path)?;
+                }
+            }
+
+            // The traversal completed, so there is nothing left to resume unless an edge
+            // failed.
+            if let Some(path) = &self.options.state_file {
+                if state.pending.is_empty() {
+                    std::fs::remove_file(path)?;
+                }
+            }
+        }
+    }
+
+    /// Returns the URIs, as the store spells them, of type `uri_type` linked to
+    /// `values_list` in the given direction, looked up through `service` if the edge has one.
+    ///
+    /// The URIs are looked up in batches. When the endpoint rejects a batch as too large,
+    /// the batch size is halved for the rest of the run and the batch is retried, down to
+    /// a single URI.
+    async fn fetch_neighbours(
+        &self,
+        direction: Direction,
+        values_list: &[&str],
+        uri_type: &str,
+        service: Option<&str>,
+        path: Option<&str>,
+    ) -> Result<Vec<String>, ScratchpadError> {
+        let mut uris = vec![];
+        let mut remaining = values_list;
+
+        while !remaining.is_empty() {
+            let size = self.batch_size.load(Ordering::Relaxed).min(remaining.len());
+            let (batch, rest) = remaining.split_at(size);
+
+            match self
+                .fetch_batch(direction, &batch.join("\n"), uri_type, service, path)
+                .await
+            {
+                Ok(found) => {
+                    uris.extend(found);
+                    remaining = rest;
+                }
+                Err(ScratchpadError::QueryTooLarge(msg)) if size > 1 => {
+                    warn!("{}, retrying with batches of {} URIs", msg, size / 2);
+                    self.batch_size.store(size / 2, Ordering::Relaxed);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(uris)
+    }
+
+    async fn fetch_batch(
+        &self,
+        direction: Direction,
+        values_list: &str,
+        uri_type: &str,
+        service: Option<&str>,
+        path: Option<&str>,
+    ) -> Result<Vec<String>, ScratchpadError> {
+        let query = build_select(
+            direction,
+            values_list,
+            Some(uri_type),
+            self.deletable_types.as_deref(),
+            service,
+            path,
+            self.options.since.as_ref(),
+        );
+
+        let mut uris = vec![];
+        let mut after: Option<String> = None;
+        loop {
+            let text = match self.options.keyset_page_size {
+                Some(page_size) => Cow::Owned(keyset_page(&query, after.as_deref(), page_size)),
+                None => Cow::Borrowed(&query.text),
+            };
+            let r = self.lookup(direction, &text).await?;
+            let bindings = parse_json_uris(&r, query.result_var)?;
+            for s in bindings.iter().filter_map(|v| v.uri(query.result_var)) {
+                let s = resolve_iri(s, self.options.base.as_deref())?;
+                uris.push(format!("<{}>", s));
+            }
+
+            // A full page may be followed by more, which start after its last IRI as the
+            // store spells it.
+            after = match self.options.keyset_page_size {
+                Some(page_size) if bindings.len() >= page_size => bindings
+                    .last()
+                    .and_then(|v| v.uri(query.result_var))
+                    .map(str::to_string),
+                _ => None,
+            };
+            if after.is_none() {
+                break;
+            }
+        }
+
+        Ok(uris)
+    }
+
+    /// Guards against runaway configs (e.g. a type that matches everything) filling the disk.
+    fn check_statement_limit(
+        &self,
+        emitted: usize,
+        origin: impl FnOnce() -> String,
+    ) -> Result<(), ScratchpadError> {
+        match self.options.max_statements {
+            Some(max) if emitted > max => Err(ScratchpadError::Limit(format!(
+                "More than {} DELETE statements generated, the last one was caused by {}. \
+                 Check the config or raise --max-statements",
+                max,
+                origin()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// With --prune-empty-blocks, whether a statement for `uris` would delete any triple.
+    async fn keep_statement(&self, uris: &[String]) -> Result<bool, ScratchpadError> {
+        if !self.options.prune_empty_blocks {
+            return Ok(true);
+        }
+
+        self.has_triples(uris, self.options.since.as_ref()).await
+    }
+
+    /// The types of the config, as key or edge, that have no instance in the store. Edge types
+    /// with a `service` are looked up in that service.
+    pub async fn types_without_instances(&self) -> Result<Vec<String>, ScratchpadError> {
+        let mut types = vec![];
+        for (key, value) in &self.config.data {
+            types.push((key.as_str(), None));
+            types.extend(
+                edges(value)
+                    .into_iter()
+                    .map(|edge| (edge.type_iri, edge.service)),
+            );
+        }
+        types.sort();
+        types.dedup();
+
+        let mut missing = vec![];
+        for (type_iri, service) in types {
+            let r = self
+                .query(&create_type_exists_query(type_iri, service))
+                .await?;
+            if !parse_ask(&r)? {
+                missing.push(type_iri.to_string());
+            }
+        }
+        missing.dedup();
+
+        Ok(missing)
+    }
+
+    /// Returns the subjects that still have triples a DELETE statement would have removed,
+    /// to check whether executed statements actually took effect.
+    pub async fn remaining_subjects(
+        &self,
+        subjects: &[String],
+    ) -> Result<Vec<String>, ScratchpadError> {
+        let mut remaining = vec![];
+        for subject in subjects {
+            // The provenance triple is deleted along with the rest, so --since no longer
+            // applies once the statements have been executed.
+            if self
+                .has_triples(std::slice::from_ref(subject), None)
+                .await?
+            {
+                remaining.push(subject.clone());
+            }
+        }
+
+        Ok(remaining)
+    }
+
+    /// Adds the number of triples per predicate a statement for `subjects` deletes to
+    /// `counts`, and per named graph to `graphs` unless statements target the default graph.
+    pub async fn count_predicates(
+        &self,
+        subjects: &[String],
+        counts: &mut HashMap<String, u64>,
+        graphs: &mut HashMap<String, u64>,
+    ) -> Result<(), ScratchpadError> {
+        let vars = &self.options.vars;
+        match (&vars.g, &vars.graph) {
+            (Some(g), _) => {
+                self.count_deleted_triples(subjects, &mut [(&vars.p, counts), (g, graphs)])
+                    .await
+            }
+            (None, Some(graph)) => {
+                let mut deleted = HashMap::new();
+                self.count_deleted_triples(subjects, &mut [(&vars.p, &mut deleted)])
+                    .await?;
+                *graphs.entry(graph.clone()).or_default() += deleted.values().sum::<u64>();
+                for (predicate, count) in deleted {
+                    *counts.entry(predicate).or_default() += count;
+                }
+                Ok(())
+            }
+            (None, None) => {
+                self.count_deleted_triples(subjects, &mut [(&vars.p, counts)])
+                    .await
+            }
+        }
+    }
+
+    /// Adds the number of triples per named graph a statement for `subjects` deletes to
+    /// `counts`. Does nothing when statements target the default graph.
+    pub async fn count_graph_triples(
+        &self,
+        subjects: &[String],
+        counts: &mut HashMap<String, u64>,
+    ) -> Result<(), ScratchpadError> {
+        let vars = &self.options.vars;
+        match (&vars.g, &vars.graph) {
+            (Some(g), _) => {
+                self.count_deleted_triples(subjects, &mut [(g, counts)])
+                    .await
+            }
+            // Statements only delete from the one graph, so every triple is counted for it.
+            (None, Some(graph)) => {
+                let mut deleted = HashMap::new();
+                self.count_deleted_triples(subjects, &mut [(&vars.p, &mut deleted)])
+                    .await?;
+                *counts.entry(graph.clone()).or_default() += deleted.values().sum::<u64>();
+                Ok(())
+            }
+            (None, None) => Ok(()),
+        }
+    }
+
+    /// Counts the triples a statement for `subjects` deletes, grouped by every variable of
+    /// `counts`, and adds the totals per value of each variable to its map.
+    async fn count_deleted_triples(
+        &self,
+        subjects: &[String],
+        counts: &mut [(&str, &mut HashMap<String, u64>)],
+    ) -> Result<(), ScratchpadError> {
+        // Statements that delete whole graphs have no subjects.
+        if subjects.is_empty() {
+            return Ok(());
+        }
+        let group_by = counts
+            .iter()
+            .map(|(var, _)| *var)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let query = build_triple_count_query(
+            &values_block(subjects),
+            &self.options.predicates,
+            self.options.since.as_ref(),
+            &self.options.vars,
+            &group_by,
+        );
+        let r = self.query(&query).await?;
+
+        for (var, counts) in counts {
+            add_counts(&r, &var[1..], counts)?;
+        }
+
+        Ok(())
+    }
+
+    /// The triples a statement for `subjects` deletes, as sorted N-Quads lines (N-Triples
+    /// when statements target the default graph).
+    pub async fn deleted_quads(&self, subjects: &[String]) -> Result<Vec<String>, ScratchpadError> {
+        if subjects.is_empty() {
+            return Ok(vec![]);
+        }
+        let vars = &self.options.vars;
+        let query = build_deleted_triples_query(
+            &values_block(subjects),
+            &self.options.predicates,
+            self.options.since.as_ref(),
+            vars,
+        );
+        let r = self.query(&query).await?;
+
+        let mut quads = parse_json_uris(&r, &vars.s[1..])?
+            .iter()
+            .filter_map(|binding| {
+                let mut terms = [&vars.s, &vars.p, &vars.o]
+                    .into_iter()
+                    .chain(&vars.g)
+                    .map(|var| binding.term(&var[1..]).map(|term| term.to_nquads()))
+                    .collect::<Option<Vec<_>>>()?;
+                terms.extend(vars.graph.clone());
+                Some(format!("{} .", terms.join(" ")))
+            })
+            .collect::<Vec<_>>();
+        quads.sort();
+
+        Ok(quads)
+    }
+
+    /// Returns the graphs among the keys of `deleted` that have no triples left once the
+    /// number of triples in `deleted` is removed from them, sorted.
+    pub async fn emptied_graphs(
+        &self,
+        deleted: &HashMap<String, u64>,
+    ) -> Result<Vec<String>, ScratchpadError> {
+        let mut graphs = deleted.keys().cloned().collect::<Vec<_>>();
+        graphs.sort();
+
+        let mut sizes = HashMap::new();
+        for chunk in graphs.chunks(self.options.chunk_size.unwrap_or(graphs.len()).max(1)) {
+            let r = self
+                .query(&build_graph_size_query(&values_block(chunk)))
+                .await?;
+            add_counts(&r, "g", &mut sizes)?;
+        }
+
+        Ok(graphs
+            .into_iter()
+            .filter(|graph| sizes.get(graph).is_some_and(|size| *size <= deleted[graph]))
+            .collect())
+    }
+
+    /// A statement dropping `graph`, to run after the statements that empty it.
+    pub fn drop_graph_statement(&self, graph: &str) -> PlannedStatement {
+        let update = build_drop_graph_query(graph);
+        let mut text = self.options.format.terminate(
+            &update,
+            self.options.output_template.as_deref(),
+            self.options.separator.as_deref(),
+        );
+        if self.options.statement_headers {
+            text.insert_str(0, "# Graph emptied by the statements above\n");
+        }
+
+        PlannedStatement {
+            text,
+            update,
+            subjects: vec![],
+            type_iri: None,
+            depth: None,
+        }
+    }
+
+    /// Statements deleting the reification nodes (`rdf:Statement`s) of statements about any
+    /// of `subjects`, one per chunk of subjects that has any, together with the number of
+    /// nodes they delete.
+    pub async fn reification_statements(
+        &self,
+        subjects: &[String],
+    ) -> Result<(Vec<PlannedStatement>, u64), ScratchpadError> {
+        let mut statements = vec![];
+        let mut nodes = 0;
+        let chunk_size = self.options.chunk_size.unwrap_or(subjects.len()).max(1);
+        for chunk in subjects.chunks(chunk_size) {
+            let values = values_block(chunk);
+            let r = self
+                .query(&create_count_query(&reification_pattern(&values)))
+                .await?;
+            let count = parse_count(&r, "count")?;
+            if count == 0 {
+                continue;
+            }
+            nodes += count;
+
+            let update = build_reification_delete_query(&values, &self.options.vars);
+            if self.options.validate {
+                validate_update(&update)?;
+            }
+            let mut text = self.options.format.terminate(
+                &update,
+                self.options.output_template.as_deref(),
+                self.options.separator.as_deref(),
+            );
+            if self.options.statement_headers {
+                text.insert_str(
+                    0,
+                    &format!("# {} reification node(s) of deleted subjects\n", count),
+                );
+            }
+            statements.push(PlannedStatement {
+                text,
+                update,
+                subjects: vec![],
+                type_iri: None,
+                depth: None,
+            });
+        }
+
+        Ok((statements, nodes))
+    }
+
+    /// A statement deleting the triples of other subjects that point to `seed`, which the
+    /// traversal leaves alone unless the config follows them.
+    pub fn incoming_statement(&self, seed: &str) -> PlannedStatement {
+        let update = create_simple_reverse_parametrized_delete_query(seed, &self.options.vars);
+        let mut text = self.options.format.terminate(
+            &update,
+            self.options.output_template.as_deref(),
+            self.options.separator.as_deref(),
+        );
+        if self.options.statement_headers {
+            text.insert_str(0, &format!("# Triples pointing to {}\n", seed));
+        }
+
+        PlannedStatement {
+            text,
+            update,
+            subjects: vec![],
+            type_iri: None,
+            depth: None,
+        }
+    }
+
+    /// Asks whether any of `uris` has triples matching the deleted pattern. Anything but an
+    /// ASK answer is an error, so a failing endpoint is not taken for either answer.
+    async fn has_triples(
+        &self,
+        uris: &[String],
+        since: Option<&TemporalFilter>,
+    ) -> Result<bool, ScratchpadError> {
+        let query = build_ask_query(
+            &values_block(uris),
+            &self.options.predicates,
+            since,
+            &self.options.vars,
+        );
+        let r = self.query(&query).await?;
+
+        parse_ask(&r)
+    }
+
+    /// Sends a generated query, validated first if requested and preceded by the query
+    /// pragma if there is one.
+    async fn query(&self, query: &str) -> Result<Value, ScratchpadError> {
+        let query = self.prepare_query(query)?;
+        self.backend.query(&query).await
+    }
+
+    /// Like [`Self::query`], for a lookup following edges in `direction`.
+    async fn lookup(&self, direction: Direction, query: &str) -> Result<Value, ScratchpadError> {
+        let query = self.prepare_query(query)?;
+        self.backend.lookup(direction, &query).await
+    }
+
+    fn prepare_query<'q>(&self, query: &'q str) -> Result<Cow<'q, str>, ScratchpadError> {
+        if self.options.validate {
+            validate_query(query)?;
+        }
+
+        Ok(match &self.options.query_pragma {
+            Some(pragma) => Cow::Owned(format!("{}\n{}", pragma, query)),
+            None => Cow::Borrowed(query),
+        })
+    }
+
+    /// A statement moving the triples of `uris` to the subjects they map to.
+    fn rename_statement(&self, uris: &[String]) -> String {
+        let mapping = self
+            .options
+            .rename
+            .as_ref()
+            .expect("renaming is configured");
+        let pairs = uris
+            .iter()
+            .map(|uri| (uri.clone(), mapping.replacement(uri)))
+            .collect::<Vec<_>>();
+
+        build_rename_query(
+            &pairs,
+            &self.options.predicates,
+            self.options.since.as_ref(),
+            &self.options.vars,
+        )
+    }
+
+    /// Builds the bare SPARQL update deleting `uris`, without any output formatting.
+    fn delete_statement(&self, uris: &[String]) -> Result<String, ScratchpadError> {
+        // The shorthand only works if the WHERE pattern is exactly the deleted pattern.
+        let statement = match (self.options.delete_style, &self.options.since) {
+            _ if self.options.rename.is_some() => self.rename_statement(uris),
+            // `WITH` cannot be combined with the shorthand.
+            (DeleteStyle::DeleteWhere, None)
+                if self.options.predicates.is_empty() && self.options.vars.graph.is_none() =>
+            {
+                build_delete_where_query(uris, &self.options.vars)
+            }
+            _ => build_parametrized_delete_query(
+                &values_block(uris),
+                &self.options.predicates,
+                self.options.since.as_ref(),
+                &self.options.vars,
+            ),
+        };
+        if self.options.validate {
+            validate_update(&statement)?;
+        }
+
+        Ok(statement)
+    }
+}
+
+/// Combines graph patterns into a single UNION.
+fn union(branches: &[String]) -> String {
+    branches
+        .iter()
+        .map(|branch| format!("{{ {} }}", branch))
+        .collect::<Vec<_>>()
+        .join("\nUNION\n")
+}
+
+/// Lists `uris` one per line, indented to sit inside a top-level VALUES block.
+fn values_block(uris: &[String]) -> String {
+    uris.iter()
+        .map(|v| format!("    {}", v))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Adds the `?count` of every binding of a grouped COUNT query to the entry of the IRI
+/// bound to `var`.
+fn add_counts(
+    r: &Value,
+    var: &str,
+    counts: &mut HashMap<String, u64>,
+) -> Result<(), ScratchpadError> {
+    for binding in parse_json_uris(r, var)? {
+        let (Some(iri), Some(count)) = (binding.uri(var), binding.value("count")) else {
+            continue;
+        };
+        let count = count.parse::<u64>().map_err(|_| {
+            ScratchpadError::Sparql(format!("Expected a number of triples, got {}", count))
+        })?;
+        *counts.entry(format!("<{}>", iri)).or_default() += count;
+    }
+
+    Ok(())
+}
+
+/// Deterministic Fisher-Yates shuffle driven by splitmix64, so a seed always produces the
+/// same order.
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut state = seed;
+    for i in (1..items.len()).rev() {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        items.swap(i, (z % (i as u64 + 1)) as usize);
+    }
+}
+
+/// Removes the `<>` around an IRI.
+fn strip_iri(iri: &str) -> &str {
+    iri.trim_start_matches('<').trim_end_matches('>')
+}
+
+/// The types of `config` in the order they are processed, each with the edges it follows.
+/// Flags types the seed type never leads to, and edges whose types are not expanded any
+/// further because they have no config entry or are already processed by then.
+pub fn explain(config: &JsonConfig, seed_type: Option<&str>, only: Option<Direction>) -> String {
+    let mut s = match seed_type {
+        Some(seed_type) => format!("Traversal plan from {}:\n", seed_type),
+        None => "Traversal plan (pass --type to see which types are reached):\n".to_string(),
+    };
+    let mut reached = seed_type.into_iter().collect::<HashSet<_>>();
+
+    for (i, (key, value)) in config.data.iter().enumerate() {
+        if seed_type.is_some() && !reached.contains(key.as_str()) {
+            s.push_str(&format!("{:>3}. {}  (not reached, skipped)\n", i + 1, key));
+            continue;
+        }
+        s.push_str(&format!("{:>3}. {}\n", i + 1, key));
+
+        for edge in edges(value) {
+            if only.is_some_and(|only| only != edge.direction) {
+                continue;
+            }
+            reached.insert(edge.type_iri);
+
+            let mut line = format!("       {:<7} {}", edge.direction.as_str(), edge.type_iri);
+            if let Some(path) = edge.path {
+                line.push_str(&format!(" via {}", path));
+            }
+            if let Some(service) = edge.service {
+                line.push_str(&format!(" in SERVICE {}", service));
+            }
+            match config.data.get_index_of(edge.type_iri) {
+                None => line.push_str("  (no config entry, deleted but not expanded)"),
+                Some(j) if j <= i => line.push_str("  (already processed, not expanded)"),
+                Some(_) => {}
+            }
+            s.push_str(&line);
+            s.push('\n');
+        }
+    }
+
+    s
+}
+
+/// An entry of a `reverse` or `forward` array in the config.
+#[derive(Clone, Copy, PartialEq)]
+struct Edge<'a> {
+    direction: Direction,
+    type_iri: &'a str,
+    /// Endpoint holding the linked resources, queried through a `SERVICE` block.
+    service: Option<&'a str>,
+    /// Property path linking the resources, instead of any predicate.
+    path: Option<&'a str>,
+}
+
+/// Lists the edges of a config entry, reverse ones first. An edge is either a type or an
+/// object `{ "type": ..., "service": ..., "path": ... }`.
+fn edges(value: &Value) -> Vec<Edge<'_>> {
+    let mut edges = vec![];
+
+    for (direction, field) in [
+        (Direction::Reverse, "reverse"),
+        (Direction::Forward, "forward"),
+    ] {
+        if let Some(array) = value.get(field).and_then(|v| v.as_array()) {
+            edges.extend(array.iter().filter_map(|item| match item {
+                Value::String(type_iri) => Some(Edge {
+                    direction,
+                    type_iri,
+                    service: None,
+                    path: None,
+                }),
+                Value::Object(edge) => Some(Edge {
+                    direction,
+                    type_iri: edge.get("type")?.as_str()?,
+                    service: edge.get("service").and_then(|s| s.as_str()),
+                    path: edge.get("path").and_then(|p| p.as_str()),
+                }),
+                _ => None,
+            }));
+        }
+    }
+
+    edges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use serde_json::json;
+    use spargebra::GraphUpdateOperation;
+
+    use crate::filter::NamespaceFilter;
+
+    /// Answers the lookups of a traversal from a handful of triples, and every other query
+    /// with an empty result. Records the direction of every query it gets.
+    #[derive(Default)]
+    struct TripleStore {
+        triples: Vec<(&'static str, &'static str)>,
+        types: Vec<(&'static str, &'static str)>,
+        calls: Mutex<Vec<Option<Direction>>>,
+        /// The text of every query, in the order they were sent.
+        sent: Mutex<Vec<String>>,
+        /// Lookups in this direction fail, as if the store went down mid-run.
+        failing: Option<Direction>,
+        /// URIs without triples left, which ASK queries answer no for.
+        emptied: Vec<&'static str>,
+        /// Lookups with more values are refused as too large.
+        max_values: Option<usize>,
+    }
+
+    impl TripleStore {
+        fn link(mut self, subject: &'static str, object: &'static str) -> Self {
+            self.triples.push((subject, object));
+            self
+        }
+
+        fn typed(mut self, uri: &'static str, type_iri: &'static str) -> Self {
+            self.types.push((uri, type_iri));
+            self
+        }
+
+        fn failing(mut self, direction: Direction) -> Self {
+            self.failing = Some(direction);
+            self
+        }
+
+        fn emptied(mut self, uri: &'static str) -> Self {
+            self.emptied.push(uri);
+            self
+        }
+
+        fn refusing_over(mut self, max_values: usize) -> Self {
+            self.max_values = Some(max_values);
+            self
+        }
+
+        fn has_type(&self, uri: &str, type_iri: &str) -> bool {
+            self.types.contains(&(uri, type_iri))
+        }
+
+        /// The `?values`, type and allowed types of a query from `build_select`.
+        fn answer(&self, direction: Direction, sparql: &str) -> Value {
+            let values = sparql
+                .split_once("VALUES ?values {")
+                .and_then(|(_, rest)| rest.split_once('}'))
+                .map(|(values, _)| values.split_whitespace().collect::<Vec<_>>())
+                .unwrap_or_default();
+            let type_iri = sparql
+                .split_once(" a ")
+                .and_then(|(_, rest)| rest.split_whitespace().next())
+                .unwrap_or_default();
+            // Set by --strict-types.
+            let allowed_types = sparql
+                .split_once("?other_type NOT IN (")
+                .and_then(|(_, rest)| rest.split_once(')'))
+                .map(|(types, _)| types.split(", ").collect::<Vec<_>>());
+
+            let var = direction.selected_var();
+            let mut found = self
+                .triples
+                .iter()
+                .filter_map(|&(s, o)| match direction {
+                    Direction::Reverse => values.contains(&o).then_some(s),
+                    Direction::Forward => values.contains(&s).then_some(o),
+                })
+                .filter(|uri| self.has_type(uri, type_iri))
+                .filter(|uri| {
+                    allowed_types.as_ref().is_none_or(|allowed| {
+                        self.types
+                            .iter()
+                            .all(|&(typed, t)| typed != *uri || allowed.contains(&t))
+                    })
+                })
+                .collect::<Vec<_>>();
+            found.dedup();
+            let bindings = found
+                .iter()
+                .map(|uri| json!({ var: { "type": "uri", "value": strip_iri(uri) } }))
+                .collect::<Vec<_>>();
+
+            json!({ "head": { "vars": [var] }, "results": { "bindings": bindings } })
+        }
+    }
+
+    impl SparqlBackend for TripleStore {
+        async fn query(&self, sparql: &str) -> Result<Value, ScratchpadError> {
+            self.calls.lock().unwrap().push(None);
+            self.sent.lock().unwrap().push(sparql.to_string());
+            if let Some(ask) = sparql.strip_prefix("ASK {\n  VALUES ?s {") {
+                let (values, _) = ask.split_once('}').unwrap();
+                let any_left = values
+                    .split_whitespace()
+                    .any(|uri| !self.emptied.contains(&uri));
+                return Ok(json!({ "head": {}, "boolean": any_left }));
+            }
+            Ok(json!({ "head": { "vars": [] }, "results": { "bindings": [] } }))
+        }
+
+        async fn lookup(
+            &self,
+            direction: Direction,
+            sparql: &str,
+        ) -> Result<Value, ScratchpadError> {
+            self.calls.lock().unwrap().push(Some(direction));
+            self.sent.lock().unwrap().push(sparql.to_string());
+            if self.failing == Some(direction) {
+                return Err(ScratchpadError::Sparql("connection reset".to_string()));
+            }
+            let values = sparql
+                .split_once("VALUES ?values {")
+                .and_then(|(_, rest)| rest.split_once('}'))
+                .map_or(0, |(values, _)| values.split_whitespace().count());
+            if self.max_values.is_some_and(|max| values > max) {
+                return Err(ScratchpadError::QueryTooLarge("413".to_string()));
+            }
+            Ok(self.answer(direction, sparql))
+        }
+    }
+
+    /// Organizations with sites pointing to them, and the sites' addresses.
+    fn org_store() -> TripleStore {
+        TripleStore::default()
+            .typed("<http://x/org>", "<http://t/Org>")
+            .typed("<http://x/site1>", "<http://t/Site>")
+            .typed("<http://x/site2>", "<http://t/Site>")
+            .typed("<http://x/addr1>", "<http://t/Addr>")
+            .link("<http://x/site1>", "<http://x/org>")
+            .link("<http://x/site2>", "<http://x/org>")
+            .link("<http://x/site1>", "<http://x/addr1>")
+    }
+
+    fn org_config() -> JsonConfig {
+        serde_json::from_value(json!({
+            "<http://t/Org>": { "reverse": ["<http://t/Site>"], "forward": [] },
+            "<http://t/Site>": { "reverse": [], "forward": ["<http://t/Addr>"] }
+        }))
+        .unwrap()
+    }
+
+    async fn plan(planner: &DeletionPlanner<TripleStore>) -> Vec<PlannedStatement> {
+        let seeds = ["<http://x/org>".to_string()];
+        planner
+            .delete_statements(&seeds, "<http://t/Org>")
+            .try_collect()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn lookups_are_sent_with_the_direction_they_follow() {
+        let planner = DeletionPlanner::new(org_store(), org_config(), PlannerOptions::default());
+
+        let statements = plan(&planner).await;
+
+        let subjects = statements
+            .iter()
+            .map(|statement| statement.subjects.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            subjects,
+            [
+                vec!["<http://x/org>"],
+                vec!["<http://x/site1>", "<http://x/site2>"],
+                vec!["<http://x/addr1>"],
+            ]
+        );
+        assert_eq!(
+            *planner.backend.calls.lock().unwrap(),
+            [Some(Direction::Reverse), Some(Direction::Forward)]
+        );
+    }
+
+    #[tokio::test]
+    async fn every_spelling_is_deleted_but_expanded_once() {
+        let store = org_store()
+            .typed("<HTTP://Example.org/a%7Eb>", "<http://t/Site>")
+            .typed("<http://example.org/a~b>", "<http://t/Site>")
+            .typed("<http://x/addr2>", "<http://t/Addr>")
+            .link("<HTTP://Example.org/a%7Eb>", "<http://x/org>")
+            .link("<http://example.org/a~b>", "<http://x/org>")
+            .link("<HTTP://Example.org/a%7Eb>", "<http://x/addr2>");
+        let planner = DeletionPlanner::new(store, org_config(), PlannerOptions::default());
+
+        let statements = plan(&planner).await;
+
+        assert_eq!(
+            statements[1].subjects,
+            [
+                "<HTTP://Example.org/a%7Eb>",
+                "<http://example.org/a~b>",
+                "<http://x/site1>",
+                "<http://x/site2>"
+            ]
+        );
+        assert!(statements[1]
+            .update
+            .contains("    <HTTP://Example.org/a%7Eb>\n"));
+        assert!(statements[1]
+            .update
+            .contains("    <http://example.org/a~b>\n"));
+        assert_eq!(
+            statements[2].subjects,
+            ["<http://x/addr1>", "<http://x/addr2>"]
+        );
+        let sent = planner.backend.sent.lock().unwrap();
+        let address_lookup = sent
+            .iter()
+            .find(|query| query.contains("<http://t/Addr>"))
+            .unwrap();
+        assert!(address_lookup.contains("<HTTP://Example.org/a%7Eb>"));
+        assert!(!address_lookup.contains("a~b"));
+    }
+
+    #[tokio::test]
+    async fn deletion_graph_records_how_each_subject_was_reached() {
+        let store = TripleStore::default()
+            .typed("<http://x/team>", "<http://t/Team>")
+            .typed("<http://x/ann>", "<http://t/Member>")
+            .typed("<http://x/badge>", "<http://t/Badge>")
+            .typed("<http://x/bob>", "<http://t/Member>")
+            .link("<http://x/ann>", "<http://x/team>")
+            .link("<http://x/ann>", "<http://x/badge>")
+            .link("<http://x/bob>", "<http://x/other-team>");
+        // Parsed from text, a `json!` map would sort the types and process members first.
+        let config = serde_json::from_str(
+            r#"{
+                "<http://t/Team>": { "reverse": ["<http://t/Member>"], "forward": [] },
+                "<http://t/Member>": { "reverse": [], "forward": ["<http://t/Badge>"] }
+            }"#,
+        )
+        .unwrap();
+        let planner = DeletionPlanner::new(store, config, PlannerOptions::default());
+
+        let graph = planner
+            .deletion_graph(&["<http://x/team>".to_string()], "<http://t/Team>")
+            .await
+            .unwrap();
+
+        let nodes = graph
+            .subjects
+            .iter()
+            .map(|(uri, subject)| {
+                let via = subject
+                    .via
+                    .as_ref()
+                    .map(|via| (via.direction, via.from_type.as_str()));
+                (uri.as_str(), subject.type_iri.as_str(), via)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            nodes,
+            [
+                ("<http://x/team>", "<http://t/Team>", None),
+                (
+                    "<http://x/ann>",
+                    "<http://t/Member>",
+                    Some((Direction::Reverse, "<http://t/Team>"))
+                ),
+                (
+                    "<http://x/badge>",
+                    "<http://t/Badge>",
+                    Some((Direction::Forward, "<http://t/Member>"))
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn interrupted_traversal_resumes_from_the_state_file() {
+        let state_file = std::env::temp_dir().join(format!("resume-{}.json", std::process::id()));
+        let options = || PlannerOptions {
+            state_file: Some(state_file.clone()),
+            ..PlannerOptions::default()
+        };
+        let seeds = ["<http://x/org>".to_string()];
+
+        // The forward lookup of the sites fails after the organization was processed.
+        let broken = DeletionPlanner::new(
+            org_store().failing(Direction::Forward),
+            org_config(),
+            options(),
+        );
+        let mut partial = vec![];
+        let statements = broken.delete_statements(&seeds, "<http://t/Org>");
+        pin_mut!(statements);
+        while let Some(Ok(statement)) = statements.next().await {
+            partial.extend(statement.subjects);
+        }
+        assert_eq!(
+            partial,
+            ["<http://x/org>", "<http://x/site1>", "<http://x/site2>"]
+        );
+        let saved = TraversalState::load(&state_file).unwrap().unwrap();
+        assert_eq!(saved.pending, ["<http://t/Site>"]);
+
+        let resumed = DeletionPlanner::new(org_store(), org_config(), options());
+        let subjects = plan(&resumed)
+            .await
+            .into_iter()
+            .flat_map(|statement| statement.subjects)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            subjects,
+            [
+                "<http://x/org>",
+                "<http://x/site1>",
+                "<http://x/site2>",
+                "<http://x/addr1>"
+            ]
+        );
+        // Only the pending type is looked up again, and the finished run leaves no state.
+        assert_eq!(
+            *resumed.backend.calls.lock().unwrap(),
+            [Some(Direction::Forward)]
+        );
+        assert!(!state_file.exists());
+    }
+
+    #[tokio::test]
+    async fn resuming_with_another_seed_is_refused() {
+        let state_file =
+            std::env::temp_dir().join(format!("other-seed-{}.json", std::process::id()));
+        TraversalState::new(&["<http://x/other>".to_string()], "<http://t/Org>", vec![])
+            .save(&state_file)
+            .unwrap();
+        let options = PlannerOptions {
+            state_file: Some(state_file.clone()),
+            ..PlannerOptions::default()
+        };
+        let planner = DeletionPlanner::new(org_store(), org_config(), options);
+
+        let seeds = ["<http://x/org>".to_string()];
+        let result = planner
+            .delete_statements(&seeds, "<http://t/Org>")
+            .try_collect::<Vec<_>>()
+            .await;
+
+        assert!(matches!(result, Err(ScratchpadError::State(_))));
+        std::fs::remove_file(state_file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn minified_output_is_shorter_and_still_parses() {
+        let output = |format| async move {
+            let options = PlannerOptions {
+                format,
+                ..PlannerOptions::default()
+            };
+            let planner = DeletionPlanner::new(org_store(), org_config(), options);
+            plan(&planner).await
+        };
+
+        let pretty = output(OutputFormat::Pretty).await;
+        let minified = output(OutputFormat::Minified).await;
+
+        let bytes = |statements: &[PlannedStatement]| {
+            statements
+                .iter()
+                .map(|statement| statement.text.len())
+                .sum::<usize>()
+        };
+        assert!(bytes(&minified) < bytes(&pretty) * 3 / 4);
+        for statement in &minified {
+            assert_eq!(statement.text.lines().count(), 1);
+            validate_update(statement.text.trim_end().trim_end_matches(';')).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn validation_passes_every_generated_query_and_statement() {
+        let options = PlannerOptions {
+            validate: true,
+            ..PlannerOptions::default()
+        };
+        let planner = DeletionPlanner::new(org_store(), org_config(), options);
+
+        let statements = plan(&planner).await;
+
+        assert_eq!(statements.len(), 3);
+        assert_eq!(planner.backend.calls.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn statements_are_yielded_before_the_next_lookup() {
+        let planner = DeletionPlanner::new(org_store(), org_config(), PlannerOptions::default());
+        let seeds = ["<http://x/org>".to_string()];
+        let statements = planner.delete_statements(&seeds, "<http://t/Org>");
+        pin_mut!(statements);
+        let lookups = || planner.backend.calls.lock().unwrap().len();
+
+        let seed = statements.next().await.unwrap().unwrap();
+        assert_eq!(seed.subjects, ["<http://x/org>"]);
+        assert_eq!(lookups(), 0);
+
+        let sites = statements.next().await.unwrap().unwrap();
+        assert_eq!(sites.depth, Some(1));
+        assert_eq!(lookups(), 1);
+
+        let addresses = statements.next().await.unwrap().unwrap();
+        assert_eq!(addresses.depth, Some(2));
+        assert_eq!(lookups(), 2);
+        assert!(statements.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn prefixed_config_generates_the_same_statements() {
+        let prefixed = serde_json::from_value::<JsonConfig>(json!({
+            "prefixes": { "t": "http://t/" },
+            "t:Org": { "reverse": ["t:Site"], "forward": [] },
+            "t:Site": { "reverse": [], "forward": ["<http://t/Addr>"] }
+        }))
+        .unwrap()
+        .expand_prefixes()
+        .unwrap();
+        let texts = |config| async move {
+            let planner = DeletionPlanner::new(org_store(), config, PlannerOptions::default());
+            plan(&planner)
+                .await
+                .into_iter()
+                .map(|statement| statement.text)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(texts(prefixed).await, texts(org_config()).await);
+    }
+
+    #[tokio::test]
+    async fn too_many_statements_name_the_edge_that_caused_them() {
+        let options = PlannerOptions {
+            max_statements: Some(2),
+            ..PlannerOptions::default()
+        };
+        let planner = DeletionPlanner::new(org_store(), org_config(), options);
+
+        let seeds = ["<http://x/org>".to_string()];
+        let result = planner
+            .delete_statements(&seeds, "<http://t/Org>")
+            .try_collect::<Vec<_>>()
+            .await;
+
+        let Err(ScratchpadError::Limit(msg)) = result else {
+            panic!("expected the statement limit to be hit");
+        };
+        assert!(msg.starts_with("More than 2 DELETE statements generated"));
+        assert!(msg.contains("the forward edge <http://t/Site> -> <http://t/Addr>"));
+    }
+
+    #[tokio::test]
+    async fn statements_up_to_the_limit_are_fine() {
+        let options = PlannerOptions {
+            max_statements: Some(3),
+            ..PlannerOptions::default()
+        };
+        let planner = DeletionPlanner::new(org_store(), org_config(), options);
+
+        assert_eq!(plan(&planner).await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn since_filter_is_only_added_when_configured() {
+        let since = TemporalFilter::new("<http://purl.org/dc/terms/created>", "2024-01-01");
+        let filtered = DeletionPlanner::new(
+            org_store(),
+            org_config(),
+            PlannerOptions {
+                since: Some(since.unwrap()),
+                ..PlannerOptions::default()
+            },
+        );
+        let unfiltered = DeletionPlanner::new(org_store(), org_config(), PlannerOptions::default());
+
+        let with_filter = plan(&filtered).await;
+        let without_filter = plan(&unfiltered).await;
+
+        assert_eq!(with_filter.len(), without_filter.len());
+        for statement in &with_filter {
+            assert!(statement
+                .update
+                .contains("?s <http://purl.org/dc/terms/created> ?created_at ."));
+            assert!(statement
+                .update
+                .contains("FILTER(?created_at > \"2024-01-01T00:00:00Z\"^^"));
+        }
+        for statement in &without_filter {
+            assert!(!statement.update.contains("created_at"));
+        }
+    }
+
+    #[tokio::test]
+    async fn seed_is_deleted_as_subject_and_as_object() {
+        let planner = DeletionPlanner::new(org_store(), org_config(), PlannerOptions::default());
+
+        let statements = plan(&planner).await;
+        let incoming = planner.incoming_statement("<http://x/org>");
+
+        assert!(statements[0]
+            .update
+            .contains("VALUES ?s {\n    <http://x/org>\n  }"));
+        assert!(incoming.update.contains("BIND(<http://x/org> AS ?o)"));
+        assert!(incoming.update.contains("FILTER(?s != ?o)"));
+        assert!(incoming.subjects.is_empty());
+    }
+
+    /// Answers every query with the same rows.
+    struct Snapshot(Value);
+
+    impl SparqlBackend for Snapshot {
+        async fn query(&self, _sparql: &str) -> Result<Value, ScratchpadError> {
+            Ok(self.0.clone())
+        }
+
+        async fn lookup(&self, _: Direction, sparql: &str) -> Result<Value, ScratchpadError> {
+            self.query(sparql).await
+        }
+    }
+
+    #[tokio::test]
+    async fn deleted_triples_are_written_as_valid_nquads() {
+        let org = json!({ "type": "uri", "value": "http://x/org" });
+        let graph = json!({ "type": "uri", "value": "http://g/public" });
+        let row = |p: &str, o: Value| {
+            let p = json!({ "type": "uri", "value": p });
+            json!({ "s": org, "p": p, "o": o, "g": graph })
+        };
+        let rows = vec![
+            row(
+                "http://xmlns.com/foaf/0.1/name",
+                json!({
+                    "type": "literal",
+                    "value": "Gemeente \"Oud\"\nHeverlee",
+                    "xml:lang": "nl-BE"
+                }),
+            ),
+            row(
+                "http://x/inhabitants",
+                json!({
+                    "type": "typed-literal",
+                    "value": "33000",
+                    "datatype": "http://www.w3.org/2001/XMLSchema#integer"
+                }),
+            ),
+            row(
+                "http://x/code",
+                json!({
+                    "type": "literal",
+                    "value": "24086",
+                    "datatype": "http://www.w3.org/2001/XMLSchema#string"
+                }),
+            ),
+            row(
+                "http://x/seat",
+                json!({ "type": "bnode", "value": "nodeID://b-10.4" }),
+            ),
+            row(
+                "http://x/homepage",
+                json!({ "type": "uri", "value": "http://x/geméénte" }),
+            ),
+        ];
+        let store = Snapshot(json!({
+            "head": { "vars": ["s", "p", "o", "g"] },
+            "results": { "bindings": rows }
+        }));
+        let planner = DeletionPlanner::new(store, org_config(), PlannerOptions::default());
+
+        let quads = planner
+            .deleted_quads(&["<http://x/org>".to_string()])
+            .await
+            .unwrap();
+
+        assert_eq!(quads.len(), 5);
+        assert!(quads
+            .contains(&"<http://x/org> <http://x/code> \"24086\" <http://g/public> .".to_string()));
+        assert!(quads.contains(
+            &"<http://x/org> <http://x/seat> _:nodeIDb104 <http://g/public> .".to_string()
+        ));
+        for quad in &quads {
+            // Every N-Quads term is valid in SPARQL too, so a quad parses as INSERT DATA.
+            let (triple, graph) = quad.strip_suffix(" .").unwrap().rsplit_once(' ').unwrap();
+            let update = format!("INSERT DATA {{ GRAPH {} {{ {} }} }}", graph, triple);
+            assert!(
+                spargebra::SparqlParser::new().parse_update(&update).is_ok(),
+                "{}",
+                quad
+            );
+        }
+    }
+
+    /// The quads the DELETE templates of `update` remove, with `?s` bound to each of
+    /// `subjects` where the template has it.
+    fn deleted_quads(update: &str, subjects: &[String]) -> Vec<String> {
+        let update = spargebra::SparqlParser::new().parse_update(update).unwrap();
+        let mut quads = vec![];
+        for operation in update.operations {
+            let GraphUpdateOperation::DeleteInsert { delete, .. } = operation else {
+                panic!("only DELETE operations are generated");
+            };
+            for quad in delete.iter().map(ToString::to_string) {
+                if quad.contains("?s ") {
+                    quads.extend(
+                        subjects
+                            .iter()
+                            .map(|s| quad.replace("?s ", &format!("{} ", s))),
+                    );
+                } else {
+                    quads.push(quad);
+                }
+            }
+        }
+        quads.sort();
+        quads
+    }
+
+    #[tokio::test]
+    async fn delete_where_removes_the_same_quads_as_the_full_form() {
+        let full = DeletionPlanner::new(org_store(), org_config(), PlannerOptions::default());
+        let shorthand = DeletionPlanner::new(
+            org_store(),
+            org_config(),
+            PlannerOptions {
+                delete_style: DeleteStyle::DeleteWhere,
+                ..PlannerOptions::default()
+            },
+        );
+
+        let full = plan(&full).await;
+        let shorthand = plan(&shorthand).await;
+
+        assert_eq!(full.len(), shorthand.len());
+        for (full, shorthand) in full.iter().zip(&shorthand) {
+            assert_eq!(full.subjects, shorthand.subjects);
+            assert_eq!(
+                shorthand.update.matches("DELETE WHERE").count(),
+                shorthand.subjects.len()
+            );
+            assert_eq!(
+                deleted_quads(&full.update, &full.subjects),
+                deleted_quads(&shorthand.update, &shorthand.subjects)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn delete_where_falls_back_to_the_full_form_for_narrower_deletes() {
+        let since = TemporalFilter::new("<http://purl.org/dc/terms/created>", "2024-01-01");
+        let narrowed = [
+            PlannerOptions {
+                since: Some(since.unwrap()),
+                ..PlannerOptions::default()
+            },
+            PlannerOptions {
+                predicates: vec!["<http://p/name>".to_string()],
+                ..PlannerOptions::default()
+            },
+        ];
+
+        for options in narrowed {
+            let options = PlannerOptions {
+                delete_style: DeleteStyle::DeleteWhere,
+                ..options
+            };
+            let planner = DeletionPlanner::new(org_store(), org_config(), options);
+
+            for statement in plan(&planner).await {
+                assert!(!statement.update.contains("DELETE WHERE"));
+                assert!(statement.update.contains("VALUES ?s {"));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn filtered_uris_are_neither_deleted_nor_expanded() {
+        let options = PlannerOptions {
+            uri_filter: Box::new(NamespaceFilter {
+                deny: vec!["http://x/site2".to_string()],
+                ..NamespaceFilter::default()
+            }),
+            ..PlannerOptions::default()
+        };
+        let planner = DeletionPlanner::new(org_store(), org_config(), options);
+
+        let subjects = plan(&planner)
+            .await
+            .into_iter()
+            .flat_map(|statement| statement.subjects)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            subjects,
+            ["<http://x/org>", "<http://x/site1>", "<http://x/addr1>"]
+        );
+    }
+
+    #[tokio::test]
+    async fn unexpanded_uris_are_still_deleted_as_leaves() {
+        let options = PlannerOptions {
+            expand_filter: Box::new(NamespaceFilter {
+                allow: vec!["http://x/org".to_string()],
+                ..NamespaceFilter::default()
+            }),
+            ..PlannerOptions::default()
+        };
+        let planner = DeletionPlanner::new(org_store(), org_config(), options);
+
+        let subjects = plan(&planner)
+            .await
+            .into_iter()
+            .flat_map(|statement| statement.subjects)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            subjects,
+            ["<http://x/org>", "<http://x/site1>", "<http://x/site2>"]
+        );
+    }
+
+    #[tokio::test]
+    async fn external_uris_outside_the_namespace_are_reached_but_not_expanded() {
+        let store = TripleStore::default()
+            .typed(
+                "<http://data.lblod.info/id/bestuur/7>",
+                "<http://t/Bestuur>",
+            )
+            .typed(
+                "<http://publications.europa.eu/country/BEL>",
+                "<http://t/Country>",
+            )
+            .typed(
+                "<http://publications.europa.eu/continent/EU>",
+                "<http://t/Continent>",
+            )
+            .link(
+                "<http://data.lblod.info/id/bestuur/7>",
+                "<http://publications.europa.eu/country/BEL>",
+            )
+            .link(
+                "<http://publications.europa.eu/country/BEL>",
+                "<http://publications.europa.eu/continent/EU>",
+            );
+        let config = serde_json::from_value(json!({
+            "<http://t/Bestuur>": { "reverse": [], "forward": ["<http://t/Country>"] },
+            "<http://t/Country>": { "reverse": [], "forward": ["<http://t/Continent>"] }
+        }))
+        .unwrap();
+        let options = PlannerOptions {
+            expand_filter: Box::new(NamespaceFilter {
+                allow: vec!["http://data.lblod.info/".to_string()],
+                ..NamespaceFilter::default()
+            }),
+            ..PlannerOptions::default()
+        };
+        let planner = DeletionPlanner::new(store, config, options);
+
+        let seeds = ["<http://data.lblod.info/id/bestuur/7>".to_string()];
+        let subjects = planner
+            .delete_statements(&seeds, "<http://t/Bestuur>")
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap()
+            .into_iter()
+            .flat_map(|statement| statement.subjects)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            subjects,
+            [
+                "<http://data.lblod.info/id/bestuur/7>",
+                "<http://publications.europa.eu/country/BEL>"
+            ]
+        );
+        let sent = planner.backend.sent.lock().unwrap();
+        assert!(sent
+            .iter()
+            .filter(|query| query.contains("VALUES ?values {"))
+            .all(|query| !query.contains("country/BEL")));
+    }
+
+    #[tokio::test]
+    async fn failing_edge_is_reported_while_the_other_edges_are_followed() {
+        let store = || {
+            org_store()
+                .typed("<http://x/flanders>", "<http://t/Region>")
+                .link("<http://x/org>", "<http://x/flanders>")
+                .failing(Direction::Forward)
+        };
+        let config = || -> JsonConfig {
+            serde_json::from_str(
+                r#"{
+                    "<http://t/Org>": {
+                        "forward": ["<http://t/Region>"],
+                        "reverse": ["<http://t/Site>"]
+                    }
+                }"#,
+            )
+            .unwrap()
+        };
+        let options = PlannerOptions {
+            continue_on_error: true,
+            ..PlannerOptions::default()
+        };
+        let planner = DeletionPlanner::new(store(), config(), options);
+
+        let subjects = plan(&planner)
+            .await
+            .into_iter()
+            .flat_map(|statement| statement.subjects)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            subjects,
+            ["<http://x/org>", "<http://x/site1>", "<http://x/site2>"]
+        );
+        let failures = planner.failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(
+            failures[0].to_string(),
+            "the forward edge <http://t/Org> -> <http://t/Region>: SPARQL error: connection reset"
+        );
+        assert!(planner.failures().is_empty());
+
+        // Without the flag the first failing edge aborts the run.
+        let strict = DeletionPlanner::new(store(), config(), PlannerOptions::default());
+        let seeds = ["<http://x/org>".to_string()];
+        let result = strict
+            .delete_statements(&seeds, "<http://t/Org>")
+            .try_collect::<Vec<_>>()
+            .await;
+        assert!(matches!(result, Err(ScratchpadError::Sparql(_))));
+    }
+
+    #[tokio::test]
+    async fn edges_with_a_path_look_up_through_that_property_path() {
+        let config = serde_json::from_str::<JsonConfig>(
+            r#"{
+                "prefixes": { "prov": "http://www.w3.org/ns/prov#" },
+                "<http://t/Org>": {
+                    "forward": [
+                        { "type": "<http://t/Addr>", "path": "<http://x/a>/<http://x/b>*" }
+                    ],
+                    "reverse": [{ "type": "<http://t/Site>", "path": "prov:wasDerivedFrom+" }]
+                }
+            }"#,
+        )
+        .unwrap()
+        .expand_prefixes()
+        .unwrap();
+        let planner = DeletionPlanner::new(org_store(), config, PlannerOptions::default());
+
+        plan(&planner).await;
+
+        let sent = planner.backend.sent.lock().unwrap();
+        let lookups = sent
+            .iter()
+            .filter(|query| query.contains("VALUES ?values {"))
+            .collect::<Vec<_>>();
+        assert_eq!(lookups.len(), 2);
+        assert!(lookups
+            .iter()
+            .any(|query| query.contains("?values <http://x/a>/<http://x/b>* ?o .")));
+        assert!(lookups.iter().any(|query| query.contains(
+            "?s a <http://t/Site> ;\n        <http://www.w3.org/ns/prov#wasDerivedFrom>+ ?values ."
+        )));
+        for query in lookups {
+            crate::validate::validate_query(query).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn dropping_the_lists_of_processed_types_changes_nothing() {
+        let uncapped = DeletionPlanner::new(org_store(), org_config(), PlannerOptions::default());
+        let options = PlannerOptions {
+            max_stored_uris: Some(1),
+            ..PlannerOptions::default()
+        };
+        let capped = DeletionPlanner::new(org_store(), org_config(), options);
+
+        let texts = |statements: Vec<PlannedStatement>| {
+            statements
+                .into_iter()
+                .map(|statement| statement.text)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(texts(plan(&capped).await), texts(plan(&uncapped).await));
+    }
+
+    #[tokio::test]
+    async fn only_reverse_never_follows_forward_edges() {
+        let options = PlannerOptions {
+            only_direction: Some(Direction::Reverse),
+            ..PlannerOptions::default()
+        };
+        let planner = DeletionPlanner::new(org_store(), org_config(), options);
+
+        let statements = plan(&planner).await;
+
+        assert_eq!(statements.len(), 2);
+        assert!(statements
+            .iter()
+            .all(|statement| statement.type_iri.as_deref() != Some("<http://t/Addr>")));
+        let calls = planner.backend.calls.lock().unwrap();
+        assert!(!calls.contains(&Some(Direction::Forward)));
+    }
+
+    #[tokio::test]
+    async fn subjects_found_through_a_service_are_deleted_from_the_primary_store() {
+        let config = serde_json::from_value(json!({
+            "<http://t/Org>": { "reverse": ["<http://t/Site>"], "forward": [] },
+            "<http://t/Site>": {
+                "reverse": [],
+                "forward": [{ "type": "<http://t/Addr>", "service": "<http://remote/sparql>" }]
+            }
+        }))
+        .unwrap();
+        let planner = DeletionPlanner::new(org_store(), config, PlannerOptions::default());
+
+        let statements = plan(&planner).await;
+
+        assert_eq!(statements[2].subjects, ["<http://x/addr1>"]);
+        assert!(statements
+            .iter()
+            .all(|statement| !statement.update.contains("SERVICE")));
+    }
+
+    #[tokio::test]
+    async fn statements_that_would_delete_nothing_are_pruned() {
+        let options = || PlannerOptions {
+            prune_empty_blocks: true,
+            ..PlannerOptions::default()
+        };
+        // One of the sites still has triples, so their statement stays.
+        let store = || {
+            org_store()
+                .emptied("<http://x/site2>")
+                .emptied("<http://x/addr1>")
+        };
+        let pruned = DeletionPlanner::new(store(), org_config(), options());
+        let unpruned = DeletionPlanner::new(store(), org_config(), PlannerOptions::default());
+
+        let kept = plan(&pruned).await;
+
+        let subjects = kept
+            .iter()
+            .map(|statement| statement.subjects.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            subjects,
+            [
+                vec!["<http://x/org>"],
+                vec!["<http://x/site1>", "<http://x/site2>"]
+            ]
+        );
+        assert_eq!(plan(&unpruned).await.len(), 3);
+        assert!(!unpruned.backend.calls.lock().unwrap().contains(&None));
+    }
+
+    #[tokio::test]
+    async fn batches_are_halved_until_the_store_accepts_them() {
+        let store = TripleStore::default()
+            .typed("<http://x/org>", "<http://t/Org>")
+            .typed("<http://x/org2>", "<http://t/Org>")
+            .typed("<http://x/org3>", "<http://t/Org>")
+            .typed("<http://x/org4>", "<http://t/Org>")
+            .typed("<http://x/org5>", "<http://t/Org>")
+            .typed("<http://x/site1>", "<http://t/Site>")
+            .link("<http://x/site1>", "<http://x/org4>");
+        let seeds = ["org", "org2", "org3", "org4", "org5"].map(|s| format!("<http://x/{}>", s));
+        let planner = DeletionPlanner::new(
+            store.refusing_over(2),
+            org_config(),
+            PlannerOptions::default(),
+        );
+
+        let statements: Vec<_> = planner
+            .delete_statements(&seeds, "<http://t/Org>")
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(statements.last().unwrap().subjects, ["<http://x/site1>"]);
+        // The 5 seeds are refused, then looked up as 2, 2 and 1, and then the site.
+        assert_eq!(planner.batch_size.load(Ordering::Relaxed), 2);
+        let lookups = planner.backend.calls.lock().unwrap().len();
+        assert_eq!(lookups, 5);
+    }
+
+    #[tokio::test]
+    async fn verification_reports_subjects_that_kept_their_triples() {
+        // The delete of site1 was silently ignored by the store.
+        let store = org_store()
+            .emptied("<http://x/org>")
+            .emptied("<http://x/site2>");
+        let planner = DeletionPlanner::new(store, org_config(), PlannerOptions::default());
+        let deleted = ["<http://x/org>", "<http://x/site1>", "<http://x/site2>"].map(String::from);
+
+        let remaining = planner.remaining_subjects(&deleted).await.unwrap();
+
+        assert_eq!(remaining, ["<http://x/site1>"]);
+        // One ASK per subject, so each leftover is named on its own.
+        assert_eq!(planner.backend.calls.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn verification_fails_on_an_answer_that_is_no_ask_result() {
+        // A proxy in front of the store answers with rows instead of a boolean.
+        let counts = Counts(Mutex::new(VecDeque::from([0])));
+        let planner = DeletionPlanner::new(counts, org_config(), PlannerOptions::default());
+
+        let remaining = planner
+            .remaining_subjects(&["<http://x/site1>".to_string()])
+            .await;
+
+        let Err(ScratchpadError::Sparql(msg)) = remaining else {
+            panic!("a malformed answer must not count as remaining triples");
+        };
+        assert_eq!(msg, "Expected an ASK result, got SELECT results");
+    }
+
+    #[tokio::test]
+    async fn custom_separator_and_headers_go_between_the_statements() {
+        let options = |statement_headers| PlannerOptions {
+            separator: Some("\n;\n".to_string()),
+            statement_headers,
+            ..PlannerOptions::default()
+        };
+        let with_headers = DeletionPlanner::new(org_store(), org_config(), options(true));
+        let bare = DeletionPlanner::new(org_store(), org_config(), options(false));
+
+        let with_headers = plan(&with_headers).await;
+        let bare = plan(&bare).await;
+
+        let output = bare.iter().map(|s| s.text.as_str()).collect::<String>();
+        assert_eq!(output.matches("\n;\n").count(), bare.len());
+        assert!(!output.contains('#'));
+        assert!(!output.contains("\n\n;\n\n"));
+        for (headed, bare) in with_headers.iter().zip(&bare) {
+            let (header, rest) = headed.text.split_once('\n').unwrap();
+            assert!(header.starts_with("# From "));
+            assert_eq!(rest, bare.text);
+        }
+    }
+
+    /// Answers the triple count queries of the report from `(subject, predicate, graph)`
+    /// triples.
+    struct QuadStore(Vec<(&'static str, &'static str, &'static str)>);
+
+    impl QuadStore {
+        /// The answer to `build_graph_size_query` for the whitespace separated `graphs`.
+        fn graph_sizes(&self, graphs: &str) -> Value {
+            let bindings = graphs
+                .split_whitespace()
+                .map(|graph| (graph, self.0.iter().filter(|(_, _, g)| *g == graph).count()))
+                .filter(|(_, size)| *size > 0)
+                .map(|(graph, size)| {
+                    json!({
+                        "g": { "type": "uri", "value": strip_iri(graph) },
+                        "count": { "type": "literal", "value": size.to_string() }
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            json!({ "head": { "vars": ["g", "count"] }, "results": { "bindings": bindings } })
+        }
+    }
+
+    impl SparqlBackend for QuadStore {
+        async fn query(&self, sparql: &str) -> Result<Value, ScratchpadError> {
+            if let Some((_, rest)) = sparql.split_once("VALUES ?g {") {
+                return Ok(self.graph_sizes(rest.split_once('}').unwrap().0));
+            }
+            let (_, rest) = sparql.split_once("VALUES ?s {").unwrap();
+            let (subjects, _) = rest.split_once('}').unwrap();
+            let subjects = subjects.split_whitespace().collect::<Vec<_>>();
+
+            let mut counts = IndexMap::<_, u64>::new();
+            for &(s, p, g) in &self.0 {
+                if subjects.contains(&s) {
+                    *counts.entry((p, g)).or_default() += 1;
+                }
+            }
+            let bindings = counts
+                .iter()
+                .map(|((p, g), count)| {
+                    json!({
+                        "p": { "type": "uri", "value": strip_iri(p) },
+                        "g": { "type": "uri", "value": strip_iri(g) },
+                        "count": { "type": "literal", "value": count.to_string() }
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let head = json!({ "vars": ["p", "g", "count"] });
+            Ok(json!({ "head": head, "results": { "bindings": bindings } }))
+        }
+
+        async fn lookup(&self, _: Direction, sparql: &str) -> Result<Value, ScratchpadError> {
+            self.query(sparql).await
+        }
+    }
+
+    #[tokio::test]
+    async fn graphs_left_empty_by_the_deletion_are_dropped() {
+        let label = "<http://www.w3.org/2000/01/rdf-schema#label>";
+        let store = QuadStore(vec![
+            ("<http://x/bestuur>", label, "<http://g/org-bestuur>"),
+            ("<http://x/mandaat>", label, "<http://g/org-bestuur>"),
+            ("<http://x/bestuur>", label, "<http://g/shared>"),
+            ("<http://x/buur>", label, "<http://g/shared>"),
+            ("<http://x/mandaat>", label, "<http://g/org-mandaten>"),
+        ]);
+        let planner = DeletionPlanner::new(store, org_config(), PlannerOptions::default());
+        let mut deleted = HashMap::new();
+
+        for subjects in [["<http://x/bestuur>"], ["<http://x/mandaat>"]] {
+            let subjects = subjects.map(String::from);
+            planner
+                .count_graph_triples(&subjects, &mut deleted)
+                .await
+                .unwrap();
+        }
+        let graphs = planner.emptied_graphs(&deleted).await.unwrap();
+
+        assert_eq!(deleted.len(), 3);
+        assert_eq!(
+            graphs,
+            ["<http://g/org-bestuur>", "<http://g/org-mandaten>"]
+        );
+        let drop = planner.drop_graph_statement(&graphs[0]);
+        assert_eq!(drop.update, "DROP SILENT GRAPH <http://g/org-bestuur>");
+        assert!(drop.subjects.is_empty());
+    }
+
+    #[tokio::test]
+    async fn predicates_are_counted_over_every_statement() {
+        let name = "<http://xmlns.com/foaf/0.1/name>";
+        let store = QuadStore(vec![
+            ("<http://x/org>", name, "<http://g/public>"),
+            ("<http://x/org>", "<http://t/site>", "<http://g/public>"),
+            ("<http://x/org>", "<http://t/site>", "<http://g/public>"),
+            ("<http://x/site1>", name, "<http://g/private>"),
+            ("<http://x/site1>", "<http://t/site>", "<http://g/private>"),
+            ("<http://x/site2>", name, "<http://g/public>"),
+            ("<http://x/other>", name, "<http://g/public>"),
+        ]);
+        let planner = DeletionPlanner::new(store, org_config(), PlannerOptions::default());
+        let (mut predicates, mut graphs) = (HashMap::new(), HashMap::new());
+
+        for subjects in [
+            vec!["<http://x/org>"],
+            vec!["<http://x/site1>", "<http://x/site2>"],
+        ] {
+            let subjects = subjects.into_iter().map(String::from).collect::<Vec<_>>();
+            planner
+                .count_predicates(&subjects, &mut predicates, &mut graphs)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(predicates.len(), 2);
+        assert_eq!(predicates[name], 3);
+        assert_eq!(predicates["<http://t/site>"], 3);
+        assert_eq!(graphs["<http://g/public>"], 4);
+        assert_eq!(graphs["<http://g/private>"], 2);
+    }
+
+    #[tokio::test]
+    async fn relative_iris_from_the_store_are_deleted_in_their_absolute_form() {
+        let store = || {
+            TripleStore::default()
+                .typed("<http://x/org>", "<http://t/Org>")
+                .typed("<sites/1>", "<http://t/Site>")
+                .link("<sites/1>", "<http://x/org>")
+        };
+        let with_base = PlannerOptions {
+            base: Some("http://x/".to_string()),
+            ..PlannerOptions::default()
+        };
+        let resolving = DeletionPlanner::new(store(), org_config(), with_base);
+        let failing = DeletionPlanner::new(store(), org_config(), PlannerOptions::default());
+        let seeds = ["<http://x/org>".to_string()];
+
+        let statements = plan(&resolving).await;
+        let unresolved: Result<Vec<_>, _> = failing
+            .delete_statements(&seeds, "<http://t/Org>")
+            .try_collect()
+            .await;
+
+        assert_eq!(statements[1].subjects, ["<http://x/sites/1>"]);
+        assert!(statements[1].update.contains("<http://x/sites/1>"));
+        assert!(matches!(unresolved, Err(ScratchpadError::Sparql(msg)) if msg.contains("--base")));
+    }
+
+    /// Answers the per-type counts of `build_linked_types_query` for the neighbours of
+    /// the organization, the ones pointing to it and the ones it points to.
+    struct Neighbours {
+        reverse: Vec<(&'static str, &'static str)>,
+        forward: Vec<(&'static str, &'static str)>,
+    }
+
+    impl SparqlBackend for Neighbours {
+        async fn query(&self, sparql: &str) -> Result<Value, ScratchpadError> {
+            unreachable!("only lookups are sent, got {}", sparql)
+        }
+
+        async fn lookup(
+            &self,
+            direction: Direction,
+            sparql: &str,
+        ) -> Result<Value, ScratchpadError> {
+            assert!(sparql.contains("VALUES ?values {\n    <http://x/org>\n  }"));
+            let counts = match direction {
+                Direction::Reverse => &self.reverse,
+                Direction::Forward => &self.forward,
+            };
+            let bindings = counts
+                .iter()
+                .map(|(t, count)| {
+                    json!({
+                        "t": { "type": "uri", "value": strip_iri(t) },
+                        "count": { "type": "typed-literal", "value": count }
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            Ok(json!({ "head": { "vars": ["t", "count"] }, "results": { "bindings": bindings } }))
+        }
+    }
+
+    #[tokio::test]
+    async fn linked_types_are_counted_and_marked_when_the_config_follows_them() {
+        let store = Neighbours {
+            reverse: vec![("<http://t/Site>", "2"), ("<http://t/Mandate>", "14")],
+            forward: vec![("<http://t/Classification>", "1"), ("<http://t/Site>", "1")],
+        };
+        let planner = DeletionPlanner::new(store, org_config(), PlannerOptions::default());
+
+        let seeds = ["<http://x/org>".to_string()];
+        let linked = planner
+            .linked_types(&seeds, "<http://t/Org>")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            linked.summary(),
+            concat!(
+                "Linked types (* = followed by the config):\n",
+                "    forward          1  <http://t/Classification>\n",
+                "    forward          1  <http://t/Site>\n",
+                "    reverse         14  <http://t/Mandate>\n",
+                "  * reverse          2  <http://t/Site>\n",
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn linked_types_with_a_count_that_is_no_number_are_an_error() {
+        let store = Neighbours {
+            reverse: vec![("<http://t/Site>", "2")],
+            forward: vec![("<http://t/Classification>", "many")],
+        };
+        let planner = DeletionPlanner::new(store, org_config(), PlannerOptions::default());
+
+        let seeds = ["<http://x/org>".to_string()];
+        let linked = planner.linked_types(&seeds, "<http://t/Org>").await;
+
+        let Err(ScratchpadError::Sparql(msg)) = linked else {
+            panic!("a type must not be left out for its count");
+        };
+        assert_eq!(
+            msg,
+            "Expected a number of instances of <http://t/Classification>, got many"
+        );
+    }
+
+    /// Answers every query with the next of a list of counts, as a `?count` row.
+    struct Counts(Mutex<VecDeque<u64>>);
+
+    impl SparqlBackend for Counts {
+        async fn query(&self, _sparql: &str) -> Result<Value, ScratchpadError> {
+            let count = self.0.lock().unwrap().pop_front().unwrap();
+            let row = json!({ "count": { "type": "literal", "value": count.to_string() } });
+            Ok(json!({ "head": { "vars": ["count"] }, "results": { "bindings": [row] } }))
+        }
+
+        async fn lookup(&self, _: Direction, sparql: &str) -> Result<Value, ScratchpadError> {
+            self.query(sparql).await
+        }
+    }
+
+    #[tokio::test]
+    async fn estimated_requests_shrink_with_larger_chunks() {
+        let seeds = ["<http://x/org>".to_string()];
+        let mut requests = vec![];
+
+        for chunk_size in [1, 10, 100] {
+            // One organization with 250 sites.
+            let counts = Counts(Mutex::new(VecDeque::from([1, 250])));
+            let options = PlannerOptions {
+                chunk_size: Some(chunk_size),
+                ..PlannerOptions::default()
+            };
+            let planner = DeletionPlanner::new(counts, org_config(), options);
+
+            let estimate = planner.estimate(&seeds, "<http://t/Org>").await.unwrap();
+
+            assert_eq!(
+                estimate.types,
+                [
+                    ("<http://t/Org>".to_string(), 1),
+                    ("<http://t/Site>".to_string(), 250)
+                ]
+            );
+            assert_eq!(estimate.statements, 3);
+            requests.push(estimate.requests);
+        }
+
+        assert_eq!(requests, [1 + 250, 1 + 25, 1 + 3]);
+    }
+
+    #[tokio::test]
+    async fn prefixed_variables_replace_every_default_one() {
+        let options = PlannerOptions {
+            vars: QueryVars::prefixed("__").unwrap(),
+            predicates: vec!["<http://xmlns.com/foaf/0.1/name>".to_string()],
+            validate: true,
+            ..PlannerOptions::default()
+        };
+        let planner = DeletionPlanner::new(org_store(), org_config(), options);
+
+        let mut updates = plan(&planner)
+            .await
+            .into_iter()
+            .map(|statement| statement.update)
+            .collect::<Vec<_>>();
+        updates.push(planner.incoming_statement("<http://x/org>").update);
+
+        for update in &updates {
+            for var in ["?g", "?s", "?p", "?o"] {
+                assert!(!update.contains(var), "{} left in {}", var, update);
+            }
+            assert!(update.contains("GRAPH ?__g {\n    ?__s ?__p ?__o ."));
+        }
+        assert!(QueryVars::prefixed("x-").is_err());
+    }
+
+    #[tokio::test]
+    async fn json_yaml_and_toml_configs_plan_the_same_deletion() {
+        let dir = std::env::temp_dir().join(format!("config-formats-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files = [
+            (
+                "config.json",
+                r#"{
+                    "<http://t/Org>": { "reverse": ["<http://t/Site>"], "forward": [] },
+                    "<http://t/Site>": { "reverse": [], "forward": ["<http://t/Addr>"] }
+                }"#,
+            ),
+            (
+                "config.yaml",
+                concat!(
+                    "# Sites point to their organization.\n",
+                    "'<http://t/Org>':\n",
+                    "  reverse: ['<http://t/Site>']\n",
+                    "  forward: []\n",
+                    "'<http://t/Site>':\n",
+                    "  reverse: []\n",
+                    "  forward:\n",
+                    "    - '<http://t/Addr>'\n",
+                ),
+            ),
+            (
+                "config.toml",
+                concat!(
+                    "['<http://t/Org>']\n",
+                    "reverse = ['<http://t/Site>']\n",
+                    "forward = []\n",
+                    "['<http://t/Site>']\n",
+                    "reverse = []\n",
+                    "forward = ['<http://t/Addr>']\n",
+                ),
+            ),
+        ];
+
+        let mut outputs = vec![];
+        for (name, contents) in files {
+            let path = dir.join(name);
+            std::fs::write(&path, contents).unwrap();
+            let config = JsonConfig::load(&path).unwrap();
+            let planner = DeletionPlanner::new(org_store(), config, PlannerOptions::default());
+            let statements = plan(&planner).await;
+            outputs.push(statements.into_iter().map(|s| s.text).collect::<String>());
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert!(outputs[0].contains("<http://x/addr1>"));
+        assert_eq!(outputs[0], outputs[1]);
+        assert_eq!(outputs[0], outputs[2]);
+    }
+
+    #[tokio::test]
+    async fn output_does_not_depend_on_the_order_of_results() {
+        let store = |sites: [&'static str; 3]| {
+            sites.iter().fold(
+                TripleStore::default().typed("<http://x/org>", "<http://t/Org>"),
+                |store, &site| {
+                    store
+                        .typed(site, "<http://t/Site>")
+                        .link(site, "<http://x/org>")
+                },
+            )
+        };
+        let texts = |store| async {
+            let planner = DeletionPlanner::new(store, org_config(), PlannerOptions::default());
+            plan(&planner)
+                .await
+                .into_iter()
+                .map(|statement| statement.text)
+                .collect::<String>()
+        };
+
+        let first = texts(store(["<http://x/s3>", "<http://x/s1>", "<http://x/s2>"])).await;
+        let second = texts(store(["<http://x/s2>", "<http://x/s3>", "<http://x/s1>"])).await;
+
+        assert_eq!(first, second);
+        assert!(first.contains("    <http://x/s1>\n    <http://x/s2>\n    <http://x/s3>\n"));
+    }
+
+    #[tokio::test]
+    async fn inference_pragma_precedes_every_query_but_no_statement() {
+        let pragma = "DEFINE input:inference \"none\"";
+        let options = PlannerOptions {
+            query_pragma: Some(pragma.to_string()),
+            // The pragma is not SPARQL, so queries are validated without it.
+            validate: true,
+            ..PlannerOptions::default()
+        };
+        let with_pragma = DeletionPlanner::new(org_store(), org_config(), options);
+        let without = DeletionPlanner::new(org_store(), org_config(), PlannerOptions::default());
+
+        let statements = plan(&with_pragma).await;
+        plan(&without).await;
+
+        let sent = with_pragma.backend.sent.lock().unwrap();
+        assert_eq!(sent.len(), 2);
+        for query in sent.iter() {
+            assert!(query.starts_with(&format!("{}\n", pragma)));
+        }
+        assert!(statements.iter().all(|s| !s.text.contains("DEFINE")));
+        let sent = without.backend.sent.lock().unwrap();
+        assert!(sent.iter().all(|query| !query.contains("DEFINE")));
+    }
+
+    #[test]
+    fn explain_lists_the_types_in_config_order_with_their_edges() {
+        // Keys out of alphabetical order, the plan has to follow the file.
+        let config: JsonConfig = serde_json::from_str(
+            r#"{
+                "<http://t/Org>": {
+                    "reverse": ["<http://t/Member>"],
+                    "forward": ["<http://t/Site>"]
+                },
+                "<http://t/Member>": { "reverse": [], "forward": ["<http://t/Org>"] },
+                "<http://t/Account>": { "reverse": [], "forward": [] },
+                "<http://t/Site>": { "reverse": [], "forward": ["<http://t/Addr>"] }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            explain(&config, Some("<http://t/Org>"), None),
+            "Traversal plan from <http://t/Org>:\n\
+             \x20 1. <http://t/Org>\n\
+             \x20      reverse <http://t/Member>\n\
+             \x20      forward <http://t/Site>\n\
+             \x20 2. <http://t/Member>\n\
+             \x20      forward <http://t/Org>  (already processed, not expanded)\n\
+             \x20 3. <http://t/Account>  (not reached, skipped)\n\
+             \x20 4. <http://t/Site>\n\
+             \x20      forward <http://t/Addr>  (no config entry, deleted but not expanded)\n"
+        );
+        // Following reverse edges only, the sites are never reached.
+        let reverse_only = explain(&config, Some("<http://t/Org>"), Some(Direction::Reverse));
+        assert!(reverse_only.contains("  4. <http://t/Site>  (not reached, skipped)\n"));
+        assert!(!reverse_only.contains("forward"));
+    }
+
+    #[tokio::test]
+    async fn strict_types_only_follow_resources_without_other_types() {
+        // The first site doubles as a shared venue, which no config entry covers.
+        let store = || {
+            org_store()
+                .typed("<http://x/site1>", "<http://t/Venue>")
+                .typed("<http://x/addr1>", "<http://t/Org>")
+                .link("<http://x/site2>", "<http://x/addr1>")
+        };
+        let strict = PlannerOptions {
+            strict_types: true,
+            ..PlannerOptions::default()
+        };
+
+        let permissive = DeletionPlanner::new(store(), org_config(), PlannerOptions::default());
+        let strict = DeletionPlanner::new(store(), org_config(), strict);
+
+        let subjects = |statements: Vec<PlannedStatement>| {
+            statements
+                .into_iter()
+                .flat_map(|statement| statement.subjects)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            subjects(plan(&permissive).await),
+            [
+                "<http://x/org>",
+                "<http://x/site1>",
+                "<http://x/site2>",
+                "<http://x/addr1>"
+            ]
+        );
+        // The address has two types, but both are in the config.
+        assert_eq!(
+            subjects(plan(&strict).await),
+            ["<http://x/org>", "<http://x/site2>", "<http://x/addr1>"]
+        );
+        let sent = strict.backend.sent.lock().unwrap();
+        assert!(sent[0]
+            .contains("?other_type NOT IN (<http://t/Addr>, <http://t/Org>, <http://t/Site>)"));
+    }
+
+    /// Answers each query with the rows of the first pattern it contains, so a test only
+    /// spells out the lookups it expects. Any other query fails the test.
+    struct Canned(Vec<(&'static str, Vec<&'static str>)>);
+
+    impl SparqlBackend for Canned {
+        async fn query(&self, sparql: &str) -> Result<Value, ScratchpadError> {
+            let Some((_, uris)) = self.0.iter().find(|(pattern, _)| sparql.contains(pattern))
+            else {
+                panic!("no canned response for {}", sparql);
+            };
+            let var = if sparql.contains("SELECT DISTINCT ?o") {
+                "o"
+            } else {
+                "s"
+            };
+            let bindings = uris
+                .iter()
+                .map(|uri| json!({ var: { "type": "uri", "value": uri } }))
+                .collect::<Vec<_>>();
+
+            Ok(json!({ "head": { "vars": [var] }, "results": { "bindings": bindings } }))
+        }
+    }
+
+    #[tokio::test]
+    async fn traversal_runs_against_canned_responses() {
+        let backend = Canned(vec![
+            (
+                "?s a <http://t/Mandate> ;",
+                vec!["http://x/mandate/1", "http://x/mandate/2"],
+            ),
+            ("?o a <http://t/Person> .", vec!["http://x/person/jan"]),
+        ]);
+        let config = serde_json::from_value(json!({
+            "<http://t/Council>": { "reverse": ["<http://t/Mandate>"], "forward": [] },
+            "<http://t/Mandate>": { "reverse": [], "forward": ["<http://t/Person>"] }
+        }))
+        .unwrap();
+        let planner = DeletionPlanner::new(backend, config, PlannerOptions::default());
+
+        let statements: Vec<PlannedStatement> = planner
+            .delete_statements(&["<http://x/council>".to_string()], "<http://t/Council>")
+            .try_collect()
+            .await
+            .unwrap();
+
+        let subjects = statements
+            .iter()
+            .map(|statement| statement.subjects.join(" "))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            subjects,
+            [
+                "<http://x/council>",
+                "<http://x/mandate/1> <http://x/mandate/2>",
+                "<http://x/person/jan>"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn flattened_output_is_one_statement_per_chunk_of_all_subjects() {
+        let store = || {
+            TripleStore::default()
+                .typed("<http://x/town>", "<http://t/Town>")
+                .typed("<http://x/dept/a>", "<http://t/Dept>")
+                .typed("<http://x/dept/b>", "<http://t/Dept>")
+                .typed("<http://x/hall>", "<http://t/Building>")
+                .typed("<http://x/depot>", "<http://t/Building>")
+                .link("<http://x/dept/a>", "<http://x/town>")
+                .link("<http://x/dept/b>", "<http://x/town>")
+                .link("<http://x/dept/a>", "<http://x/hall>")
+                .link("<http://x/dept/b>", "<http://x/depot>")
+        };
+        // From a string, json! would sort the departments before the town.
+        let config = || {
+            serde_json::from_str(
+                r#"{
+                    "<http://t/Town>": { "reverse": ["<http://t/Dept>"], "forward": [] },
+                    "<http://t/Dept>": { "reverse": [], "forward": ["<http://t/Building>"] }
+                }"#,
+            )
+            .unwrap()
+        };
+        let statements = |options: PlannerOptions| async move {
+            let planner = DeletionPlanner::new(store(), config(), options);
+            let seeds = ["<http://x/town>".to_string()];
+            planner
+                .delete_statements(&seeds, "<http://t/Town>")
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap()
+        };
+        let flatten = |chunk_size| PlannerOptions {
+            flatten_output: true,
+            chunk_size,
+            ..PlannerOptions::default()
+        };
+
+        let per_type = statements(PlannerOptions::default()).await;
+        let flattened = statements(flatten(None)).await;
+        let chunked = statements(flatten(Some(2))).await;
+
+        assert_eq!(per_type.len(), 3);
+        assert_eq!(flattened.len(), 1);
+        assert_eq!(chunked.len(), 3);
+        let mut all = per_type
+            .into_iter()
+            .flat_map(|statement| statement.subjects)
+            .collect::<Vec<_>>();
+        all.sort();
+        assert_eq!(flattened[0].subjects, all);
+        assert_eq!(
+            chunked
+                .iter()
+                .map(|statement| statement.subjects.len())
+                .collect::<Vec<_>>(),
+            [2, 2, 1]
+        );
+    }
+
+    #[tokio::test]
+    async fn triples_with_literal_objects_are_deleted_with_their_subject() {
+        let planner = DeletionPlanner::new(org_store(), org_config(), PlannerOptions::default());
+
+        let statements = plan(&planner).await;
+
+        let sites = &statements[1];
+        assert_eq!(sites.subjects, ["<http://x/site1>", "<http://x/site2>"]);
+        let update = spargebra::SparqlParser::new()
+            .parse_update(&sites.update)
+            .unwrap();
+        let GraphUpdateOperation::DeleteInsert {
+            delete, pattern, ..
+        } = &update.operations[0]
+        else {
+            panic!("expected a DELETE");
+        };
+        // A site's name, e.g. "Gemeentehuis"@nl, is matched by the open object as well as
+        // any IRI, and nothing in the WHERE clause narrows it down to IRIs.
+        assert!(delete
+            .iter()
+            .all(|quad| matches!(quad.object, spargebra::term::GroundTermPattern::Variable(_))));
+        let pattern = pattern.to_string();
+        assert!(!pattern.contains("isIRI") && !pattern.contains("isLiteral"));
+        assert_eq!(
+            deleted_quads(&sites.update, &sites.subjects),
+            [
+                "GRAPH ?g { <http://x/site1> ?p ?o }",
+                "GRAPH ?g { <http://x/site2> ?p ?o }"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reflexive_triples_delete_their_uri_once_without_expanding_it_again() {
+        // A unit that lists itself among its own parent units.
+        let unit = || {
+            TripleStore::default()
+                .typed("<http://x/unit>", "<http://t/Unit>")
+                .link("<http://x/unit>", "<http://x/unit>")
+        };
+        let config = || {
+            serde_json::from_value(json!({
+                "<http://t/Unit>": { "reverse": ["<http://t/Unit>"], "forward": [] }
+            }))
+            .unwrap()
+        };
+        let seeds = ["<http://x/unit>".to_string()];
+        let subjects = |planner| {
+            DeletionPlanner::delete_statements(planner, &seeds, "<http://t/Unit>")
+                .map_ok(|statement| statement.subjects)
+                .try_concat()
+        };
+
+        let alone = DeletionPlanner::new(unit(), config(), PlannerOptions::default());
+        let with_subunit = DeletionPlanner::new(
+            unit()
+                .typed("<http://x/subunit>", "<http://t/Unit>")
+                .link("<http://x/subunit>", "<http://x/unit>"),
+            config(),
+            PlannerOptions::default(),
+        );
+
+        assert_eq!(subjects(&alone).await.unwrap(), ["<http://x/unit>"]);
+        assert_eq!(alone.backend.sent.lock().unwrap().len(), 1);
+        assert_eq!(
+            subjects(&with_subunit).await.unwrap(),
+            ["<http://x/unit>", "<http://x/subunit>"]
+        );
+    }
+
+    /// Answers whether a type has instances, locally or in the `SERVICE` of the query.
+    struct Instances {
+        local: Vec<&'static str>,
+        remote: Vec<&'static str>,
+    }
+
+    impl SparqlBackend for Instances {
+        async fn query(&self, sparql: &str) -> Result<Value, ScratchpadError> {
+            let type_iri = sparql
+                .split_once("?x a ")
+                .and_then(|(_, rest)| rest.split_whitespace().next())
+                .unwrap();
+            let types = match sparql.contains("SERVICE") {
+                true => &self.remote,
+                false => &self.local,
+            };
+
+            Ok(json!({ "head": {}, "boolean": types.contains(&type_iri) }))
+        }
+    }
+
+    #[tokio::test]
+    async fn config_types_without_instances_are_flagged() {
+        let config = serde_json::from_str(
+            r#"{
+                "<http://t/Bestuurseenheid>": {
+                    "reverse": ["<http://t/Mandaat>", "<http://t/Mandat>"],
+                    "forward": [{ "type": "<http://t/Adres>", "service": "<http://remote/sparql>" }]
+                },
+                "<http://t/Mandaat>": { "reverse": [], "forward": ["<http://t/Adres>"] }
+            }"#,
+        )
+        .unwrap();
+        // The addresses live in the local store only, not in the service the config names.
+        let store = Instances {
+            local: vec![
+                "<http://t/Bestuurseenheid>",
+                "<http://t/Mandaat>",
+                "<http://t/Adres>",
+            ],
+            remote: vec![],
+        };
+        let planner = DeletionPlanner::new(store, config, PlannerOptions::default());
+
+        let missing = planner.types_without_instances().await.unwrap();
+
+        assert_eq!(missing, ["<http://t/Adres>", "<http://t/Mandat>"]);
+    }
+
+    #[tokio::test]
+    async fn shuffled_edges_delete_the_same_subjects() {
+        // The headquarters is both a site of the organization and its address, so which
+        // statement deletes it depends on the edge that gets to it first.
+        let store = || {
+            TripleStore::default()
+                .typed("<http://x/org>", "<http://t/Org>")
+                .typed("<http://x/site1>", "<http://t/Site>")
+                .typed("<http://x/member1>", "<http://t/Member>")
+                .typed("<http://x/hq>", "<http://t/Site>")
+                .typed("<http://x/hq>", "<http://t/Addr>")
+                .link("<http://x/site1>", "<http://x/org>")
+                .link("<http://x/member1>", "<http://x/org>")
+                .link("<http://x/hq>", "<http://x/org>")
+                .link("<http://x/org>", "<http://x/hq>")
+        };
+        let config = || {
+            serde_json::from_value(json!({
+                "<http://t/Org>": {
+                    "reverse": ["<http://t/Site>", "<http://t/Member>"],
+                    "forward": ["<http://t/Addr>"]
+                }
+            }))
+            .unwrap()
+        };
+        let blocks = |traversal_seed| async move {
+            let options = PlannerOptions {
+                traversal_seed,
+                ..PlannerOptions::default()
+            };
+            let planner = DeletionPlanner::new(store(), config(), options);
+            plan(&planner)
+                .await
+                .into_iter()
+                .map(|statement| statement.subjects)
+                .collect::<Vec<_>>()
+        };
+
+        let unshuffled = blocks(None).await;
+        let mut expected = unshuffled.concat();
+        expected.sort();
+        let mut reordered = false;
+        for seed in 0..8 {
+            let shuffled = blocks(Some(seed)).await;
+            reordered |= shuffled != unshuffled;
+            let mut subjects = shuffled.concat();
+            subjects.sort();
+            assert_eq!(subjects, expected, "--traversal-seed {}", seed);
+        }
+
+        assert_eq!(expected.len(), 4);
+        assert!(reordered, "no seed changed the order of the edges");
+    }
+
+    /// Answers lookups with the IRI-ordered page of `sites` their keyset filter and LIMIT
+    /// ask for, and records the number of rows of every page.
+    struct Paged {
+        sites: Vec<&'static str>,
+        pages: Mutex<Vec<usize>>,
+    }
+
+    impl SparqlBackend for Paged {
+        async fn query(&self, sparql: &str) -> Result<Value, ScratchpadError> {
+            let after = sparql
+                .split_once("STR(?s) > \"")
+                .and_then(|(_, rest)| rest.split_once('"'))
+                .map(|(after, _)| after);
+            let limit = sparql
+                .split_once("LIMIT ")
+                .and_then(|(_, rest)| rest.trim().parse::<usize>().ok())
+                .unwrap();
+
+            let mut sites = self.sites.clone();
+            sites.sort();
+            let page = sites
+                .into_iter()
+                .filter(|site| after.is_none_or(|after| *site > after))
+                .take(limit)
+                .map(|site| json!({ "s": { "type": "uri", "value": site } }))
+                .collect::<Vec<_>>();
+            self.pages.lock().unwrap().push(page.len());
+
+            Ok(json!({ "head": { "vars": ["s"] }, "results": { "bindings": page } }))
+        }
+    }
+
+    #[tokio::test]
+    async fn keyset_pages_fetch_every_result_once() {
+        let paged = |sites: Vec<&'static str>| {
+            let options = PlannerOptions {
+                keyset_page_size: Some(2),
+                ..PlannerOptions::default()
+            };
+            let store = Paged {
+                sites,
+                pages: Mutex::new(vec![]),
+            };
+            DeletionPlanner::new(store, org_config(), options)
+        };
+        let uneven = paged(vec![
+            "http://x/site/e",
+            "http://x/site/a",
+            "http://x/site/d",
+            "http://x/site/b",
+            "http://x/site/c",
+        ]);
+        let even = paged(vec!["http://x/site/b", "http://x/site/a"]);
+
+        let uris = uneven
+            .fetch_batch(
+                Direction::Reverse,
+                "<http://x/org>",
+                "<http://t/Site>",
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let two = even
+            .fetch_batch(
+                Direction::Reverse,
+                "<http://x/org>",
+                "<http://t/Site>",
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            uris,
+            [
+                "<http://x/site/a>",
+                "<http://x/site/b>",
+                "<http://x/site/c>",
+                "<http://x/site/d>",
+                "<http://x/site/e>"
+            ]
+        );
+        assert_eq!(*uneven.backend.pages.lock().unwrap(), [2, 2, 1]);
+        // A full last page is only known to be the last one by the empty page after it.
+        assert_eq!(two.len(), 2);
+        assert_eq!(*even.backend.pages.lock().unwrap(), [2, 0]);
+    }
+
+    #[tokio::test]
+    async fn reification_nodes_are_deleted_for_the_chunks_that_have_them() {
+        // The first chunk holds a mandate whose start date was reified, the second none.
+        let counts = Counts(Mutex::new(VecDeque::from([1, 0])));
+        let options = PlannerOptions {
+            chunk_size: Some(2),
+            validate: true,
+            ..PlannerOptions::default()
+        };
+        let planner = DeletionPlanner::new(counts, org_config(), options);
+        let subjects = [
+            "<http://x/mandate/1>".to_string(),
+            "<http://x/mandate/2>".to_string(),
+            "<http://x/mandate/3>".to_string(),
+        ];
+
+        let (statements, nodes) = planner.reification_statements(&subjects).await.unwrap();
+
+        assert_eq!(nodes, 1);
+        assert_eq!(statements.len(), 1);
+        let update = &statements[0].update;
+        assert!(update.contains("    <http://x/mandate/1>\n    <http://x/mandate/2>\n  }"));
+        assert!(!update.contains("<http://x/mandate/3>"));
+        assert!(update.contains("<http://www.w3.org/1999/02/22-rdf-syntax-ns#subject>"));
+        assert!(update.contains("    ?s ?p_role ?s_reified .\n    ?s ?p ?o ."));
+    }
+
+    /// Moves every subject into an anonymized namespace, keeping its local name.
+    struct Anonymize;
+
+    impl SubjectMapping for Anonymize {
+        fn replacement(&self, subject: &str) -> String {
+            subject.replace("<http://x/", "<http://anon.example/")
+        }
+    }
+
+    #[tokio::test]
+    async fn renamed_subjects_are_paired_with_their_replacement() {
+        for (rename, site1) in [
+            (
+                Box::new(Anonymize) as Box<dyn SubjectMapping>,
+                "<http://anon.example/site1>",
+            ),
+            (
+                Box::new(RenameTo("<http://x/tombstone>".to_string())),
+                "<http://x/tombstone>",
+            ),
+        ] {
+            let options = PlannerOptions {
+                rename: Some(rename),
+                validate: true,
+                ..PlannerOptions::default()
+            };
+            let planner = DeletionPlanner::new(org_store(), org_config(), options);
+
+            let statements = plan(&planner).await;
+
+            let sites = statements
+                .iter()
+                .find(|statement| statement.subjects.contains(&"<http://x/site1>".to_string()))
+                .unwrap();
+            assert!(sites.update.contains("\nINSERT {\n"));
+            assert!(sites
+                .update
+                .contains(&format!("    (<http://x/site1> {})\n", site1)));
+            assert_eq!(statements.len(), 3);
+        }
+    }
+
+    #[tokio::test]
+    async fn samples_with_one_seed_keep_the_same_few_sites() {
+        const SITES: [&str; 12] = [
+            "<http://x/site/aalst>",
+            "<http://x/site/brugge>",
+            "<http://x/site/diest>",
+            "<http://x/site/eeklo>",
+            "<http://x/site/gent>",
+            "<http://x/site/halle>",
+            "<http://x/site/ieper>",
+            "<http://x/site/lier>",
+            "<http://x/site/mol>",
+            "<http://x/site/ninove>",
+            "<http://x/site/oudenaarde>",
+            "<http://x/site/tielt>",
+        ];
+        let store = || {
+            SITES.iter().fold(
+                TripleStore::default().typed("<http://x/org>", "<http://t/Org>"),
+                |store, site| {
+                    store
+                        .typed(site, "<http://t/Site>")
+                        .link(site, "<http://x/org>")
+                },
+            )
+        };
+        let sampled_sites = |sample_seed| async move {
+            let options = PlannerOptions {
+                sample: Some(4),
+                sample_seed,
+                ..PlannerOptions::default()
+            };
+            let planner = DeletionPlanner::new(store(), org_config(), options);
+            plan(&planner)
+                .await
+                .into_iter()
+                .flat_map(|statement| statement.subjects)
+                .filter(|subject| subject.starts_with("<http://x/site/"))
+                .collect::<Vec<_>>()
+        };
+
+        let first = sampled_sites(42).await;
+
+        assert_eq!(first.len(), 4);
+        assert_eq!(sampled_sites(42).await, first);
+        let mut others = vec![];
+        for seed in [1, 2, 3] {
+            others.push(sampled_sites(seed).await);
+        }
+        assert!(others.iter().any(|other| *other != first));
+    }
+}
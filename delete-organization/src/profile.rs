@@ -0,0 +1,345 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches};
+use toml::{Table, Value};
+
+use crate::cli::Cli;
+use crate::error::ScratchpadError;
+
+/// Options that only make sense on the command line.
+const COMMAND_LINE_ONLY: &[&str] = &["profile", "show-config", "help", "version"];
+
+/// `$XDG_CONFIG_HOME/work-scratchpad/config.toml`, or `~/.config/...` without it.
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_home.join("work-scratchpad").join("config.toml"))
+}
+
+/// Parses the command line, taking every option it leaves out from the profile file
+/// before falling back to the built-in default. Prints the effective options and exits
+/// for --show-config.
+pub fn parse_args() -> Result<Cli, ScratchpadError> {
+    let (cli, shown) = resolve(std::env::args_os().collect(), default_path())?;
+    if let Some(shown) = shown {
+        print!("{}", shown);
+        std::process::exit(0);
+    }
+
+    Ok(cli)
+}
+
+/// `args` completed from the profile file at `path`, together with the effective options
+/// when --show-config asks for them.
+fn resolve(
+    mut args: Vec<OsString>,
+    path: Option<PathBuf>,
+) -> Result<(Cli, Option<String>), ScratchpadError> {
+    let matches = Cli::command().get_matches_from(&args);
+    let values = load(path, matches.get_one::<String>("profile"))?;
+
+    let (name, explicit) = selected(&matches);
+    let command = subcommand(name);
+    let mut from_profile = HashSet::new();
+    for (key, value) in &values {
+        let Some(arg) = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(key))
+        else {
+            // Valid for another subcommand, like `update-endpoint` when generating.
+            continue;
+        };
+        if explicit.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+
+        let takes_values = arg.get_action().takes_values();
+        for value in scalars(key, value)? {
+            match (takes_values, value.as_str()) {
+                (true, _) => args.push(format!("--{}={}", key, value).into()),
+                (false, "true") => args.push(format!("--{}", key).into()),
+                (false, "false") => {}
+                (false, _) => {
+                    return Err(ScratchpadError::Config(format!(
+                        "{} in the profile is a flag, set it to true or false",
+                        key
+                    )))
+                }
+            }
+        }
+        from_profile.insert(key.clone());
+    }
+
+    let matches = Cli::command().get_matches_from(&args);
+    let cli = Cli::from_arg_matches(&matches)
+        .map_err(|e| ScratchpadError::Config(format!("Invalid profile: {}", e)))?;
+    let shown = cli
+        .show_config
+        .then(|| show_config(name, selected(&matches).1, &from_profile));
+
+    Ok((cli, shown))
+}
+
+/// Reads the top-level values of the profile file at `path`, with the values of the
+/// `[profiles.<name>]` table on top of them. A missing file only is an error when a
+/// profile was asked for.
+fn load(path: Option<PathBuf>, profile: Option<&String>) -> Result<Table, ScratchpadError> {
+    let Some(path) = path.filter(|path| path.exists() || profile.is_some()) else {
+        return Ok(Table::new());
+    };
+    let contents = std::fs::read_to_string(&path).map_err(|e| {
+        ScratchpadError::Config(format!(
+            "Cannot read profile file {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    let mut values = contents
+        .parse::<Table>()
+        .map_err(|e| ScratchpadError::Config(format!("Cannot parse {}: {}", path.display(), e)))?;
+
+    let profiles = match values.remove("profiles") {
+        Some(Value::Table(profiles)) => profiles,
+        Some(_) => {
+            return Err(ScratchpadError::Config(format!(
+                "profiles in {} has to be a table of [profiles.<name>] tables",
+                path.display()
+            )))
+        }
+        None => Table::new(),
+    };
+    let known = known_options();
+    check_keys(&values, &known, &path)?;
+    for table in profiles.values() {
+        if let Value::Table(table) = table {
+            check_keys(table, &known, &path)?;
+        }
+    }
+
+    if let Some(profile) = profile {
+        let Some(Value::Table(overrides)) = profiles.get(profile) else {
+            return Err(ScratchpadError::Config(format!(
+                "{} has no [profiles.{}] table",
+                path.display(),
+                profile
+            )));
+        };
+        values.extend(overrides.clone());
+    }
+
+    Ok(values)
+}
+
+fn check_keys(values: &Table, known: &HashSet<String>, path: &Path) -> Result<(), ScratchpadError> {
+    match values.keys().find(|key| !known.contains(key.as_str())) {
+        Some(key) => Err(ScratchpadError::Config(format!(
+            "Unknown option {} in {}, use the long name of a command line option",
+            key,
+            path.display()
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// The long names of the options of all subcommands that a profile can set.
+fn known_options() -> HashSet<String> {
+    let command = Cli::command();
+
+    command
+        .get_arguments()
+        .chain(
+            command
+                .get_subcommands()
+                .flat_map(|sub| sub.get_arguments()),
+        )
+        .filter_map(|arg| arg.get_long())
+        .filter(|long| !COMMAND_LINE_ONLY.contains(long))
+        .map(str::to_string)
+        .collect()
+}
+
+/// The name and matches of the subcommand being run, `None` for the implicit `generate`.
+fn selected(matches: &ArgMatches) -> (Option<&str>, &ArgMatches) {
+    match matches.subcommand() {
+        Some((name, sub)) => (Some(name), sub),
+        None => (None, matches),
+    }
+}
+
+fn subcommand(name: Option<&str>) -> clap::Command {
+    let command = Cli::command();
+
+    match name.and_then(|name| command.find_subcommand(name)) {
+        Some(sub) => sub.clone(),
+        None => command,
+    }
+}
+
+/// The command line values of a profile value, one per element of an array.
+fn scalars(key: &str, value: &Value) -> Result<Vec<String>, ScratchpadError> {
+    match value {
+        Value::String(s) => Ok(vec![s.clone()]),
+        Value::Integer(i) => Ok(vec![i.to_string()]),
+        Value::Float(f) => Ok(vec![f.to_string()]),
+        Value::Boolean(b) => Ok(vec![b.to_string()]),
+        Value::Array(values) => Ok(values
+            .iter()
+            .map(|v| scalars(key, v))
+            .collect::<Result<Vec<_>, _>>()?
+            .concat()),
+        _ => Err(ScratchpadError::Config(format!(
+            "{} in the profile has to be a string, number, boolean or array",
+            key
+        ))),
+    }
+}
+
+/// Every option of the subcommand with its effective value and where that comes from, as
+/// TOML that can be pasted into the profile file.
+fn show_config(name: Option<&str>, matches: &ArgMatches, from_profile: &HashSet<String>) -> String {
+    let command = subcommand(name);
+    let mut s = format!("# Effective options of `{}`\n", name.unwrap_or("generate"));
+
+    for arg in command.get_arguments() {
+        let Some(long) = arg.get_long() else {
+            continue;
+        };
+        if COMMAND_LINE_ONLY.contains(&long) {
+            continue;
+        }
+        let id = arg.get_id().as_str();
+        let source = match matches.value_source(id) {
+            _ if from_profile.contains(long) => "profile",
+            Some(ValueSource::CommandLine) => "command line",
+            Some(ValueSource::DefaultValue) => "default",
+            Some(_) => "environment",
+            None => {
+                s.push_str(&format!("# {} is not set\n", long));
+                continue;
+            }
+        };
+
+        let raw = matches
+            .get_raw(id)
+            .into_iter()
+            .flatten()
+            .map(|value| value.to_string_lossy())
+            .collect::<Vec<_>>();
+        let value = match (arg.get_action(), raw.as_slice()) {
+            (ArgAction::SetTrue | ArgAction::SetFalse, [flag]) => flag.to_string(),
+            (ArgAction::Append, values) => format!(
+                "[{}]",
+                values
+                    .iter()
+                    .map(|v| Value::String(v.to_string()).to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            (_, values) => Value::String(values.join(" ")).to_string(),
+        };
+        s.push_str(&format!("{} = {}  # {}\n", long, value, source));
+    }
+
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Command;
+
+    /// A profile file of its own per test, the tests run in parallel.
+    fn profile_file(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("profile-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        ["delete-organization"]
+            .iter()
+            .chain(args)
+            .map(OsString::from)
+            .collect()
+    }
+
+    #[test]
+    fn command_line_overrides_the_profile_which_overrides_the_default() {
+        let path = profile_file(
+            "precedence",
+            "endpoint = \"http://virtuoso:8890/sparql\"\nchunk-size = 200\n",
+        );
+
+        let (cli, shown) = resolve(
+            args(&["generate", "--chunk-size", "25"]),
+            Some(path.clone()),
+        )
+        .unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let Some(Command::Generate(generate)) = cli.command else {
+            panic!("expected generate");
+        };
+        assert_eq!(generate.traversal.chunk_size, Some(25));
+        assert_eq!(generate.connection.endpoint, "http://virtuoso:8890/sparql");
+        assert!(shown.is_none());
+    }
+
+    #[test]
+    fn named_profile_goes_on_top_of_the_top_level_values() {
+        let path = profile_file(
+            "named",
+            concat!(
+                "query-timeout = 30\n",
+                "skip-namespace = [\"http://www.w3.org/\"]\n",
+                "[profiles.staging]\n",
+                "query-timeout = 120\n",
+                "prune-empty-blocks = true\n",
+            ),
+        );
+
+        let (cli, shown) = resolve(
+            args(&["--profile", "staging", "--show-config"]),
+            Some(path.clone()),
+        )
+        .unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(cli.generate.connection.query_timeout, Some(120));
+        assert_eq!(
+            cli.generate.traversal.skip_namespaces,
+            ["http://www.w3.org/"]
+        );
+        assert!(cli.generate.traversal.prune_empty_blocks);
+        let shown = shown.unwrap();
+        assert!(shown.contains("query-timeout = \"120\"  # profile\n"));
+        assert!(shown.contains("prune-empty-blocks = true  # profile\n"));
+        assert!(shown.contains("# chunk-size is not set\n"));
+    }
+
+    #[test]
+    fn unknown_keys_and_profiles_are_rejected() {
+        let path = profile_file("unknown", "endpont = \"http://localhost:8890/sparql\"\n");
+        let Err(ScratchpadError::Config(msg)) = load(Some(path.clone()), None) else {
+            panic!("a misspelled option must not be ignored");
+        };
+        assert!(msg.starts_with("Unknown option endpont in "));
+
+        std::fs::write(&path, "chunk-size = 10\n").unwrap();
+        let Err(ScratchpadError::Config(msg)) = load(Some(path.clone()), Some(&"prod".into()))
+        else {
+            panic!("a missing profile must not be ignored");
+        };
+        std::fs::remove_file(&path).unwrap();
+        assert!(msg.ends_with("has no [profiles.prod] table"));
+        // Without a profile asked for, a missing file is the same as an empty one.
+        assert!(load(Some(path), None).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ScratchpadError;
+
+/// Number of predicates listed in the summary printed at the end of a run.
+const TOP_PREDICATES: usize = 10;
+/// Number of graphs listed in the summary printed at the end of a run.
+const TOP_GRAPHS: usize = 10;
+
+#[derive(Serialize, Deserialize)]
+pub struct PredicateCount {
+    pub predicate: String,
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GraphCount {
+    pub graph: String,
+    pub count: u64,
+}
+
+/// What a run would delete, written as JSON by --report.
+#[derive(Serialize, Deserialize)]
+pub struct Report {
+    pub run_id: String,
+    pub seeds: Vec<String>,
+    pub seed_type: String,
+    pub statements: usize,
+    /// Every subject the generated statements delete, in generation order.
+    pub subjects: Vec<String>,
+    /// How often each predicate occurs among the deleted triples, most frequent first.
+    pub predicates: Vec<PredicateCount>,
+    /// The named graphs holding deleted triples and how many each, most affected first.
+    /// Empty when statements target the default graph.
+    #[serde(default)]
+    pub graphs: Vec<GraphCount>,
+}
+
+impl Report {
+    pub fn set_predicate_counts(&mut self, counts: HashMap<String, u64>) {
+        let mut predicates = counts
+            .into_iter()
+            .map(|(predicate, count)| PredicateCount { predicate, count })
+            .collect::<Vec<_>>();
+        predicates.sort_by(|a, b| b.count.cmp(&a.count).then(a.predicate.cmp(&b.predicate)));
+
+        self.predicates = predicates;
+    }
+
+    pub fn set_graph_counts(&mut self, counts: HashMap<String, u64>) {
+        let mut graphs = counts
+            .into_iter()
+            .map(|(graph, count)| GraphCount { graph, count })
+            .collect::<Vec<_>>();
+        graphs.sort_by(|a, b| b.count.cmp(&a.count).then(a.graph.cmp(&b.graph)));
+
+        self.graphs = graphs;
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ScratchpadError> {
+        let reader = BufReader::new(File::open(path)?);
+
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ScratchpadError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    pub fn summary(&self) -> String {
+        let total = self.predicates.iter().map(|p| p.count).sum::<u64>();
+        let mut s = format!(
+            "{} statement(s) deleting {} triple(s) of {} subject(s)\n",
+            self.statements,
+            total,
+            self.subjects.len()
+        );
+
+        if !self.predicates.is_empty() {
+            s.push_str("Most frequent predicates:\n");
+        }
+        for predicate in self.predicates.iter().take(TOP_PREDICATES) {
+            s.push_str(&format!(
+                "  {:>10}  {}\n",
+                predicate.count, predicate.predicate
+            ));
+        }
+        if self.predicates.len() > TOP_PREDICATES {
+            s.push_str(&format!(
+                "  ... and {} more, see the report file\n",
+                self.predicates.len() - TOP_PREDICATES
+            ));
+        }
+
+        if !self.graphs.is_empty() {
+            s.push_str(&format!("Affected graphs ({}):\n", self.graphs.len()));
+        }
+        for graph in self.graphs.iter().take(TOP_GRAPHS) {
+            s.push_str(&format!("  {:>10}  {}\n", graph.count, graph.graph));
+        }
+        if self.graphs.len() > TOP_GRAPHS {
+            s.push_str(&format!(
+                "  ... and {} more, see the report file\n",
+                self.graphs.len() - TOP_GRAPHS
+            ));
+        }
+
+        s
+    }
+}
+
+/// Subjects deleted by only one of two runs, e.g. before and after a config change.
+pub struct SubjectDiff {
+    /// Deleted by the current run but not by the previous one, sorted.
+    pub added: Vec<String>,
+    /// Deleted by the previous run but not by the current one, sorted.
+    pub removed: Vec<String>,
+}
+
+impl SubjectDiff {
+    pub fn new(previous: &[String], current: &[String]) -> Self {
+        let previous = previous.iter().collect::<BTreeSet<_>>();
+        let current = current.iter().collect::<BTreeSet<_>>();
+
+        SubjectDiff {
+            added: current
+                .difference(&previous)
+                .map(|s| s.to_string())
+                .collect(),
+            removed: previous
+                .difference(&current)
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let mut s = format!(
+            "{} subject(s) added, {} removed compared to the previous run\n",
+            self.added.len(),
+            self.removed.len()
+        );
+        for subject in &self.added {
+            s.push_str(&format!("+ {}\n", subject));
+        }
+        for subject in &self.removed {
+            s.push_str(&format!("- {}\n", subject));
+        }
+
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(subjects: &[&str]) -> Report {
+        Report {
+            run_id: "run".to_string(),
+            seeds: vec!["<http://x/org>".to_string()],
+            seed_type: "<http://t/Org>".to_string(),
+            statements: 3,
+            subjects: subjects.iter().map(|s| s.to_string()).collect(),
+            predicates: vec![],
+            graphs: vec![],
+        }
+    }
+
+    #[test]
+    fn predicates_are_listed_most_frequent_first() {
+        let mut report = report(&["<http://x/org>"]);
+        let mut counts = HashMap::new();
+        counts.insert("<http://t/b>".to_string(), 5);
+        counts.insert("<http://t/a>".to_string(), 5);
+        counts.insert("<http://t/c>".to_string(), 40);
+
+        report.set_predicate_counts(counts);
+
+        let order = report
+            .predicates
+            .iter()
+            .map(|p| p.predicate.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(order, ["<http://t/c>", "<http://t/a>", "<http://t/b>"]);
+        assert!(report
+            .summary()
+            .starts_with("3 statement(s) deleting 50 triple(s) of 1 subject(s)\n"));
+    }
+
+    #[test]
+    fn summary_only_lists_the_top_predicates() {
+        let mut report = report(&[]);
+        report.set_predicate_counts(
+            (1..=12)
+                .map(|i| (format!("<http://t/p{:02}>", i), i))
+                .collect(),
+        );
+
+        let summary = report.summary();
+
+        assert!(summary.contains("          12  <http://t/p12>\n"));
+        assert!(!summary.contains("<http://t/p02>"));
+        assert!(summary.ends_with("  ... and 2 more, see the report file\n"));
+    }
+
+    #[test]
+    fn affected_graphs_are_listed_most_affected_first() {
+        let mut report = report(&["<http://x/org>", "<http://x/site1>"]);
+        let mut counts = HashMap::new();
+        for (graph, count) in [
+            ("<http://mu.semte.ch/graphs/public>", 3),
+            ("<http://mu.semte.ch/graphs/organizations/kalmthout>", 17),
+            ("<http://mu.semte.ch/graphs/harvesting>", 3),
+        ] {
+            counts.insert(graph.to_string(), count);
+        }
+
+        report.set_graph_counts(counts);
+
+        assert!(report.summary().ends_with(
+            "Affected graphs (3):\n\
+             \x20         17  <http://mu.semte.ch/graphs/organizations/kalmthout>\n\
+             \x20          3  <http://mu.semte.ch/graphs/harvesting>\n\
+             \x20          3  <http://mu.semte.ch/graphs/public>\n"
+        ));
+        // Statements on the default graph have no graphs to list.
+        report.set_graph_counts(HashMap::new());
+        assert!(!report.summary().contains("Affected graphs"));
+    }
+
+    #[test]
+    fn diff_lists_the_subjects_only_one_run_deletes() {
+        let path = std::env::temp_dir().join(format!("report-diff-{}.json", std::process::id()));
+        report(&["<http://x/org>", "<http://x/site1>", "<http://x/addr1>"])
+            .save(&path)
+            .unwrap();
+        let previous = Report::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let current = [
+            "<http://x/site2>",
+            "<http://x/org>",
+            "<http://x/site1>",
+            "<http://x/addr2>",
+        ]
+        .map(String::from);
+
+        let diff = SubjectDiff::new(&previous.subjects, &current);
+
+        assert_eq!(diff.added, ["<http://x/addr2>", "<http://x/site2>"]);
+        assert_eq!(diff.removed, ["<http://x/addr1>"]);
+        assert_eq!(
+            diff.summary(),
+            "2 subject(s) added, 1 removed compared to the previous run\n\
+             + <http://x/addr2>\n\
+             + <http://x/site2>\n\
+             - <http://x/addr1>\n"
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use indexmap::IndexMap;
+use serde::Serialize;
+
+use crate::client::SparqlClient;
+use crate::error::ScratchpadError;
+use crate::planner::Direction;
+use crate::{
+    build_linked_types_query, create_instances_query, fetch_sparql_results, parse_json_uris,
+};
+
+/// A config entry as written by `generate-config`.
+#[derive(Default, Serialize)]
+pub struct ScaffoldEntry {
+    pub reverse: Vec<String>,
+    pub forward: Vec<String>,
+}
+
+/// Builds a starter config from the data: for up to `sample_size` instances of each type,
+/// the types linked to them in either direction become its edges, starting at `root_type`
+/// and going `depth` levels deep. Every type found gets an entry of its own, after the type
+/// it was found from, so the config is processed in the order it was discovered. The types
+/// of the last level get empty entries.
+pub async fn scaffold_config(
+    client: &SparqlClient,
+    endpoint: &str,
+    root_type: &str,
+    sample_size: usize,
+    depth: usize,
+) -> Result<IndexMap<String, ScaffoldEntry>, ScratchpadError> {
+    let mut config = IndexMap::new();
+    config.insert(root_type.to_string(), ScaffoldEntry::default());
+
+    let mut level = vec![root_type.to_string()];
+    for _ in 0..depth {
+        let mut next = vec![];
+        for type_iri in &level {
+            let query = create_instances_query(type_iri, sample_size);
+            let r = fetch_sparql_results(client, endpoint, &query.text).await?;
+            let instances = parse_json_uris(&r, query.result_var)?
+                .iter()
+                .filter_map(|binding| binding.uri(query.result_var))
+                .map(|uri| format!("<{}>", uri))
+                .collect::<Vec<_>>();
+            if instances.is_empty() {
+                status!("{}: no instances", type_iri);
+                continue;
+            }
+
+            let mut entry = ScaffoldEntry::default();
+            for direction in [Direction::Reverse, Direction::Forward] {
+                let query = build_linked_types_query(direction, &instances.join(" "));
+                let r = fetch_sparql_results(client, endpoint, &query.text).await?;
+                let mut types = parse_json_uris(&r, query.result_var)?
+                    .iter()
+                    .filter_map(|binding| binding.uri(query.result_var))
+                    .map(|uri| format!("<{}>", uri))
+                    .collect::<Vec<_>>();
+                types.sort();
+
+                for linked in &types {
+                    if !config.contains_key(linked) && !next.contains(linked) {
+                        next.push(linked.clone());
+                    }
+                }
+                match direction {
+                    Direction::Reverse => entry.reverse = types,
+                    Direction::Forward => entry.forward = types,
+                }
+            }
+            status!(
+                "{}: {} reverse, {} forward type(s) linked to {} instance(s)",
+                type_iri,
+                entry.reverse.len(),
+                entry.forward.len(),
+                instances.len()
+            );
+            config.insert(type_iri.clone(), entry);
+        }
+
+        for type_iri in &next {
+            config.insert(type_iri.clone(), ScaffoldEntry::default());
+        }
+        level = next;
+    }
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{json_response, TestServer};
+
+    fn bindings(var: &str, uris: &[&str]) -> Vec<u8> {
+        let rows = uris
+            .iter()
+            .map(|uri| {
+                format!(
+                    r#"{{ "{}": {{ "type": "uri", "value": "{}" }} }}"#,
+                    var, uri
+                )
+            })
+            .collect::<Vec<_>>();
+        json_response(&format!(
+            r#"{{ "head": {{ "vars": ["{}"] }}, "results": {{ "bindings": [{}] }} }}"#,
+            var,
+            rows.join(", ")
+        ))
+    }
+
+    #[tokio::test]
+    async fn sampled_neighbour_types_become_the_edges_of_the_config() {
+        // An organization with members and sites pointing to it and an address it points
+        // to. The sites have no instances of their own in the sample, and the address
+        // points back at a site.
+        let server = TestServer::start(vec![
+            bindings("s", &["http://x/org"]),
+            bindings("t", &["http://t/Site", "http://t/Member"]),
+            bindings("t", &["http://t/Addr"]),
+            bindings("s", &["http://x/member1", "http://x/member2"]),
+            bindings("t", &[]),
+            bindings("t", &["http://t/Org"]),
+            bindings("s", &[]),
+            bindings("s", &["http://x/addr"]),
+            bindings("t", &["http://t/Site"]),
+            bindings("t", &[]),
+        ]);
+        let client = SparqlClient::new(reqwest::Client::new());
+
+        let config = scaffold_config(&client, &server.endpoint, "<http://t/Org>", 5, 2)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            config.keys().collect::<Vec<_>>(),
+            [
+                "<http://t/Org>",
+                "<http://t/Member>",
+                "<http://t/Site>",
+                "<http://t/Addr>"
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&config).unwrap(),
+            serde_json::json!({
+                "<http://t/Org>": {
+                    "reverse": ["<http://t/Member>", "<http://t/Site>"],
+                    "forward": ["<http://t/Addr>"]
+                },
+                "<http://t/Member>": { "reverse": [], "forward": ["<http://t/Org>"] },
+                "<http://t/Site>": { "reverse": [], "forward": [] },
+                "<http://t/Addr>": { "reverse": ["<http://t/Site>"], "forward": [] }
+            })
+        );
+        let requests = server.requests();
+        assert!(requests[4].contains("%3Chttp%3A%2F%2Fx%2Fmember2%3E"));
+    }
+}
@@ -0,0 +1,404 @@
+use serde_json::Value;
+
+use crate::error::ScratchpadError;
+
+/// A SPARQL JSON result, with the deletion logic left to the callers.
+pub enum Solutions<'a> {
+    /// The rows of a SELECT, in the order the endpoint returned them.
+    Select(Vec<Binding<'a>>),
+    /// The answer of an ASK.
+    Ask(bool),
+}
+
+impl<'a> Solutions<'a> {
+    /// The rows of a SELECT, none for an ASK.
+    pub fn rows(&self) -> &[Binding<'a>] {
+        match self {
+            Solutions::Select(rows) => rows,
+            Solutions::Ask(_) => &[],
+        }
+    }
+
+    /// The answer of an ASK, `None` for a SELECT.
+    pub fn boolean(&self) -> Option<bool> {
+        match self {
+            Solutions::Select(_) => None,
+            Solutions::Ask(answer) => Some(*answer),
+        }
+    }
+
+    /// Short description for errors about an unexpected kind of result.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Solutions::Select(_) => "SELECT results",
+            Solutions::Ask(_) => "an ASK result",
+        }
+    }
+}
+
+/// Reads a SPARQL JSON result. Anything that is neither a SELECT result set nor an ASK
+/// answer, like the `null` of a failed request or an error object, is an error, so a failed
+/// query cannot be mistaken for one without results.
+pub fn parse_solution(value: &Value) -> Result<Solutions<'_>, ScratchpadError> {
+    if let Some(answer) = value.get("boolean").and_then(Value::as_bool) {
+        return Ok(Solutions::Ask(answer));
+    }
+    if let Some(rows) = value
+        .get("results")
+        .and_then(|results| results.get("bindings"))
+        .and_then(Value::as_array)
+    {
+        return Ok(Solutions::Select(rows.iter().map(Binding::new).collect()));
+    }
+
+    let got = if value.is_null() {
+        "no results".to_string()
+    } else {
+        value.to_string().chars().take(200).collect()
+    };
+    Err(ScratchpadError::Sparql(format!(
+        "Expected SPARQL results, got {}",
+        got
+    )))
+}
+
+/// The answer of an ASK result, an error for a SELECT result or anything else.
+pub fn parse_ask(value: &Value) -> Result<bool, ScratchpadError> {
+    let solutions = parse_solution(value)?;
+
+    solutions.boolean().ok_or_else(|| {
+        ScratchpadError::Sparql(format!("Expected an ASK result, got {}", solutions.kind()))
+    })
+}
+
+/// The number bound to `var` in the single row of an aggregate like `SELECT (COUNT(*) AS
+/// ?count)`. An ASK result, a result without rows or a value that is not a non-negative
+/// integer is an error.
+pub fn parse_count(value: &Value, var: &str) -> Result<u64, ScratchpadError> {
+    let solutions = parse_solution(value)?;
+    let Solutions::Select(rows) = &solutions else {
+        return Err(ScratchpadError::Sparql(format!(
+            "Expected a COUNT result, got {}",
+            solutions.kind()
+        )));
+    };
+    let Some(count) = rows.first().and_then(|row| row.value(var)) else {
+        return Err(ScratchpadError::Sparql(format!(
+            "Expected a COUNT result with ?{} bound, got {}",
+            var, value
+        )));
+    };
+
+    count.parse::<u64>().map_err(|_| {
+        ScratchpadError::Sparql(format!("Expected ?{} to be a count, got {}", var, count))
+    })
+}
+
+/// An RDF term bound to a variable, as found in SPARQL JSON results.
+#[derive(Debug, PartialEq)]
+pub enum RdfTerm<'a> {
+    Uri(&'a str),
+    Literal {
+        value: &'a str,
+        datatype: Option<&'a str>,
+        lang: Option<&'a str>,
+    },
+    BlankNode(&'a str),
+}
+
+const XSD_STRING: &str = "http://www.w3.org/2001/XMLSchema#string";
+
+impl RdfTerm<'_> {
+    /// The term in N-Triples/N-Quads syntax. Blank node labels are only kept to the
+    /// characters N-Quads allows.
+    pub fn to_nquads(&self) -> String {
+        match self {
+            RdfTerm::Uri(uri) => format!("<{}>", escape_iri(uri)),
+            RdfTerm::Literal {
+                value,
+                datatype,
+                lang,
+            } => {
+                let literal = format!("\"{}\"", escape_literal(value));
+                match (lang, datatype) {
+                    (Some(lang), _) => format!("{}@{}", literal, lang),
+                    (None, Some(datatype)) if *datatype != XSD_STRING => {
+                        format!("{}^^<{}>", literal, escape_iri(datatype))
+                    }
+                    (None, _) => literal,
+                }
+            }
+            RdfTerm::BlankNode(label) => format!(
+                "_:{}",
+                label
+                    .chars()
+                    .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+                    .collect::<String>()
+            ),
+        }
+    }
+}
+
+fn escape_iri(iri: &str) -> String {
+    iri.chars()
+        .map(|c| match c {
+            '<' | '>' | '"' | '{' | '}' | '|' | '^' | '`' | '\\' | '\0'..=' ' => {
+                format!("\\u{:04X}", c as u32)
+            }
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+fn escape_literal(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// One solution of a SELECT result set. Every accessor returns `None` for an unbound
+/// variable or a term of unexpected shape instead of panicking.
+#[derive(Clone, Copy)]
+pub struct Binding<'a>(&'a Value);
+
+impl<'a> Binding<'a> {
+    pub fn new(value: &'a Value) -> Self {
+        Binding(value)
+    }
+
+    /// The IRI bound to `var`, `None` if it is bound to a literal or blank node.
+    pub fn uri(&self, var: &str) -> Option<&'a str> {
+        match self.term(var)? {
+            RdfTerm::Uri(uri) => Some(uri),
+            _ => None,
+        }
+    }
+
+    /// The lexical value bound to `var`, whatever kind of term it is.
+    pub fn value(&self, var: &str) -> Option<&'a str> {
+        self.0.get(var)?.get("value")?.as_str()
+    }
+
+    pub fn term(&self, var: &str) -> Option<RdfTerm<'a>> {
+        let term = self.0.get(var)?;
+        let value = term.get("value")?.as_str()?;
+
+        // Virtuoso reports typed literals with their own "typed-literal" type.
+        match term.get("type")?.as_str()? {
+            "uri" => Some(RdfTerm::Uri(value)),
+            "literal" | "typed-literal" => Some(RdfTerm::Literal {
+                value,
+                datatype: term.get("datatype").and_then(Value::as_str),
+                lang: term.get("xml:lang").and_then(Value::as_str),
+            }),
+            "bnode" => Some(RdfTerm::BlankNode(value)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A row as Virtuoso returns it, with every kind of term it knows.
+    fn row() -> Value {
+        json!({
+            "site": { "type": "uri", "value": "http://data.lblod.info/id/site/3" },
+            "name": { "type": "literal", "value": "Gemeentehuis", "xml:lang": "nl" },
+            "since": {
+                "type": "typed-literal",
+                "value": "2019-01-01",
+                "datatype": "http://www.w3.org/2001/XMLSchema#date"
+            },
+            "address": { "type": "bnode", "value": "b0" },
+            "broken": { "type": "uri" },
+            "odd": { "type": "triple", "value": "<< >>" }
+        })
+    }
+
+    #[test]
+    fn ask_results_are_read_as_their_boolean() {
+        let result = serde_json::from_str(r#"{ "head": {}, "boolean": false }"#).unwrap();
+
+        let solutions = parse_solution(&result).unwrap();
+
+        assert_eq!(solutions.boolean(), Some(false));
+        assert!(solutions.rows().is_empty());
+        assert_eq!(solutions.kind(), "an ASK result");
+    }
+
+    #[test]
+    fn ask_answers_are_read_and_select_results_refused() {
+        let yes = json!({ "head": {}, "boolean": true });
+        let rows = json!({ "head": { "vars": ["s"] }, "results": { "bindings": [] } });
+
+        assert!(parse_ask(&yes).unwrap());
+        assert!(!parse_ask(&json!({ "boolean": false })).unwrap());
+        let Err(ScratchpadError::Sparql(msg)) = parse_ask(&rows) else {
+            panic!("SELECT results are no ASK answer");
+        };
+        assert_eq!(msg, "Expected an ASK result, got SELECT results");
+        assert!(parse_ask(&json!({ "boolean": "true" })).is_err());
+    }
+
+    #[test]
+    fn counts_are_read_from_the_single_aggregate_row() {
+        let count = |value: &str| {
+            json!({
+                "head": { "vars": ["triples"] },
+                "results": { "bindings": [{ "triples": {
+                    "type": "literal",
+                    "value": value,
+                    "datatype": "http://www.w3.org/2001/XMLSchema#integer"
+                } }] }
+            })
+        };
+        let error = |result: &Value, var: &str| match parse_count(result, var) {
+            Err(ScratchpadError::Sparql(msg)) => msg,
+            other => panic!("expected an error, got {:?}", other.ok()),
+        };
+
+        assert_eq!(parse_count(&count("18446"), "triples").unwrap(), 18446);
+        assert_eq!(
+            error(&json!({ "boolean": true }), "triples"),
+            "Expected a COUNT result, got an ASK result"
+        );
+        assert!(error(&count("18446"), "count")
+            .starts_with("Expected a COUNT result with ?count bound, got {"));
+        assert!(error(&json!({ "results": { "bindings": [] } }), "triples")
+            .starts_with("Expected a COUNT result with ?triples bound"));
+        assert_eq!(
+            error(&count("-3"), "triples"),
+            "Expected ?triples to be a count, got -3"
+        );
+        assert_eq!(
+            error(&count("2.5e3"), "triples"),
+            "Expected ?triples to be a count, got 2.5e3"
+        );
+    }
+
+    #[test]
+    fn select_rows_keep_their_order_and_term_types() {
+        let result = serde_json::from_str::<Value>(
+            r#"{
+                "head": { "vars": ["s", "o"] },
+                "results": { "bindings": [
+                    { "s": { "type": "uri", "value": "http://x/b" },
+                      "o": { "type": "literal", "value": "42",
+                             "datatype": "http://www.w3.org/2001/XMLSchema#int" } },
+                    { "s": { "type": "bnode", "value": "r1" } },
+                    { "s": { "type": "uri", "value": "http://x/a" },
+                      "o": { "type": "uri", "value": "http://x/b" } }
+                ] }
+            }"#,
+        )
+        .unwrap();
+
+        let solutions = parse_solution(&result).unwrap();
+
+        assert_eq!(solutions.boolean(), None);
+        assert_eq!(solutions.kind(), "SELECT results");
+        let rows = solutions.rows();
+        assert_eq!(
+            rows.iter().map(|row| row.value("s")).collect::<Vec<_>>(),
+            [Some("http://x/b"), Some("r1"), Some("http://x/a")]
+        );
+        assert_eq!(
+            rows[0].term("o"),
+            Some(RdfTerm::Literal {
+                value: "42",
+                datatype: Some("http://www.w3.org/2001/XMLSchema#int"),
+                lang: None,
+            })
+        );
+        assert_eq!(rows[1].term("o"), None);
+        assert_eq!(rows[2].uri("o"), Some("http://x/b"));
+    }
+
+    #[test]
+    fn empty_results_differ_from_no_results() {
+        let empty = json!({ "head": { "vars": ["s"] }, "results": { "bindings": [] } });
+        assert!(parse_solution(&empty).unwrap().rows().is_empty());
+
+        let Err(ScratchpadError::Sparql(msg)) = parse_solution(&Value::Null) else {
+            panic!("the null of a failed request is not an empty result");
+        };
+        assert_eq!(msg, "Expected SPARQL results, got no results");
+
+        let error = json!({ "error": "x".repeat(500) });
+        let Err(ScratchpadError::Sparql(msg)) = parse_solution(&error) else {
+            panic!("an error object is not an empty result");
+        };
+        assert!(msg.starts_with("Expected SPARQL results, got {\"error\":\"xxx"));
+        assert_eq!(msg.len(), "Expected SPARQL results, got ".len() + 200);
+    }
+
+    #[test]
+    fn uri_is_only_read_from_iris() {
+        let row = row();
+        let binding = Binding::new(&row);
+
+        assert_eq!(
+            binding.uri("site"),
+            Some("http://data.lblod.info/id/site/3")
+        );
+        assert_eq!(binding.uri("name"), None);
+        assert_eq!(binding.uri("address"), None);
+        assert_eq!(binding.uri("missing"), None);
+    }
+
+    #[test]
+    fn value_is_read_from_any_term() {
+        let row = row();
+        let binding = Binding::new(&row);
+
+        assert_eq!(binding.value("name"), Some("Gemeentehuis"));
+        assert_eq!(binding.value("address"), Some("b0"));
+        assert_eq!(binding.value("odd"), Some("<< >>"));
+        assert_eq!(binding.value("broken"), None);
+    }
+
+    #[test]
+    fn term_keeps_the_kind_language_and_datatype() {
+        let row = row();
+        let binding = Binding::new(&row);
+
+        assert_eq!(
+            binding.term("site"),
+            Some(RdfTerm::Uri("http://data.lblod.info/id/site/3"))
+        );
+        assert_eq!(
+            binding.term("name"),
+            Some(RdfTerm::Literal {
+                value: "Gemeentehuis",
+                datatype: None,
+                lang: Some("nl"),
+            })
+        );
+        assert_eq!(
+            binding.term("since"),
+            Some(RdfTerm::Literal {
+                value: "2019-01-01",
+                datatype: Some("http://www.w3.org/2001/XMLSchema#date"),
+                lang: None,
+            })
+        );
+        assert_eq!(binding.term("address"), Some(RdfTerm::BlankNode("b0")));
+    }
+
+    #[test]
+    fn unexpected_shapes_are_none_instead_of_a_panic() {
+        let row = row();
+        let binding = Binding::new(&row);
+        let not_an_object = json!(["site"]);
+
+        assert_eq!(binding.term("broken"), None);
+        assert_eq!(binding.term("odd"), None);
+        assert_eq!(binding.term("missing"), None);
+        assert_eq!(Binding::new(&not_an_object).uri("site"), None);
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ScratchpadError;
+use crate::uri::canonicalize_uri;
+
+/// Snapshot of a (possibly interrupted) traversal so that a long deletion run
+/// can be resumed instead of starting over.
+#[derive(Serialize, Deserialize)]
+pub struct TraversalState {
+    pub seed_uris: Vec<String>,
+    pub seed_type: String,
+    /// URIs discovered so far, grouped by their type.
+    pub visited: HashMap<String, Vec<String>>,
+    /// URIs that are deleted but whose edges are not expanded, grouped by their type.
+    #[serde(default)]
+    pub leaves: HashMap<String, Vec<String>>,
+    /// Config types that still need their forward/reverse edges expanded, in processing order.
+    pub pending: Vec<String>,
+}
+
+impl TraversalState {
+    pub fn new(seed_uris: &[String], seed_type: &str, pending: Vec<String>) -> Self {
+        let mut visited = HashMap::new();
+        visited.insert(seed_type.to_string(), seed_uris.to_vec());
+
+        TraversalState {
+            seed_uris: seed_uris.to_vec(),
+            seed_type: seed_type.to_string(),
+            visited,
+            leaves: HashMap::new(),
+            pending,
+        }
+    }
+
+    /// Loads a previously saved state, returning `None` if there is nothing to resume from.
+    pub fn load(path: &Path) -> Result<Option<Self>, ScratchpadError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let reader = BufReader::new(File::open(path)?);
+        let state = serde_json::from_reader(reader)?;
+
+        Ok(Some(state))
+    }
+
+    /// Writes the state to a temporary file first and renames it over `path`, so an
+    /// interruption mid-write never leaves a truncated state file behind.
+    pub fn save(&self, path: &Path) -> Result<(), ScratchpadError> {
+        let tmp_path = path.with_extension("tmp");
+
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        drop(writer);
+
+        fs::rename(&tmp_path, path)?;
+
+        Ok(())
+    }
+
+    /// Number of URIs held in the per-type lists.
+    pub fn stored_uris(&self) -> usize {
+        self.visited
+            .values()
+            .chain(self.leaves.values())
+            .map(Vec::len)
+            .sum()
+    }
+
+    /// Drops the lists of every type that is no longer pending, and of all leaves. Only the
+    /// list of a type that is about to be processed is read again, so this loses nothing
+    /// the rest of the traversal needs, but the dropped URIs cannot be restored by resuming.
+    /// Returns the number of URIs dropped.
+    pub fn flush_processed(&mut self) -> usize {
+        let before = self.stored_uris();
+        self.visited
+            .retain(|type_iri, _| self.pending.contains(type_iri));
+        self.leaves.clear();
+
+        before - self.stored_uris()
+    }
+
+    /// Refuses to resume a state that was recorded for a different seed.
+    pub fn check_seed(&self, seed_uris: &[String], seed_type: &str) -> Result<(), ScratchpadError> {
+        if self.seed_uris != seed_uris || self.seed_type != seed_type {
+            return Err(ScratchpadError::State(format!(
+                "State file was recorded for seed {} ({}), refusing to resume with seed {} ({})",
+                self.seed_uris.join(", "),
+                self.seed_type,
+                seed_uris.join(", "),
+                seed_type
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Every URI that already ended up in a statement. Edges that are looked up concurrently
+/// share one set, so a URI they both discover is only deleted by one of them.
+///
+/// RDF compares IRIs character by character, so every spelling the store returns for a
+/// resource (e.g. `%7E` and `~`) is a node of its own and is handed out to be deleted. The
+/// spellings are only merged by their canonical form to expand the resource once, under
+/// the first spelling seen, see [`VisitedSet::first_spellings`].
+#[derive(Default)]
+pub struct VisitedSet {
+    uris: Mutex<Visited>,
+}
+
+#[derive(Default)]
+struct Visited {
+    spellings: HashSet<String>,
+    /// The first spelling seen of every canonical form.
+    first: HashMap<String, String>,
+}
+
+impl VisitedSet {
+    /// Marks `uris` as visited and returns the spellings that were not yet, in their
+    /// original order. The whole batch is claimed under one lock, so two callers never
+    /// both get the same URI.
+    pub fn claim<'a>(&self, uris: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+        let mut visited = self.uris.lock().unwrap();
+
+        uris.into_iter()
+            .filter(|uri| {
+                visited
+                    .first
+                    .entry(canonical_key(uri))
+                    .or_insert_with(|| uri.to_string());
+                visited.spellings.insert(uri.to_string())
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// The URIs to expand for `uris`: one per canonical form, in the first spelling
+    /// claimed for it, sorted.
+    pub fn first_spellings<'a>(&self, uris: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+        let visited = self.uris.lock().unwrap();
+
+        let mut first = uris
+            .into_iter()
+            .map(|uri| match visited.first.get(&canonical_key(uri)) {
+                Some(first) => first.clone(),
+                None => uri.clone(),
+            })
+            .collect::<Vec<_>>();
+        first.sort_unstable();
+        first.dedup();
+        first
+    }
+}
+
+fn canonical_key(uri: &str) -> String {
+    canonicalize_uri(uri.trim_start_matches('<').trim_end_matches('>'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uris(uris: &[&str]) -> Vec<String> {
+        uris.iter().map(|uri| uri.to_string()).collect()
+    }
+
+    #[test]
+    fn saved_state_loads_back_unchanged() {
+        let path = std::env::temp_dir().join(format!("state-{}.json", std::process::id()));
+        let mut state = TraversalState::new(
+            &uris(&["<http://x/org>"]),
+            "<http://t/Org>",
+            uris(&["<http://t/Org>", "<http://t/Site>"]),
+        );
+        state
+            .visited
+            .insert("<http://t/Site>".to_string(), uris(&["<http://x/site>"]));
+        state
+            .leaves
+            .insert("<http://t/Tag>".to_string(), uris(&["<http://x/tag>"]));
+
+        state.save(&path).unwrap();
+        let loaded = TraversalState::load(&path).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.seed_uris, ["<http://x/org>"]);
+        assert_eq!(loaded.pending, ["<http://t/Org>", "<http://t/Site>"]);
+        assert_eq!(loaded.visited, state.visited);
+        assert_eq!(loaded.leaves, state.leaves);
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[test]
+    fn flushing_keeps_only_the_lists_of_pending_types() {
+        let mut state = TraversalState::new(
+            &uris(&["<http://x/org>"]),
+            "<http://t/Org>",
+            uris(&["<http://t/Site>"]),
+        );
+        state.visited.insert(
+            "<http://t/Site>".to_string(),
+            uris(&["<http://x/site1>", "<http://x/site2>"]),
+        );
+        state
+            .leaves
+            .insert("<http://t/Tag>".to_string(), uris(&["<http://x/tag>"]));
+
+        assert_eq!(state.stored_uris(), 4);
+        assert_eq!(state.flush_processed(), 2);
+        assert_eq!(state.stored_uris(), 2);
+        assert!(state.visited.contains_key("<http://t/Site>"));
+    }
+
+    /// A chain of types each linking to a batch of the next one, as large organizations
+    /// with deep hierarchies have. Returns the most URIs the lists held at once.
+    fn peak_stored_uris(types: usize, per_type: usize, max: Option<usize>) -> usize {
+        let type_iri = |i: usize| format!("<http://t/Level{}>", i);
+        let mut state = TraversalState::new(
+            &uris(&["<http://x/org>"]),
+            &type_iri(0),
+            (0..types).map(type_iri).collect(),
+        );
+        let mut peak = state.stored_uris();
+
+        for i in 0..types {
+            let discovered = (0..per_type)
+                .map(|n| format!("<http://x/level{}/{}>", i + 1, n))
+                .collect();
+            state.visited.insert(type_iri(i + 1), discovered);
+            state.pending.retain(|pending| *pending != type_iri(i));
+            peak = peak.max(state.stored_uris());
+            if max.is_some_and(|max| state.stored_uris() > max) {
+                state.flush_processed();
+            }
+        }
+
+        peak
+    }
+
+    #[test]
+    fn stored_uris_stay_bounded_with_a_cap() {
+        assert_eq!(peak_stored_uris(50, 2_000, None), 100_001);
+        // Over the cap by at most the URIs of the one type discovered last.
+        assert!(peak_stored_uris(50, 2_000, Some(5_000)) <= 5_000 + 2_000);
+    }
+
+    #[test]
+    fn nothing_to_resume_without_a_state_file() {
+        let path = std::env::temp_dir().join("no-such-state-file.json");
+
+        assert!(TraversalState::load(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn state_of_another_seed_is_refused() {
+        let state = TraversalState::new(&uris(&["<http://x/org>"]), "<http://t/Org>", vec![]);
+
+        assert!(state
+            .check_seed(&uris(&["<http://x/org>"]), "<http://t/Org>")
+            .is_ok());
+        assert!(state
+            .check_seed(&uris(&["<http://x/other>"]), "<http://t/Org>")
+            .is_err());
+        assert!(state
+            .check_seed(&uris(&["<http://x/org>"]), "<http://t/Site>")
+            .is_err());
+    }
+
+    #[test]
+    fn every_spelling_is_claimed_once_and_expanded_under_the_first() {
+        let visited = VisitedSet::default();
+        let spellings = uris(&[
+            "<HTTP://Example.org/a%7Eb>",
+            "<http://example.org/a~b>",
+            "<http://example.org/a%7eb>",
+        ]);
+
+        let claimed = visited.claim(&spellings);
+
+        assert_eq!(claimed, spellings);
+        assert!(visited
+            .claim(&uris(&["<http://example.org/a~b>"]))
+            .is_empty());
+        assert_eq!(
+            visited.claim(&uris(&["<http://EXAMPLE.org/a~b>"])),
+            ["<http://EXAMPLE.org/a~b>"]
+        );
+        assert_eq!(
+            visited.first_spellings(&spellings),
+            ["<HTTP://Example.org/a%7Eb>"]
+        );
+    }
+
+    #[test]
+    fn distinct_iris_are_not_merged() {
+        let visited = VisitedSet::default();
+
+        let claimed = visited.claim(&uris(&[
+            "<http://example.org/a>",
+            "<http://example.org/a/>",
+            "<http://example.org/a%2Fb>",
+            "<http://example.org/a/b>",
+            "<http://example.org/A>",
+        ]));
+
+        assert_eq!(claimed.len(), 5);
+        assert_eq!(visited.first_spellings(&claimed).len(), 5);
+    }
+
+    #[test]
+    fn concurrent_claims_of_overlapping_uris_hand_out_each_uri_once() {
+        let visited = VisitedSet::default();
+        // Each edge discovers a window of sites overlapping the windows of its neighbours,
+        // and spells every other site with an escaped tilde where its neighbours don't.
+        let edges = (0..8)
+            .map(|edge| {
+                (edge * 50..edge * 50 + 200)
+                    .map(|n| match (n + edge) % 2 {
+                        0 => format!("<http://x/~site{}>", n),
+                        _ => format!("<http://x/%7Esite{}>", n),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let claimed = std::thread::scope(|scope| {
+            let handles = edges
+                .iter()
+                .map(|discovered| {
+                    scope.spawn(|| {
+                        discovered
+                            .chunks(7)
+                            .flat_map(|batch| visited.claim(batch))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        let unique = claimed.iter().collect::<HashSet<_>>();
+        assert_eq!(unique.len(), claimed.len(), "a URI was claimed twice");
+        let spellings = edges.iter().flatten().collect::<HashSet<_>>();
+        assert_eq!(unique, spellings);
+        assert_eq!(visited.first_spellings(&claimed).len(), 7 * 50 + 200);
+    }
+}
@@ -0,0 +1,95 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// A local HTTP server for the tests of the request paths. It answers one connection per
+/// canned response, in order, and keeps the raw requests it got.
+pub struct TestServer {
+    pub endpoint: String,
+    handle: JoinHandle<Vec<String>>,
+}
+
+impl TestServer {
+    pub fn start(responses: Vec<Vec<u8>>) -> Self {
+        Self::slow(responses, Duration::ZERO)
+    }
+
+    /// Takes `delay` to answer every request. A client that gave up waiting for its
+    /// response does not fail the server.
+    pub fn slow(responses: Vec<Vec<u8>>, delay: Duration) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/sparql", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests = vec![];
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                requests.push(read_request(&mut stream));
+                thread::sleep(delay);
+                if let Err(e) = stream.write_all(&response) {
+                    assert!(!delay.is_zero(), "{}", e);
+                }
+            }
+            requests
+        });
+
+        TestServer { endpoint, handle }
+    }
+
+    /// Waits until every response was sent and returns the requests, headers and body.
+    pub fn requests(self) -> Vec<String> {
+        self.handle.join().unwrap()
+    }
+}
+
+/// A complete response that closes the connection, so every request gets the next one.
+pub fn response(status: &str, headers: &[(&str, &str)], body: &[u8]) -> Vec<u8> {
+    let mut out = format!(
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        status,
+        body.len()
+    );
+    for (name, value) in headers {
+        out.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    out.push_str("\r\n");
+
+    let mut out = out.into_bytes();
+    out.extend_from_slice(body);
+    out
+}
+
+/// A 200 response with a SPARQL JSON results body.
+pub fn json_response(body: &str) -> Vec<u8> {
+    response(
+        "200 OK",
+        &[("Content-Type", "application/sparql-results+json")],
+        body.as_bytes(),
+    )
+}
+
+fn read_request(stream: &mut impl Read) -> String {
+    let mut request = vec![];
+    let mut buf = [0; 4096];
+    loop {
+        let n = stream.read(&mut buf).unwrap();
+        request.extend_from_slice(&buf[..n]);
+        let text = String::from_utf8_lossy(&request);
+        if let Some(end) = text.find("\r\n\r\n") {
+            let length = text[..end]
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().ok())?
+                })
+                .unwrap_or(0);
+            if request.len() >= end + 4 + length || n == 0 {
+                return String::from_utf8_lossy(&request).into_owned();
+            }
+        }
+        if n == 0 {
+            return String::from_utf8_lossy(&request).into_owned();
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use reqwest::Url;
+
+use crate::error::ScratchpadError;
+
+/// Resolves a relative IRI, as some endpoints return them in bindings, against `base`.
+/// Absolute IRIs are returned unchanged.
+pub fn resolve_iri(iri: &str, base: Option<&str>) -> Result<String, ScratchpadError> {
+    if iri
+        .split_once(':')
+        .is_some_and(|(scheme, _)| is_scheme(scheme))
+    {
+        return Ok(iri.to_string());
+    }
+
+    let Some(base) = base else {
+        return Err(ScratchpadError::Sparql(format!(
+            "The endpoint returned the relative IRI {}, pass --base to resolve it",
+            iri
+        )));
+    };
+    let resolved = Url::parse(base)
+        .and_then(|base| base.join(iri))
+        .map_err(|e| {
+            ScratchpadError::Config(format!("Cannot resolve {} against {}: {}", iri, base, e))
+        })?;
+
+    Ok(resolved.to_string())
+}
+
+/// Brings an IRI into a canonical form so that spelling variants of the same IRI
+/// deduplicate to a single entry.
+///
+/// Only normalizations that RFC 3986 guarantees to be equivalence-preserving are applied:
+/// - percent-encoded unreserved characters (`A-Z a-z 0-9 - . _ ~`) are decoded,
+/// - the hex digits of the remaining percent-encodings are uppercased,
+/// - the scheme and host are lowercased,
+/// - an empty `http(s)` path becomes `/`.
+///
+/// Anything else, e.g. a trailing slash on a non-empty path or an encoded reserved
+/// character like `%2F`, is kept as-is because it may denote a different resource.
+pub fn canonicalize_uri(uri: &str) -> String {
+    let uri = normalize_percent_encoding(uri);
+
+    let Some((scheme, rest)) = uri.split_once(':') else {
+        return uri;
+    };
+    if !is_scheme(scheme) {
+        return uri;
+    }
+    let scheme = scheme.to_ascii_lowercase();
+
+    let Some(hier) = rest.strip_prefix("//") else {
+        return format!("{}:{}", scheme, rest);
+    };
+
+    let authority_end = hier.find(['/', '?', '#']).unwrap_or(hier.len());
+    let (authority, tail) = hier.split_at(authority_end);
+
+    // Keep the userinfo untouched, only the host is case-insensitive.
+    let authority = match authority.rsplit_once('@') {
+        Some((userinfo, host)) => format!("{}@{}", userinfo, host.to_ascii_lowercase()),
+        None => authority.to_ascii_lowercase(),
+    };
+
+    let tail = if (scheme == "http" || scheme == "https") && !tail.starts_with('/') {
+        format!("/{}", tail)
+    } else {
+        tail.to_string()
+    };
+
+    format!("{}://{}{}", scheme, authority, tail)
+}
+
+fn is_scheme(scheme: &str) -> bool {
+    let mut chars = scheme.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+fn normalize_percent_encoding(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut out = String::with_capacity(uri.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && bytes[i + 1].is_ascii_hexdigit()
+            && bytes[i + 2].is_ascii_hexdigit()
+        {
+            let byte = u8::from_str_radix(&uri[i + 1..i + 3], 16).unwrap();
+            if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+                out.push(byte as char);
+            } else {
+                out.push_str(&format!("%{:02X}", byte));
+            }
+            i += 3;
+            continue;
+        }
+
+        // Copy the (possibly multi-byte) character as-is.
+        let c = uri[i..].chars().next().unwrap();
+        out.push(c);
+        i += c.len_utf8();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoded_unreserved_characters_are_decoded() {
+        assert_eq!(canonicalize_uri("http://x/a%7Eb"), "http://x/a~b");
+        assert_eq!(canonicalize_uri("http://x/%41%2d%5F"), "http://x/A-_");
+    }
+
+    #[test]
+    fn other_encodings_keep_their_meaning_with_uppercase_hex() {
+        assert_eq!(canonicalize_uri("http://x/a%2fb"), "http://x/a%2Fb");
+        assert_eq!(canonicalize_uri("http://x/caf%c3%a9"), "http://x/caf%C3%A9");
+        assert_eq!(canonicalize_uri("http://x/100%"), "http://x/100%");
+    }
+
+    #[test]
+    fn scheme_and_host_are_case_insensitive_but_the_path_is_not() {
+        assert_eq!(
+            canonicalize_uri("HTTP://Example.ORG/Path"),
+            "http://example.org/Path"
+        );
+        assert_eq!(canonicalize_uri("https://Me@Host"), "https://Me@host/");
+        assert_eq!(canonicalize_uri("URN:Isbn:123"), "urn:Isbn:123");
+        assert_eq!(canonicalize_uri("http://x/a/"), "http://x/a/");
+    }
+
+    #[test]
+    fn relative_iris_resolve_against_the_base() {
+        let base = Some("http://data.example/id/orgs/");
+
+        assert_eq!(
+            resolve_iri("42", base).unwrap(),
+            "http://data.example/id/orgs/42"
+        );
+        assert_eq!(
+            resolve_iri("../sites/7", base).unwrap(),
+            "http://data.example/id/sites/7"
+        );
+        assert_eq!(
+            resolve_iri("/vocab#Org", base).unwrap(),
+            "http://data.example/vocab#Org"
+        );
+        assert_eq!(resolve_iri("urn:uuid:3f2a", base).unwrap(), "urn:uuid:3f2a");
+    }
+
+    #[test]
+    fn relative_iris_need_a_usable_base() {
+        let Err(ScratchpadError::Sparql(msg)) = resolve_iri("orgs/42", None) else {
+            panic!("a relative IRI cannot be deleted as is");
+        };
+        assert_eq!(
+            msg,
+            "The endpoint returned the relative IRI orgs/42, pass --base to resolve it"
+        );
+        assert!(resolve_iri("http://data.example/orgs/42", None).is_ok());
+        assert!(matches!(
+            resolve_iri("orgs/42", Some("not a base")),
+            Err(ScratchpadError::Config(_))
+        ));
+    }
+}
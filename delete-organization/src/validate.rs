@@ -0,0 +1,107 @@
+use spargebra::SparqlParser;
+
+use crate::error::ScratchpadError;
+
+/// Checks that a generated SELECT parses, so query builder bugs surface before the
+/// query is sent.
+pub fn validate_query(query: &str) -> Result<(), ScratchpadError> {
+    SparqlParser::new().parse_query(query).map_err(|e| {
+        ScratchpadError::Sparql(format!("Generated query does not parse: {}\n{}", e, query))
+    })?;
+
+    Ok(())
+}
+
+/// Checks that a generated DELETE parses, so a broken statement never ends up in the output.
+pub fn validate_update(update: &str) -> Result<(), ScratchpadError> {
+    SparqlParser::new().parse_update(update).map_err(|e| {
+        ScratchpadError::Sparql(format!(
+            "Generated update does not parse: {}\n{}",
+            e, update
+        ))
+    })?;
+
+    Ok(())
+}
+
+/// Checks that the `path` of a config edge is a single SPARQL property path, e.g. `a/b*`,
+/// which cannot close the group of the lookup query it is inserted into.
+pub fn validate_path(path: &str) -> Result<(), ScratchpadError> {
+    let mut depth = 0;
+    let mut in_iri = false;
+    for c in path.chars() {
+        match c {
+            '<' if !in_iri => in_iri = true,
+            '>' if in_iri => in_iri = false,
+            '(' if !in_iri => depth += 1,
+            ')' if !in_iri => {
+                depth -= 1;
+                if depth < 0 {
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let query = format!("SELECT * WHERE {{ ?s ({}) ?o }}", path);
+    let parsed = match depth {
+        0 => SparqlParser::new()
+            .parse_query(&query)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        _ => Err("unbalanced parentheses".to_string()),
+    };
+
+    parsed.map_err(|e| ScratchpadError::Config(format!("Invalid property path `{}`: {}", path, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broken_update_is_reported_with_its_text() {
+        let update = "DELETE { GRAPH ?g { ?s ?p ?o } WHERE { VALUES ?s { <http://x/a> } }";
+
+        let Err(ScratchpadError::Sparql(msg)) = validate_update(update) else {
+            panic!("an unclosed DELETE template must not parse");
+        };
+        assert!(msg.starts_with("Generated update does not parse"));
+        assert!(msg.ends_with(update));
+    }
+
+    #[test]
+    fn well_formed_statements_pass() {
+        validate_update(concat!(
+            "DELETE { GRAPH ?g { ?s ?p ?o } } ",
+            "WHERE { VALUES ?s { <http://x/a> } GRAPH ?g { ?s ?p ?o } }"
+        ))
+        .unwrap();
+        validate_query("SELECT DISTINCT ?s WHERE { VALUES ?o { <http://x/a> } ?s ?p ?o }").unwrap();
+    }
+
+    #[test]
+    fn select_with_a_dangling_variable_is_rejected() {
+        assert!(validate_query("SELECT ?s WHERE { VALUES ?o { } ?s ?p }").is_err());
+    }
+
+    #[test]
+    fn property_paths_must_stay_inside_the_lookup() {
+        validate_path("<http://x/a>/<http://x/b>*").unwrap();
+        validate_path("^<http://x/memberOf>|(<http://x/a>/<http://x/b>)+").unwrap();
+        validate_path("<http://x/a(b)>").unwrap();
+
+        for path in [
+            "<http://x/a>) . ?x ?y (?z",
+            "(<http://x/a>",
+            "<http://x/a> ?o } DELETE WHERE { ?s ?p",
+            "<http://x/a>//<http://x/b>",
+        ] {
+            let Err(ScratchpadError::Config(msg)) = validate_path(path) else {
+                panic!("`{}` must be rejected", path);
+            };
+            assert!(msg.starts_with(&format!("Invalid property path `{}`: ", path)));
+        }
+    }
+}